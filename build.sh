@@ -1,3 +1,3 @@
 cargo build --release
 cargo install diesel_cli --no-default-features --features postgres
-diesel database setup
\ No newline at end of file
+diesel database setupcargo test -- --include-ignored
@@ -0,0 +1,8 @@
+DELETE FROM role_permissions
+WHERE permission_id IN (
+  SELECT id FROM permissions WHERE name IN ('stores.read', 'stores.write', 'stores.delete')
+);
+
+DELETE FROM permissions WHERE name IN ('stores.read', 'stores.write', 'stores.delete');
+
+ALTER TABLE permissions DROP CONSTRAINT permissions_name_key;
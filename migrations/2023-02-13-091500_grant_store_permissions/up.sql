@@ -0,0 +1,15 @@
+ALTER TABLE permissions ADD CONSTRAINT permissions_name_key UNIQUE (name);
+
+INSERT INTO permissions (id, name)
+SELECT gen_random_uuid()::text, p.name
+FROM (VALUES ('stores.read'), ('stores.write'), ('stores.delete')) AS p (name)
+ON CONFLICT (name) DO NOTHING;
+
+-- Existing accounts were all created with the "admin" role and had
+-- unrestricted access to the store routes, so keep it that way.
+INSERT INTO role_permissions (role_id, permission_id)
+SELECT roles.id, permissions.id
+FROM roles, permissions
+WHERE roles.name = 'admin'
+  AND permissions.name IN ('stores.read', 'stores.write', 'stores.delete')
+ON CONFLICT DO NOTHING;
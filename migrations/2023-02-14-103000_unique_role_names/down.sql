@@ -0,0 +1 @@
+ALTER TABLE roles DROP CONSTRAINT roles_name_key;
@@ -0,0 +1,6 @@
+DELETE FROM role_permissions
+WHERE permission_id IN (
+  SELECT id FROM permissions WHERE name IN ('products.read', 'products.write', 'products.delete')
+);
+
+DELETE FROM permissions WHERE name IN ('products.read', 'products.write', 'products.delete');
@@ -0,0 +1,13 @@
+INSERT INTO permissions (id, name)
+SELECT gen_random_uuid()::text, p.name
+FROM (VALUES ('products.read'), ('products.write'), ('products.delete')) AS p (name)
+ON CONFLICT (name) DO NOTHING;
+
+INSERT INTO role_permissions (role_id, permission_id)
+SELECT roles.id, permissions.id
+FROM roles, permissions
+WHERE (roles.name IN ('admin', 'merchant')
+       AND permissions.name IN ('products.read', 'products.write', 'products.delete'))
+   OR (roles.name = 'staff'
+       AND permissions.name IN ('products.read', 'products.write'))
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,12 @@
+CREATE TABLE inventory (
+  user_id VARCHAR REFERENCES users (id) NOT NULL,
+  product_id VARCHAR REFERENCES products (id) NOT NULL,
+  PRIMARY KEY (user_id, product_id)
+);
+
+INSERT INTO inventory (user_id, product_id)
+SELECT user_stores.user_id, products.id
+FROM products
+JOIN user_stores ON user_stores.store_id = products.store_id;
+
+ALTER TABLE products DROP COLUMN store_id;
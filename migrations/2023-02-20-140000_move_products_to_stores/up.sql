@@ -0,0 +1,66 @@
+ALTER TABLE products ADD COLUMN store_id VARCHAR REFERENCES stores (id);
+
+-- Where each product can go: the stores its owners are members of.
+CREATE TEMPORARY TABLE product_owners ON COMMIT DROP AS
+SELECT products.id AS product_id,
+  COUNT(DISTINCT inventory.user_id) AS owners,
+  COUNT(DISTINCT user_stores.store_id) AS stores,
+  MIN(inventory.user_id) AS owner_id,
+  MIN(user_stores.store_id) AS store_id
+FROM products
+LEFT JOIN inventory ON inventory.product_id = products.id
+LEFT JOIN user_stores ON user_stores.user_id = inventory.user_id
+GROUP BY products.id;
+
+-- Products are never dropped or guessed into a store; anything that cannot
+-- be placed stops the migration so it can be sorted out by hand first.
+DO $$
+DECLARE
+  orphaned BIGINT;
+  ambiguous BIGINT;
+BEGIN
+  SELECT COUNT(*) INTO orphaned FROM product_owners WHERE owners = 0;
+  IF orphaned > 0 THEN
+    RAISE EXCEPTION '% product(s) have no inventory row, so there is no owner to move them to', orphaned
+      USING HINT = 'Link them to a user in inventory or delete them, then run the migration again.';
+  END IF;
+
+  SELECT COUNT(*) INTO ambiguous
+  FROM product_owners
+  WHERE stores > 1 OR (stores = 0 AND owners > 1);
+  IF ambiguous > 0 THEN
+    RAISE EXCEPTION '% product(s) could belong to more than one store', ambiguous
+      USING HINT = 'Leave each product with owners who share exactly one store, or a single owner without stores, then run the migration again.';
+  END IF;
+END $$;
+
+UPDATE products
+SET store_id = product_owners.store_id
+FROM product_owners
+WHERE product_owners.product_id = products.id
+  AND product_owners.stores = 1;
+
+-- Owners without any store get a draft one so their catalog is kept.
+CREATE TEMPORARY TABLE storeless_owners ON COMMIT DROP AS
+SELECT owners.owner_id AS user_id, gen_random_uuid()::text AS store_id
+FROM (
+  SELECT DISTINCT owner_id FROM product_owners WHERE stores = 0
+) AS owners;
+
+INSERT INTO stores (id, name, stage)
+SELECT store_id, 'My store', 'draft' FROM storeless_owners;
+
+INSERT INTO user_stores (user_id, store_id)
+SELECT user_id, store_id FROM storeless_owners;
+
+UPDATE products
+SET store_id = storeless_owners.store_id
+FROM product_owners
+JOIN storeless_owners ON storeless_owners.user_id = product_owners.owner_id
+WHERE product_owners.product_id = products.id
+  AND product_owners.stores = 0;
+
+ALTER TABLE products ALTER COLUMN store_id SET NOT NULL;
+CREATE INDEX products_store_id_idx ON products (store_id);
+
+DROP TABLE inventory;
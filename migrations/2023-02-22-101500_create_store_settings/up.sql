@@ -0,0 +1,8 @@
+CREATE TABLE store_settings (
+  store_id VARCHAR PRIMARY KEY REFERENCES stores (id),
+  contact_email VARCHAR,
+  timezone VARCHAR NOT NULL DEFAULT 'UTC'
+);
+
+INSERT INTO store_settings (store_id)
+SELECT id FROM stores;
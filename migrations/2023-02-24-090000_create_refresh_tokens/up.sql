@@ -0,0 +1,11 @@
+-- Every refresh token issued for one sign-in points at the same session row,
+-- which is the rotation family: deleting the session revokes the whole chain.
+CREATE TABLE refresh_tokens (
+  id VARCHAR PRIMARY KEY,
+  session_id VARCHAR NOT NULL REFERENCES session (id) ON DELETE CASCADE,
+  token_hash VARCHAR NOT NULL UNIQUE,
+  used_at TIMESTAMP,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX refresh_tokens_session_id_idx ON refresh_tokens (session_id);
@@ -0,0 +1,6 @@
+DROP INDEX session_user_id_idx;
+
+ALTER TABLE session
+  DROP COLUMN ip,
+  DROP COLUMN user_agent,
+  DROP COLUMN created_at;
@@ -0,0 +1,6 @@
+ALTER TABLE session
+  ADD COLUMN created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  ADD COLUMN user_agent VARCHAR,
+  ADD COLUMN ip VARCHAR;
+
+CREATE INDEX session_user_id_idx ON session (user_id);
@@ -0,0 +1,21 @@
+-- A cart belongs either to a signed-in user or, for guests, to whoever holds
+-- the opaque cart token whose SHA-256 is stored in token_hash.
+CREATE TABLE carts (
+  id VARCHAR PRIMARY KEY,
+  store_id VARCHAR NOT NULL REFERENCES stores (id) ON DELETE CASCADE,
+  user_id VARCHAR REFERENCES users (id) ON DELETE CASCADE,
+  token_hash VARCHAR UNIQUE,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  CHECK ((user_id IS NULL) <> (token_hash IS NULL))
+);
+
+CREATE UNIQUE INDEX carts_store_id_user_id_idx ON carts (store_id, user_id)
+  WHERE user_id IS NOT NULL;
+
+CREATE TABLE cart_items (
+  cart_id VARCHAR NOT NULL REFERENCES carts (id) ON DELETE CASCADE,
+  product_id VARCHAR NOT NULL REFERENCES products (id) ON DELETE CASCADE,
+  quantity INTEGER NOT NULL CHECK (quantity > 0),
+  PRIMARY KEY (cart_id, product_id)
+);
@@ -0,0 +1,25 @@
+CREATE TABLE orders (
+  id VARCHAR PRIMARY KEY,
+  store_id VARCHAR NOT NULL REFERENCES stores (id),
+  user_id VARCHAR NOT NULL REFERENCES users (id),
+  status VARCHAR NOT NULL DEFAULT 'pending'
+    CHECK (status IN ('pending', 'paid', 'fulfilled', 'completed', 'cancelled', 'refunded')),
+  total FLOAT8 NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX orders_store_id_created_at_idx ON orders (store_id, created_at);
+
+-- Title and price are copied from products at checkout so later edits to the
+-- catalogue never change what was sold.
+CREATE TABLE order_items (
+  id VARCHAR PRIMARY KEY,
+  order_id VARCHAR NOT NULL REFERENCES orders (id) ON DELETE CASCADE,
+  product_id VARCHAR REFERENCES products (id) ON DELETE SET NULL,
+  title VARCHAR NOT NULL,
+  unit_price FLOAT8 NOT NULL,
+  quantity INTEGER NOT NULL CHECK (quantity > 0)
+);
+
+CREATE INDEX order_items_order_id_idx ON order_items (order_id);
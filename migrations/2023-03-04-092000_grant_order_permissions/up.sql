@@ -0,0 +1,11 @@
+INSERT INTO permissions (id, name)
+SELECT gen_random_uuid()::text, p.name
+FROM (VALUES ('orders.read'), ('orders.write')) AS p (name)
+ON CONFLICT (name) DO NOTHING;
+
+INSERT INTO role_permissions (role_id, permission_id)
+SELECT roles.id, permissions.id
+FROM roles, permissions
+WHERE roles.name IN ('admin', 'merchant', 'staff')
+  AND permissions.name IN ('orders.read', 'orders.write')
+ON CONFLICT DO NOTHING;
@@ -0,0 +1,34 @@
+-- Amounts are in minor units of the ISO 4217 currency (cents for USD).
+CREATE TABLE payments (
+  id VARCHAR PRIMARY KEY,
+  store_id VARCHAR NOT NULL REFERENCES stores (id),
+  order_id VARCHAR REFERENCES orders (id),
+  user_id VARCHAR NOT NULL REFERENCES users (id),
+  provider VARCHAR NOT NULL,
+  provider_reference VARCHAR,
+  amount BIGINT NOT NULL CHECK (amount > 0),
+  currency VARCHAR(3) NOT NULL,
+  refunded_amount BIGINT NOT NULL DEFAULT 0 CHECK (refunded_amount >= 0 AND refunded_amount <= amount),
+  status VARCHAR NOT NULL DEFAULT 'created'
+    CHECK (status IN ('created', 'requires_action', 'authorized', 'captured', 'partially_refunded',
+                      'refunded', 'voided', 'declined', 'failed')),
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  UNIQUE (provider, provider_reference)
+);
+
+CREATE INDEX payments_store_id_created_at_idx ON payments (store_id, created_at);
+
+-- One row per operation against the provider, including failed ones, with
+-- the status the payment was left in afterwards.
+CREATE TABLE payment_events (
+  id VARCHAR PRIMARY KEY,
+  payment_id VARCHAR NOT NULL REFERENCES payments (id),
+  action VARCHAR NOT NULL,
+  status VARCHAR NOT NULL,
+  amount BIGINT,
+  message VARCHAR,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX payment_events_payment_id_idx ON payment_events (payment_id);
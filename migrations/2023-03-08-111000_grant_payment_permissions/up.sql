@@ -0,0 +1,13 @@
+INSERT INTO permissions (id, name)
+SELECT gen_random_uuid()::text, p.name
+FROM (VALUES ('payments.read'), ('payments.write')) AS p (name)
+ON CONFLICT (name) DO NOTHING;
+
+INSERT INTO role_permissions (role_id, permission_id)
+SELECT roles.id, permissions.id
+FROM roles, permissions
+WHERE (roles.name IN ('admin', 'merchant')
+       AND permissions.name IN ('payments.read', 'payments.write'))
+   OR (roles.name = 'staff'
+       AND permissions.name = 'payments.read')
+ON CONFLICT DO NOTHING;
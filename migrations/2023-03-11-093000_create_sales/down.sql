@@ -0,0 +1,5 @@
+DROP TABLE sale_refund_items;
+DROP TABLE sale_refunds;
+DROP FUNCTION reject_refund_changes;
+DROP TABLE sale_items;
+DROP TABLE sales;
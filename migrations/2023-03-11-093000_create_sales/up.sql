@@ -0,0 +1,60 @@
+CREATE TABLE sales (
+  id VARCHAR PRIMARY KEY,
+  store_id VARCHAR NOT NULL REFERENCES stores (id),
+  user_id VARCHAR NOT NULL REFERENCES users (id),
+  note VARCHAR,
+  total FLOAT8 NOT NULL,
+  refunded_total FLOAT8 NOT NULL DEFAULT 0,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX sales_store_id_created_at_idx ON sales (store_id, created_at);
+
+CREATE TABLE sale_items (
+  id VARCHAR PRIMARY KEY,
+  sale_id VARCHAR NOT NULL REFERENCES sales (id),
+  product_id VARCHAR REFERENCES products (id) ON DELETE SET NULL,
+  title VARCHAR NOT NULL,
+  unit_price FLOAT8 NOT NULL,
+  quantity INTEGER NOT NULL CHECK (quantity > 0),
+  refunded_quantity INTEGER NOT NULL DEFAULT 0
+    CHECK (refunded_quantity >= 0 AND refunded_quantity <= quantity)
+);
+
+CREATE INDEX sale_items_sale_id_idx ON sale_items (sale_id);
+
+CREATE TABLE sale_refunds (
+  id VARCHAR PRIMARY KEY,
+  sale_id VARCHAR NOT NULL REFERENCES sales (id),
+  user_id VARCHAR NOT NULL REFERENCES users (id),
+  reason VARCHAR,
+  amount FLOAT8 NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX sale_refunds_sale_id_idx ON sale_refunds (sale_id);
+
+CREATE TABLE sale_refund_items (
+  refund_id VARCHAR NOT NULL REFERENCES sale_refunds (id),
+  sale_item_id VARCHAR NOT NULL REFERENCES sale_items (id),
+  quantity INTEGER NOT NULL CHECK (quantity > 0),
+  amount FLOAT8 NOT NULL,
+  restocked BOOLEAN NOT NULL,
+  PRIMARY KEY (refund_id, sale_item_id)
+);
+
+-- Refunds are an append-only ledger; a mistake is corrected by recording
+-- another sale, never by editing history.
+CREATE FUNCTION reject_refund_changes() RETURNS TRIGGER AS $$
+BEGIN
+  RAISE EXCEPTION 'refund records are immutable';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER sale_refunds_immutable
+  BEFORE UPDATE OR DELETE ON sale_refunds
+  FOR EACH ROW EXECUTE FUNCTION reject_refund_changes();
+
+CREATE TRIGGER sale_refund_items_immutable
+  BEFORE UPDATE OR DELETE ON sale_refund_items
+  FOR EACH ROW EXECUTE FUNCTION reject_refund_changes();
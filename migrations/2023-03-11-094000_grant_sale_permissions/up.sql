@@ -0,0 +1,11 @@
+INSERT INTO permissions (id, name)
+SELECT gen_random_uuid()::text, p.name
+FROM (VALUES ('sales.read'), ('sales.write')) AS p (name)
+ON CONFLICT (name) DO NOTHING;
+
+INSERT INTO role_permissions (role_id, permission_id)
+SELECT roles.id, permissions.id
+FROM roles, permissions
+WHERE roles.name IN ('admin', 'merchant', 'staff')
+  AND permissions.name IN ('sales.read', 'sales.write')
+ON CONFLICT DO NOTHING;
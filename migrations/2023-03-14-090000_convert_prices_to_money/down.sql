@@ -0,0 +1,32 @@
+ALTER TABLE sale_refund_items
+  DROP COLUMN currency,
+  ALTER COLUMN amount TYPE FLOAT8 USING amount / 100.0;
+
+ALTER TABLE sale_refunds
+  DROP COLUMN currency,
+  ALTER COLUMN amount TYPE FLOAT8 USING amount / 100.0;
+
+ALTER TABLE sale_items
+  DROP COLUMN currency,
+  ALTER COLUMN unit_price TYPE FLOAT8 USING unit_price / 100.0;
+
+ALTER TABLE sales
+  DROP CONSTRAINT sales_refunded_total_check,
+  DROP COLUMN currency,
+  ALTER COLUMN total TYPE FLOAT8 USING total / 100.0,
+  ALTER COLUMN refunded_total TYPE FLOAT8 USING refunded_total / 100.0;
+
+ALTER TABLE order_items
+  DROP COLUMN currency,
+  ALTER COLUMN unit_price TYPE FLOAT8 USING unit_price / 100.0;
+
+ALTER TABLE orders
+  DROP COLUMN currency,
+  ALTER COLUMN total TYPE FLOAT8 USING total / 100.0;
+
+ALTER TABLE products
+  DROP CONSTRAINT products_price_check,
+  DROP COLUMN currency,
+  ALTER COLUMN price TYPE FLOAT8 USING price / 100.0;
+
+ALTER TABLE store_settings DROP COLUMN currency;
@@ -0,0 +1,44 @@
+ALTER TABLE store_settings
+  ADD COLUMN currency VARCHAR(3) NOT NULL DEFAULT 'USD' CHECK (currency ~ '^[A-Z]{3}$');
+
+-- Every amount becomes an integer count of the currency's minor unit, with
+-- the currency stored next to it. Until now all stores priced in dollars, so
+-- existing rows are USD cents. A NaN or infinite float cannot be cast to
+-- NUMERIC and aborts the migration instead of being silently rounded.
+ALTER TABLE products
+  ADD COLUMN currency VARCHAR(3) NOT NULL DEFAULT 'USD' CHECK (currency ~ '^[A-Z]{3}$'),
+  ALTER COLUMN price TYPE BIGINT USING ROUND(price::numeric * 100)::bigint,
+  ADD CONSTRAINT products_price_check CHECK (price >= 0);
+ALTER TABLE products ALTER COLUMN currency DROP DEFAULT;
+
+ALTER TABLE orders
+  ADD COLUMN currency VARCHAR(3) NOT NULL DEFAULT 'USD',
+  ALTER COLUMN total TYPE BIGINT USING ROUND(total::numeric * 100)::bigint;
+ALTER TABLE orders ALTER COLUMN currency DROP DEFAULT;
+
+ALTER TABLE order_items
+  ADD COLUMN currency VARCHAR(3) NOT NULL DEFAULT 'USD',
+  ALTER COLUMN unit_price TYPE BIGINT USING ROUND(unit_price::numeric * 100)::bigint;
+ALTER TABLE order_items ALTER COLUMN currency DROP DEFAULT;
+
+ALTER TABLE sales
+  ADD COLUMN currency VARCHAR(3) NOT NULL DEFAULT 'USD',
+  ALTER COLUMN total TYPE BIGINT USING ROUND(total::numeric * 100)::bigint,
+  ALTER COLUMN refunded_total TYPE BIGINT USING ROUND(refunded_total::numeric * 100)::bigint,
+  ADD CONSTRAINT sales_refunded_total_check CHECK (refunded_total >= 0 AND refunded_total <= total);
+ALTER TABLE sales ALTER COLUMN currency DROP DEFAULT;
+
+ALTER TABLE sale_items
+  ADD COLUMN currency VARCHAR(3) NOT NULL DEFAULT 'USD',
+  ALTER COLUMN unit_price TYPE BIGINT USING ROUND(unit_price::numeric * 100)::bigint;
+ALTER TABLE sale_items ALTER COLUMN currency DROP DEFAULT;
+
+ALTER TABLE sale_refunds
+  ADD COLUMN currency VARCHAR(3) NOT NULL DEFAULT 'USD',
+  ALTER COLUMN amount TYPE BIGINT USING ROUND(amount::numeric * 100)::bigint;
+ALTER TABLE sale_refunds ALTER COLUMN currency DROP DEFAULT;
+
+ALTER TABLE sale_refund_items
+  ADD COLUMN currency VARCHAR(3) NOT NULL DEFAULT 'USD',
+  ALTER COLUMN amount TYPE BIGINT USING ROUND(amount::numeric * 100)::bigint;
+ALTER TABLE sale_refund_items ALTER COLUMN currency DROP DEFAULT;
@@ -0,0 +1,36 @@
+CREATE TABLE product_options (
+  id VARCHAR PRIMARY KEY,
+  product_id VARCHAR NOT NULL REFERENCES products (id) ON DELETE CASCADE,
+  name VARCHAR NOT NULL,
+  position INTEGER NOT NULL,
+  UNIQUE (product_id, name)
+);
+
+CREATE TABLE product_option_values (
+  id VARCHAR PRIMARY KEY,
+  option_id VARCHAR NOT NULL REFERENCES product_options (id) ON DELETE CASCADE,
+  value VARCHAR NOT NULL,
+  position INTEGER NOT NULL,
+  UNIQUE (option_id, value)
+);
+
+CREATE TABLE product_variants (
+  id VARCHAR PRIMARY KEY,
+  product_id VARCHAR NOT NULL REFERENCES products (id) ON DELETE CASCADE,
+  store_id VARCHAR NOT NULL REFERENCES stores (id),
+  sku VARCHAR NOT NULL,
+  -- Minor units of the product's currency; NULL sells at the product price.
+  price BIGINT CHECK (price >= 0),
+  barcode VARCHAR,
+  quantity INTEGER NOT NULL DEFAULT 0 CHECK (quantity >= 0),
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  UNIQUE (store_id, sku)
+);
+
+CREATE INDEX product_variants_product_id_idx ON product_variants (product_id);
+
+CREATE TABLE variant_option_values (
+  variant_id VARCHAR NOT NULL REFERENCES product_variants (id) ON DELETE CASCADE,
+  option_value_id VARCHAR NOT NULL REFERENCES product_option_values (id) ON DELETE CASCADE,
+  PRIMARY KEY (variant_id, option_value_id)
+);
@@ -0,0 +1,3 @@
+DROP TABLE stock_movements;
+DROP FUNCTION reject_stock_movement_changes;
+DROP TABLE stock_locations;
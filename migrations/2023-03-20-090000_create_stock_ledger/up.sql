@@ -0,0 +1,62 @@
+CREATE TABLE stock_locations (
+  id VARCHAR PRIMARY KEY,
+  store_id VARCHAR NOT NULL REFERENCES stores (id) ON DELETE CASCADE,
+  name VARCHAR NOT NULL,
+  is_default BOOLEAN NOT NULL DEFAULT FALSE,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  UNIQUE (store_id, name)
+);
+
+-- Sales draw from and returns go back to the default location first.
+CREATE UNIQUE INDEX stock_locations_default_idx ON stock_locations (store_id) WHERE is_default;
+
+INSERT INTO stock_locations (id, store_id, name, is_default)
+SELECT gen_random_uuid()::text, id, 'Default', TRUE
+FROM stores;
+
+CREATE TABLE stock_movements (
+  id VARCHAR PRIMARY KEY,
+  store_id VARCHAR NOT NULL REFERENCES stores (id),
+  product_id VARCHAR NOT NULL REFERENCES products (id) ON DELETE CASCADE,
+  location_id VARCHAR NOT NULL REFERENCES stock_locations (id),
+  kind VARCHAR NOT NULL
+    CHECK (kind IN ('receive', 'sale', 'return', 'adjustment', 'transfer')),
+  -- Signed change in units at the location.
+  quantity INTEGER NOT NULL CHECK (quantity <> 0),
+  -- Shared by the two legs of a transfer.
+  transfer_id VARCHAR,
+  -- The order or sale a movement was caused by.
+  reference VARCHAR,
+  note VARCHAR,
+  user_id VARCHAR REFERENCES users (id),
+  created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX stock_movements_product_id_created_at_idx
+  ON stock_movements (product_id, created_at);
+CREATE INDEX stock_movements_location_id_idx ON stock_movements (location_id);
+
+-- products.quantity stays as a running total of the ledger, so whatever is
+-- in stock today becomes the opening balance of the default location.
+INSERT INTO stock_movements (id, store_id, product_id, location_id, kind, quantity, note)
+SELECT gen_random_uuid()::text, products.store_id, products.id, stock_locations.id,
+  'adjustment', products.quantity, 'Opening balance'
+FROM products
+JOIN stock_locations
+  ON stock_locations.store_id = products.store_id AND stock_locations.is_default
+WHERE products.quantity <> 0;
+
+-- The ledger is append-only. Rows only disappear together with their
+-- product, when the cascade runs after the product row is already gone.
+CREATE FUNCTION reject_stock_movement_changes() RETURNS TRIGGER AS $$
+BEGIN
+  IF TG_OP = 'DELETE' AND NOT EXISTS (SELECT 1 FROM products WHERE id = OLD.product_id) THEN
+    RETURN OLD;
+  END IF;
+  RAISE EXCEPTION 'stock movements are immutable';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER stock_movements_immutable
+  BEFORE UPDATE OR DELETE ON stock_movements
+  FOR EACH ROW EXECUTE FUNCTION reject_stock_movement_changes();
@@ -0,0 +1,20 @@
+CREATE TABLE stock_reservations (
+  id VARCHAR PRIMARY KEY,
+  store_id VARCHAR NOT NULL REFERENCES stores (id) ON DELETE CASCADE,
+  product_id VARCHAR NOT NULL REFERENCES products (id) ON DELETE CASCADE,
+  user_id VARCHAR NOT NULL REFERENCES users (id),
+  quantity INTEGER NOT NULL CHECK (quantity > 0),
+  status VARCHAR NOT NULL DEFAULT 'active'
+    CHECK (status IN ('active', 'committed', 'cancelled', 'expired')),
+  -- The order a reservation was committed into, if any.
+  reference VARCHAR,
+  expires_at TIMESTAMP NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  updated_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Units held against a product are summed over its active reservations on
+-- every sale and reservation.
+CREATE INDEX stock_reservations_active_idx
+  ON stock_reservations (product_id, expires_at)
+  WHERE status = 'active';
@@ -0,0 +1,6 @@
+DROP TABLE collection_products;
+DROP TABLE collection_rules;
+DROP TABLE collections;
+DROP TABLE product_tags;
+DROP TABLE product_categories;
+DROP TABLE categories;
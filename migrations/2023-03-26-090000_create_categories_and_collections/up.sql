@@ -0,0 +1,62 @@
+CREATE TABLE categories (
+  id VARCHAR PRIMARY KEY,
+  store_id VARCHAR NOT NULL REFERENCES stores (id) ON DELETE CASCADE,
+  -- Top level categories have no parent.
+  parent_id VARCHAR REFERENCES categories (id),
+  name VARCHAR NOT NULL,
+  slug VARCHAR NOT NULL CHECK (slug ~ '^[a-z0-9]+(-[a-z0-9]+)*$'),
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  UNIQUE (store_id, slug),
+  CHECK (parent_id <> id)
+);
+
+CREATE INDEX categories_parent_id_idx ON categories (parent_id);
+
+CREATE TABLE product_categories (
+  product_id VARCHAR NOT NULL REFERENCES products (id) ON DELETE CASCADE,
+  category_id VARCHAR NOT NULL REFERENCES categories (id) ON DELETE CASCADE,
+  PRIMARY KEY (product_id, category_id)
+);
+
+CREATE INDEX product_categories_category_id_idx ON product_categories (category_id);
+
+CREATE TABLE product_tags (
+  product_id VARCHAR NOT NULL REFERENCES products (id) ON DELETE CASCADE,
+  tag VARCHAR NOT NULL CHECK (tag <> ''),
+  PRIMARY KEY (product_id, tag)
+);
+
+CREATE INDEX product_tags_tag_idx ON product_tags (tag);
+
+CREATE TABLE collections (
+  id VARCHAR PRIMARY KEY,
+  store_id VARCHAR NOT NULL REFERENCES stores (id) ON DELETE CASCADE,
+  name VARCHAR NOT NULL,
+  slug VARCHAR NOT NULL CHECK (slug ~ '^[a-z0-9]+(-[a-z0-9]+)*$'),
+  -- Manual collections list their products; rule collections match every
+  -- product satisfying their rules.
+  kind VARCHAR NOT NULL CHECK (kind IN ('manual', 'rule')),
+  -- Whether a product must satisfy all rules or any one of them.
+  match_all BOOLEAN NOT NULL DEFAULT TRUE,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  UNIQUE (store_id, slug)
+);
+
+CREATE TABLE collection_rules (
+  id VARCHAR PRIMARY KEY,
+  collection_id VARCHAR NOT NULL REFERENCES collections (id) ON DELETE CASCADE,
+  field VARCHAR NOT NULL CHECK (field IN ('price', 'tag', 'title', 'quantity')),
+  operator VARCHAR NOT NULL
+    CHECK (operator IN ('eq', 'ne', 'lt', 'lte', 'gt', 'gte', 'contains')),
+  value VARCHAR NOT NULL,
+  position INTEGER NOT NULL
+);
+
+CREATE INDEX collection_rules_collection_id_idx ON collection_rules (collection_id);
+
+CREATE TABLE collection_products (
+  collection_id VARCHAR NOT NULL REFERENCES collections (id) ON DELETE CASCADE,
+  product_id VARCHAR NOT NULL REFERENCES products (id) ON DELETE CASCADE,
+  position INTEGER NOT NULL,
+  PRIMARY KEY (collection_id, product_id)
+);
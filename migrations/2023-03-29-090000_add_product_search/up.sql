@@ -0,0 +1,9 @@
+-- Titles outrank descriptions. Kept by Postgres itself so every write to a
+-- product is searchable straight away.
+ALTER TABLE products
+  ADD COLUMN search_vector TSVECTOR NOT NULL GENERATED ALWAYS AS (
+    setweight(to_tsvector('english', title), 'A') ||
+    setweight(to_tsvector('english', COALESCE(description, '')), 'B')
+  ) STORED;
+
+CREATE INDEX products_search_vector_idx ON products USING GIN (search_vector);
@@ -0,0 +1,2 @@
+DROP INDEX stores_name_id_idx;
+ALTER TABLE stores DROP COLUMN created_at;
@@ -0,0 +1,4 @@
+ALTER TABLE stores ADD COLUMN created_at TIMESTAMP NOT NULL DEFAULT NOW();
+
+-- Backs the default store listing order and its cursor.
+CREATE INDEX stores_name_id_idx ON stores (name, id);
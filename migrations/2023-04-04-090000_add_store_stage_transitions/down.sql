@@ -0,0 +1,4 @@
+DROP TABLE store_stage_transitions;
+ALTER TABLE stores
+  DROP CONSTRAINT stores_stage_check,
+  ALTER COLUMN stage DROP DEFAULT;
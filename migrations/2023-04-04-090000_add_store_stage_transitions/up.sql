@@ -0,0 +1,25 @@
+-- Stages used to be free text; fold the spellings clients sent onto the
+-- known stages and park anything unrecognisable in draft.
+UPDATE stores
+SET stage = CASE
+  WHEN lower(trim(stage)) IN ('draft', 'setup', 'live', 'paused', 'archived') THEN lower(trim(stage))
+  ELSE 'draft'
+END;
+
+ALTER TABLE stores
+  ALTER COLUMN stage SET DEFAULT 'draft',
+  ADD CONSTRAINT stores_stage_check
+    CHECK (stage IN ('draft', 'setup', 'live', 'paused', 'archived'));
+
+CREATE TABLE store_stage_transitions (
+  id VARCHAR PRIMARY KEY,
+  store_id VARCHAR NOT NULL REFERENCES stores (id) ON DELETE CASCADE,
+  -- NULL for the stage a store was created in.
+  from_stage VARCHAR,
+  to_stage VARCHAR NOT NULL,
+  user_id VARCHAR REFERENCES users (id) ON DELETE SET NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX store_stage_transitions_store_id_created_at_idx
+  ON store_stage_transitions (store_id, created_at);
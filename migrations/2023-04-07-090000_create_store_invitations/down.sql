@@ -0,0 +1,3 @@
+DROP TABLE store_invitations;
+DROP INDEX user_stores_owner_idx;
+ALTER TABLE user_stores DROP COLUMN role;
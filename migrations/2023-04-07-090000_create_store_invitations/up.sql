@@ -0,0 +1,25 @@
+-- Until now only a store's creator was ever linked to it.
+ALTER TABLE user_stores
+  ADD COLUMN role VARCHAR NOT NULL DEFAULT 'owner'
+    CHECK (role IN ('owner', 'manager', 'staff', 'viewer'));
+ALTER TABLE user_stores ALTER COLUMN role DROP DEFAULT;
+
+CREATE UNIQUE INDEX user_stores_owner_idx ON user_stores (store_id) WHERE role = 'owner';
+
+CREATE TABLE store_invitations (
+  id VARCHAR PRIMARY KEY,
+  store_id VARCHAR NOT NULL REFERENCES stores (id) ON DELETE CASCADE,
+  email VARCHAR NOT NULL,
+  -- Ownership changes hands through a transfer, never an invitation.
+  role VARCHAR NOT NULL CHECK (role IN ('manager', 'staff', 'viewer')),
+  token_hash VARCHAR NOT NULL UNIQUE,
+  status VARCHAR NOT NULL DEFAULT 'pending'
+    CHECK (status IN ('pending', 'accepted', 'declined', 'revoked', 'expired')),
+  invited_by VARCHAR REFERENCES users (id) ON DELETE SET NULL,
+  expires_at TIMESTAMP NOT NULL,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+  responded_at TIMESTAMP
+);
+
+CREATE UNIQUE INDEX store_invitations_pending_idx
+  ON store_invitations (store_id, lower(email)) WHERE status = 'pending';
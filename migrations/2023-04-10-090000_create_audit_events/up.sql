@@ -0,0 +1,29 @@
+-- Ids are recorded as they were at the time and not tied to their rows by
+-- foreign keys, so events outlive the users, sessions and stores they name.
+CREATE TABLE audit_events (
+  id VARCHAR PRIMARY KEY,
+  actor_id VARCHAR,
+  session_id VARCHAR,
+  store_id VARCHAR,
+  -- Dotted resource and verb, e.g. store.update.
+  action VARCHAR NOT NULL,
+  target_id VARCHAR,
+  -- Only the fields that changed, as they were before and after.
+  before JSONB,
+  after JSONB,
+  ip VARCHAR,
+  created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX audit_events_store_id_created_at_idx ON audit_events (store_id, created_at, id);
+CREATE INDEX audit_events_actor_id_created_at_idx ON audit_events (actor_id, created_at);
+
+CREATE FUNCTION reject_audit_event_changes() RETURNS TRIGGER AS $$
+BEGIN
+  RAISE EXCEPTION 'audit events are append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_events_append_only
+  BEFORE UPDATE OR DELETE ON audit_events
+  FOR EACH ROW EXECUTE FUNCTION reject_audit_event_changes();
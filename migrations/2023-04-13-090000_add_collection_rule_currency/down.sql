@@ -0,0 +1 @@
+ALTER TABLE collection_rules DROP COLUMN currency;
@@ -0,0 +1,13 @@
+-- Price rules keep the currency their amount was written in, so a later
+-- change of store currency does not change what they match.
+ALTER TABLE collection_rules ADD COLUMN currency VARCHAR;
+
+UPDATE collection_rules r
+SET currency = s.currency
+FROM collections c
+JOIN store_settings s ON s.store_id = c.store_id
+WHERE r.collection_id = c.id AND r.field = 'price';
+
+ALTER TABLE collection_rules
+  ADD CONSTRAINT collection_rules_currency_check
+  CHECK ((field = 'price') = (currency IS NOT NULL));
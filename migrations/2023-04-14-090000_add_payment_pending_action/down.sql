@@ -0,0 +1,2 @@
+ALTER TABLE payments DROP COLUMN pending_since;
+ALTER TABLE payments DROP COLUMN pending_action;
@@ -0,0 +1,8 @@
+-- Set while a provider call for the payment is in flight, so the call can be
+-- made outside the row lock without a second action racing it.
+ALTER TABLE payments ADD COLUMN pending_action VARCHAR;
+ALTER TABLE payments ADD COLUMN pending_since TIMESTAMP;
+
+ALTER TABLE payments
+  ADD CONSTRAINT payments_pending_check
+  CHECK ((pending_action IS NULL) = (pending_since IS NULL));
@@ -0,0 +1 @@
+ALTER TABLE product_variants ADD COLUMN quantity INTEGER NOT NULL DEFAULT 0 CHECK (quantity >= 0);
@@ -0,0 +1,4 @@
+-- Stock is kept in the ledger per product. Variant quantities were never
+-- moved by carts, orders, sales or reservations, so they only ever held the
+-- opening figure and are dropped rather than advertised as stock.
+ALTER TABLE product_variants DROP COLUMN quantity;
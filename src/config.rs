@@ -0,0 +1,255 @@
+use serde::Deserialize;
+use std::{env, fmt, fs, net::SocketAddr, path::Path};
+
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(String, std::io::Error),
+    Parse(String, toml::de::Error),
+    Missing(&'static str),
+    Invalid(&'static str, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, err) => write!(f, "could not read {path}: {err}"),
+            ConfigError::Parse(path, err) => write!(f, "could not parse {path}: {err}"),
+            ConfigError::Missing(key) => write!(f, "{key} must be set"),
+            ConfigError::Invalid(key, reason) => write!(f, "{key} {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub secret: String,
+    pub database_url: String,
+    pub listen_address: SocketAddr,
+    pub pool_size: u32,
+    /// `*` allows any origin.
+    pub cors_origins: Vec<String>,
+    pub access_token_ttl: chrono::Duration,
+    /// How long a session, and so its refresh token chain, survives without
+    /// being refreshed.
+    pub session_ttl: chrono::Duration,
+    /// How long reserved stock is held before it is released unless
+    /// committed.
+    pub reservation_ttl: chrono::Duration,
+    /// Most units one buyer may hold of a product at a time.
+    pub reservation_max_units: i32,
+    /// How long an invitation to join a store can be accepted.
+    pub invitation_ttl: chrono::Duration,
+    pub log_level: String,
+    pub password_hash: PasswordHashConfig,
+    /// Provider new payments go through.
+    pub payment_provider: String,
+    /// Without one, webhooks claiming to come from the mock gateway are
+    /// rejected.
+    pub mock_payment_webhook_secret: Option<String>,
+    /// Account given the admin role on every start, so a fresh database has
+    /// someone who can manage roles and permissions.
+    pub admin_email: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PasswordHashConfig {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+/// Mirrors `Config` with every field optional, as read from the TOML file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileConfig {
+    secret: Option<String>,
+    database_url: Option<String>,
+    listen_address: Option<String>,
+    pool_size: Option<u32>,
+    cors_origins: Option<Vec<String>>,
+    access_token_ttl_secs: Option<i64>,
+    session_ttl_secs: Option<i64>,
+    reservation_ttl_secs: Option<i64>,
+    reservation_max_units: Option<i32>,
+    invitation_ttl_secs: Option<i64>,
+    log_level: Option<String>,
+    password_hash_memory_kib: Option<u32>,
+    password_hash_iterations: Option<u32>,
+    password_hash_parallelism: Option<u32>,
+    payment_provider: Option<String>,
+    mock_payment_webhook_secret: Option<String>,
+    admin_email: Option<String>,
+}
+
+impl Config {
+    /// Reads `CONFIG_FILE` (or `config.toml` when present) and lets
+    /// environment variables override any value found there.
+    pub fn load() -> Result<Config, ConfigError> {
+        let file = match env::var("CONFIG_FILE") {
+            Ok(path) => read_file(&path)?,
+            Err(_) if Path::new(DEFAULT_CONFIG_FILE).exists() => read_file(DEFAULT_CONFIG_FILE)?,
+            Err(_) => FileConfig::default(),
+        };
+
+        let secret = env_string("JWT_SECRET")
+            .or(file.secret)
+            .ok_or(ConfigError::Missing("JWT_SECRET"))?;
+        if secret.len() < 32 {
+            return Err(ConfigError::Invalid(
+                "JWT_SECRET",
+                "must be at least 32 characters long".to_string(),
+            ));
+        }
+
+        let database_url = env_string("DATABASE_URL")
+            .or(file.database_url)
+            .ok_or(ConfigError::Missing("DATABASE_URL"))?;
+
+        let listen_address = env_string("LISTEN_ADDRESS")
+            .or(file.listen_address)
+            .unwrap_or_else(|| "127.0.0.1:4000".to_string());
+        let listen_address = listen_address.parse().map_err(|_| {
+            ConfigError::Invalid(
+                "LISTEN_ADDRESS",
+                format!("must be a host:port socket address, got \"{listen_address}\""),
+            )
+        })?;
+
+        let pool_size = env_parse("DATABASE_POOL_SIZE")?
+            .or(file.pool_size)
+            .unwrap_or(10);
+        if pool_size == 0 {
+            return Err(ConfigError::Invalid(
+                "DATABASE_POOL_SIZE",
+                "must be greater than zero".to_string(),
+            ));
+        }
+
+        let cors_origins = env_string("CORS_ORIGINS")
+            .map(|origins| {
+                origins
+                    .split(',')
+                    .map(|origin| origin.trim().to_string())
+                    .filter(|origin| !origin.is_empty())
+                    .collect()
+            })
+            .or(file.cors_origins)
+            .unwrap_or_else(|| vec!["*".to_string()]);
+
+        let access_token_ttl = ttl(
+            "ACCESS_TOKEN_TTL_SECS",
+            env_parse("ACCESS_TOKEN_TTL_SECS")?.or(file.access_token_ttl_secs),
+            15 * 60,
+        )?;
+        let session_ttl = ttl(
+            "SESSION_TTL_SECS",
+            env_parse("SESSION_TTL_SECS")?.or(file.session_ttl_secs),
+            30 * 24 * 60 * 60,
+        )?;
+        if session_ttl < access_token_ttl {
+            return Err(ConfigError::Invalid(
+                "SESSION_TTL_SECS",
+                "must not be shorter than ACCESS_TOKEN_TTL_SECS".to_string(),
+            ));
+        }
+
+        let reservation_ttl = ttl(
+            "RESERVATION_TTL_SECS",
+            env_parse("RESERVATION_TTL_SECS")?.or(file.reservation_ttl_secs),
+            15 * 60,
+        )?;
+        let reservation_max_units = env_parse("RESERVATION_MAX_UNITS")?
+            .or(file.reservation_max_units)
+            .unwrap_or(10);
+        if reservation_max_units <= 0 {
+            return Err(ConfigError::Invalid(
+                "RESERVATION_MAX_UNITS",
+                "must be greater than zero".to_string(),
+            ));
+        }
+        let invitation_ttl = ttl(
+            "INVITATION_TTL_SECS",
+            env_parse("INVITATION_TTL_SECS")?.or(file.invitation_ttl_secs),
+            7 * 24 * 60 * 60,
+        )?;
+
+        let log_level = env_string("LOG_LEVEL")
+            .or(file.log_level)
+            .unwrap_or_else(|| "info".to_string());
+
+        let password_hash = PasswordHashConfig {
+            memory_kib: env_parse("PASSWORD_HASH_MEMORY_KIB")?
+                .or(file.password_hash_memory_kib)
+                .unwrap_or(19 * 1024),
+            iterations: env_parse("PASSWORD_HASH_ITERATIONS")?
+                .or(file.password_hash_iterations)
+                .unwrap_or(2),
+            parallelism: env_parse("PASSWORD_HASH_PARALLELISM")?
+                .or(file.password_hash_parallelism)
+                .unwrap_or(1),
+        };
+
+        let payment_provider = env_string("PAYMENT_PROVIDER")
+            .or(file.payment_provider)
+            .unwrap_or_else(|| "mock".to_string());
+        let mock_payment_webhook_secret =
+            env_string("MOCK_PAYMENT_WEBHOOK_SECRET").or(file.mock_payment_webhook_secret);
+        let admin_email = env_string("ADMIN_EMAIL").or(file.admin_email);
+
+        Ok(Config {
+            secret,
+            database_url,
+            listen_address,
+            pool_size,
+            cors_origins,
+            access_token_ttl,
+            session_ttl,
+            reservation_ttl,
+            reservation_max_units,
+            invitation_ttl,
+            log_level,
+            password_hash,
+            payment_provider,
+            mock_payment_webhook_secret,
+            admin_email,
+        })
+    }
+}
+
+fn read_file(path: &str) -> Result<FileConfig, ConfigError> {
+    let contents = fs::read_to_string(path).map_err(|err| ConfigError::Read(path.into(), err))?;
+    toml::from_str(&contents).map_err(|err| ConfigError::Parse(path.into(), err))
+}
+
+fn env_string(key: &str) -> Option<String> {
+    env::var(key).ok().filter(|value| !value.is_empty())
+}
+
+fn env_parse<T: std::str::FromStr>(key: &'static str) -> Result<Option<T>, ConfigError> {
+    match env_string(key) {
+        Some(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| ConfigError::Invalid(key, format!("has an invalid value \"{value}\""))),
+        None => Ok(None),
+    }
+}
+
+fn ttl(
+    key: &'static str,
+    secs: Option<i64>,
+    default: i64,
+) -> Result<chrono::Duration, ConfigError> {
+    let secs = secs.unwrap_or(default);
+    if secs <= 0 {
+        return Err(ConfigError::Invalid(
+            key,
+            "must be a positive number of seconds".to_string(),
+        ));
+    }
+    Ok(chrono::Duration::seconds(secs))
+}
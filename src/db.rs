@@ -0,0 +1,132 @@
+use crate::{errors::ApiError, DbPool};
+use actix_web::web;
+use diesel::{Connection, PgConnection};
+
+/// Runs `work` as one unit of work: on a single pooled connection, inside a
+/// single transaction, off the async executor. When `work` fails, nothing it
+/// wrote is kept.
+pub async fn unit_of_work<T, F>(pool: &DbPool, work: F) -> Result<T, ApiError>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut PgConnection) -> Result<T, ApiError> + Send + 'static,
+{
+    let pool = pool.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        conn.transaction(|conn| work(conn))
+    })
+    .await?
+}
+
+#[cfg(test)]
+pub mod test_support {
+    use crate::DbPool;
+    use diesel::{
+        r2d2::{self, ConnectionManager, CustomizeConnection},
+        Connection, PgConnection,
+    };
+
+    /// Keeps the pool's only connection in a transaction that is never
+    /// committed, so tests can write freely and leave the database as they
+    /// found it.
+    #[derive(Debug)]
+    struct RolledBack;
+
+    impl CustomizeConnection<PgConnection, r2d2::Error> for RolledBack {
+        fn on_acquire(&self, conn: &mut PgConnection) -> Result<(), r2d2::Error> {
+            conn.begin_test_transaction()
+                .map_err(r2d2::Error::QueryError)
+        }
+    }
+
+    /// A single-connection pool on the migrated database in `DATABASE_URL`.
+    /// Tests using it are `#[ignore]`d and run with `cargo test -- --ignored`;
+    /// without a database they fail rather than pass without running.
+    pub fn test_pool() -> DbPool {
+        dotenv::dotenv().ok();
+        let url = std::env::var("DATABASE_URL")
+            .expect("DATABASE_URL must point at a migrated database to run database tests");
+        let pool = r2d2::Pool::builder()
+            .max_size(1)
+            .connection_customizer(Box::new(RolledBack))
+            .build(ConnectionManager::<PgConnection>::new(url))
+            .expect("Failed to connect to the test database.");
+        crate::seed::seed_defaults(None, &mut pool.get().expect("Failed to get a connection."))
+            .expect("Failed to seed default roles and permissions.");
+        pool
+    }
+
+    /// Adds a user with the sign-up role and returns their id.
+    pub fn create_user(conn: &mut PgConnection) -> String {
+        use crate::{models::NewUser, schema::roles, seed::SIGN_UP_ROLE};
+        use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+
+        let role_id = roles::table
+            .filter(roles::name.eq(SIGN_UP_ROLE))
+            .select(roles::id)
+            .first::<String>(conn)
+            .unwrap();
+        let id = uuid::Uuid::new_v4().to_string();
+        diesel::insert_into(crate::schema::users::table)
+            .values(&NewUser {
+                id: &id,
+                role_id: &role_id,
+                email: &format!("{id}@example.com"),
+                password: "not-a-hash",
+            })
+            .execute(conn)
+            .unwrap();
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{test_support::test_pool, unit_of_work};
+    use crate::errors::ApiError;
+    use diesel::{sql_query, RunQueryDsl};
+
+    fn role_count(name: &str, conn: &mut diesel::PgConnection) -> i64 {
+        use crate::schema::roles;
+        use diesel::{ExpressionMethods, QueryDsl};
+
+        roles::table
+            .filter(roles::name.eq(name))
+            .count()
+            .get_result(conn)
+            .unwrap()
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a migrated database in DATABASE_URL"]
+    async fn commits_when_work_succeeds() {
+        let pool = test_pool();
+
+        unit_of_work(&pool, |conn| {
+            sql_query("INSERT INTO roles (id, name) VALUES ('uow-ok', 'uow-ok')").execute(conn)?;
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(role_count("uow-ok", &mut pool.get().unwrap()), 1);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a migrated database in DATABASE_URL"]
+    async fn rolls_back_everything_when_work_fails() {
+        let pool = test_pool();
+
+        let res = unit_of_work(&pool, |conn| {
+            sql_query("INSERT INTO roles (id, name) VALUES ('uow-a', 'uow-a')").execute(conn)?;
+            sql_query("INSERT INTO roles (id, name) VALUES ('uow-b', 'uow-b')").execute(conn)?;
+            Err::<(), _>(ApiError::Internal("injected failure".to_string()))
+        })
+        .await;
+
+        assert!(matches!(res, Err(ApiError::Internal(_))));
+        let mut conn = pool.get().unwrap();
+        assert_eq!(role_count("uow-a", &mut conn), 0);
+        assert_eq!(role_count("uow-b", &mut conn), 0);
+    }
+}
@@ -0,0 +1,156 @@
+use actix_web::{error::BlockingError, http::StatusCode, HttpResponse, ResponseError};
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug)]
+pub enum ApiError {
+    BadRequest(String),
+    Validation(String),
+    Unauthorized(&'static str),
+    TokenExpired,
+    InvalidCredentials,
+    SessionNotFound,
+    SessionExpired,
+    RefreshTokenReused,
+    Forbidden(&'static str),
+    MissingPermission(&'static str),
+    NotFound(&'static str),
+    EmailTaken,
+    Conflict(String),
+    InvalidTransition(String),
+    PaymentProvider(String),
+    Internal(String),
+}
+
+#[derive(Serialize, Deserialize)]
+struct ErrorBody {
+    code: &'static str,
+    message: String,
+}
+
+impl ApiError {
+    /// Stable identifier clients can match on instead of the message text.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Validation(_) => "validation_failed",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::TokenExpired => "token_expired",
+            ApiError::InvalidCredentials => "invalid_credentials",
+            ApiError::SessionNotFound => "session_not_found",
+            ApiError::SessionExpired => "session_expired",
+            ApiError::RefreshTokenReused => "refresh_token_reused",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::MissingPermission(_) => "missing_permission",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::EmailTaken => "email_taken",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::InvalidTransition(_) => "invalid_transition",
+            ApiError::PaymentProvider(_) => "payment_provider_error",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::BadRequest(message)
+            | ApiError::Validation(message)
+            | ApiError::Conflict(message)
+            | ApiError::InvalidTransition(message) => f.write_str(message),
+            ApiError::Unauthorized(message)
+            | ApiError::Forbidden(message)
+            | ApiError::NotFound(message) => f.write_str(message),
+            ApiError::TokenExpired => f.write_str("Expired token sent!"),
+            ApiError::InvalidCredentials => f.write_str("Invalid email or password"),
+            ApiError::SessionNotFound => f.write_str("User session not found"),
+            ApiError::SessionExpired => f.write_str("User session expired"),
+            ApiError::RefreshTokenReused => {
+                f.write_str("Refresh token was already used, the session has been revoked")
+            }
+            ApiError::MissingPermission(permission) => {
+                write!(f, "Missing permission: {permission}")
+            }
+            ApiError::EmailTaken => f.write_str("Email has already registered"),
+            ApiError::PaymentProvider(message) => {
+                write!(f, "Payment provider error: {message}")
+            }
+            ApiError::Internal(_) => f.write_str("Internal server error"),
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Unauthorized(_)
+            | ApiError::TokenExpired
+            | ApiError::InvalidCredentials
+            | ApiError::SessionNotFound
+            | ApiError::SessionExpired
+            | ApiError::RefreshTokenReused => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) | ApiError::MissingPermission(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::EmailTaken | ApiError::Conflict(_) | ApiError::InvalidTransition(_) => {
+                StatusCode::CONFLICT
+            }
+            ApiError::PaymentProvider(_) => StatusCode::BAD_GATEWAY,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        if let ApiError::Internal(detail) = self {
+            log::error!("{detail}");
+        }
+
+        HttpResponse::build(self.status_code()).json(ErrorBody {
+            code: self.code(),
+            message: self.to_string(),
+        })
+    }
+}
+
+impl From<DieselError> for ApiError {
+    fn from(err: DieselError) -> Self {
+        match err {
+            DieselError::NotFound => ApiError::NotFound("Resource not found"),
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                ApiError::Conflict("Resource already exists".to_string())
+            }
+            DieselError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, info) => {
+                log::warn!("{}", info.message());
+                ApiError::Conflict("Resource is referenced by other records".to_string())
+            }
+            err => ApiError::Internal(err.to_string()),
+        }
+    }
+}
+
+impl From<r2d2::Error> for ApiError {
+    fn from(err: r2d2::Error) -> Self {
+        ApiError::Internal(err.to_string())
+    }
+}
+
+impl From<BlockingError> for ApiError {
+    fn from(err: BlockingError) -> Self {
+        ApiError::Internal(err.to_string())
+    }
+}
+
+impl From<Box<dyn std::error::Error + Send + Sync>> for ApiError {
+    fn from(err: Box<dyn std::error::Error + Send + Sync>) -> Self {
+        ApiError::Internal(err.to_string())
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for ApiError {
+    fn from(err: jsonwebtoken::errors::Error) -> Self {
+        ApiError::Internal(err.to_string())
+    }
+}
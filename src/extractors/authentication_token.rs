@@ -1,16 +1,14 @@
-use actix_web::{
-    dev::Payload, error::ErrorUnauthorized, http::header::HeaderValue, web, Error as ActixWebError,
-    FromRequest, HttpRequest,
-};
+use actix_web::{dev::Payload, http::header::HeaderValue, web, FromRequest, HttpRequest};
+use diesel::{OptionalExtension, PgConnection, QueryDsl, RunQueryDsl};
 use jsonwebtoken::{
     decode,
     errors::{Error as JwtError, ErrorKind},
     Algorithm, DecodingKey, TokenData, Validation,
 };
 use serde::{Deserialize, Serialize};
-use std::future::{ready, Ready};
+use std::{future::Future, pin::Pin};
 
-use crate::AppState;
+use crate::{errors::ApiError, AppState};
 
 #[derive(Serialize, Deserialize)]
 pub struct Claims {
@@ -18,58 +16,84 @@ pub struct Claims {
     pub exp: usize,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-struct EncodeBody {
-    email: String,
-    password: String,
-}
-
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AuthenticationToken {
     pub id: usize,
 }
 
+/// Besides validating the JWT, makes sure its session row still exists so a
+/// logged out or revoked token stops working before it expires.
 impl FromRequest for AuthenticationToken {
-    type Error = ActixWebError;
-    type Future = Ready<Result<Self, Self::Error>>;
+    type Error = ApiError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
 
     fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
-        let req = req.clone();
-        let authorization_header_option: Option<&HeaderValue> =
-            req.headers().get(actix_web::http::header::AUTHORIZATION);
-
-        if authorization_header_option.is_none() {
-            return ready(Err(ErrorUnauthorized("No authentication token sent!")));
-        }
-
-        let authentication_token: String = authorization_header_option
-            .unwrap()
-            .to_str()
-            .unwrap_or("")
-            .to_string();
-
-        if authentication_token.is_empty() {
-            return ready(Err(ErrorUnauthorized(
-                "Authentication token has foreign chars!",
-            )));
-        }
-
-        let secret: &str = &&req.app_data::<web::Data<AppState>>().unwrap().secret;
-
-        let token_result: Result<TokenData<Claims>, JwtError> = decode::<Claims>(
-            &authentication_token,
-            &DecodingKey::from_secret(secret.as_ref()),
-            &Validation::new(Algorithm::HS256),
-        );
-
-        match token_result {
-            Ok(token) => ready(Ok(AuthenticationToken {
-                id: token.claims.id,
-            })),
-            Err(err) => match err.kind() {
-                ErrorKind::ExpiredSignature => ready(Err(ErrorUnauthorized("Expired token sent!"))),
-                _ => ready(Err(ErrorUnauthorized("Invalid authentication token sent!"))),
-            },
-        }
+        let claim_id = decode_claim_id(req);
+        let state = req.app_data::<web::Data<AppState>>().unwrap().clone();
+
+        Box::pin(async move {
+            let id = claim_id?;
+
+            web::block(move || {
+                let mut conn = state.pool.get()?;
+                check_session(&id.to_string(), &mut conn)
+            })
+            .await??;
+
+            Ok(AuthenticationToken { id })
+        })
+    }
+}
+
+fn decode_claim_id(req: &HttpRequest) -> Result<usize, ApiError> {
+    let authorization_header_option: Option<&HeaderValue> =
+        req.headers().get(actix_web::http::header::AUTHORIZATION);
+
+    if authorization_header_option.is_none() {
+        return Err(ApiError::Unauthorized("No authentication token sent!"));
+    }
+
+    let authentication_token: String = authorization_header_option
+        .unwrap()
+        .to_str()
+        .unwrap_or("")
+        .to_string();
+
+    if authentication_token.is_empty() {
+        return Err(ApiError::Unauthorized(
+            "Authentication token has foreign chars!",
+        ));
+    }
+
+    let secret: &str = &req.app_data::<web::Data<AppState>>().unwrap().secret;
+
+    let token_result: Result<TokenData<Claims>, JwtError> = decode::<Claims>(
+        &authentication_token,
+        &DecodingKey::from_secret(secret.as_ref()),
+        &Validation::new(Algorithm::HS256),
+    );
+
+    match token_result {
+        Ok(token) => Ok(token.claims.id),
+        Err(err) => match err.kind() {
+            ErrorKind::ExpiredSignature => Err(ApiError::TokenExpired),
+            _ => Err(ApiError::Unauthorized("Invalid authentication token sent!")),
+        },
+    }
+}
+
+fn check_session(session_id: &str, conn: &mut PgConnection) -> Result<(), ApiError> {
+    use crate::schema::session::dsl::*;
+
+    let expiry = session
+        .find(session_id)
+        .select(expires_at)
+        .first::<chrono::NaiveDateTime>(conn)
+        .optional()?
+        .ok_or(ApiError::SessionNotFound)?;
+
+    if expiry < chrono::Local::now().naive_local() {
+        return Err(ApiError::SessionExpired);
     }
+    Ok(())
 }
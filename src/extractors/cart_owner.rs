@@ -0,0 +1,66 @@
+use actix_web::{dev::Payload, http::header::AUTHORIZATION, web, FromRequest, HttpRequest};
+use diesel::{OptionalExtension, PgConnection, QueryDsl, RunQueryDsl};
+use std::{
+    future::{ready, Future},
+    pin::Pin,
+};
+
+use crate::{errors::ApiError, extractors::authentication_token::AuthenticationToken, AppState};
+
+pub const CART_TOKEN_HEADER: &str = "X-Cart-Token";
+
+/// Who a cart request acts for: the signed-in user when an `Authorization`
+/// header is sent, otherwise a guest holding the token from `X-Cart-Token`.
+/// A guest without a token has no cart until their first write creates one.
+pub enum CartOwner {
+    Customer { user_id: String },
+    Guest { token: Option<String> },
+}
+
+impl FromRequest for CartOwner {
+    type Error = ApiError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        if !req.headers().contains_key(AUTHORIZATION) {
+            return Box::pin(ready(Ok(CartOwner::Guest {
+                token: read_cart_token(req),
+            })));
+        }
+
+        let auth_token = AuthenticationToken::from_request(req, payload);
+        let state = req.app_data::<web::Data<AppState>>().unwrap().clone();
+
+        Box::pin(async move {
+            let auth_token = auth_token.await?;
+
+            let user_id = web::block(move || {
+                let mut conn = state.pool.get()?;
+                get_session_user(&auth_token.id.to_string(), &mut conn)
+            })
+            .await??;
+
+            Ok(CartOwner::Customer { user_id })
+        })
+    }
+}
+
+pub fn read_cart_token(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get(CART_TOKEN_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty())
+        .map(String::from)
+}
+
+fn get_session_user(session_id: &str, conn: &mut PgConnection) -> Result<String, ApiError> {
+    use crate::schema::session::dsl::*;
+
+    let res = session
+        .find(session_id)
+        .select(user_id)
+        .first::<String>(conn)
+        .optional()?
+        .ok_or(ApiError::SessionNotFound)?;
+    Ok(res)
+}
@@ -1 +1,3 @@
 pub mod authentication_token;
+pub mod cart_owner;
+pub mod require_permission;
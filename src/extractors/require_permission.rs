@@ -0,0 +1,101 @@
+use actix_web::{dev::Payload, web, FromRequest, HttpRequest};
+use diesel::{ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl};
+use std::{future::Future, marker::PhantomData, pin::Pin};
+
+use crate::{errors::ApiError, extractors::authentication_token::AuthenticationToken, AppState};
+
+pub trait Permission {
+    const NAME: &'static str;
+}
+
+macro_rules! permissions {
+    ($($marker:ident => $name:literal),* $(,)?) => {
+        $(
+            pub struct $marker;
+
+            impl Permission for $marker {
+                const NAME: &'static str = $name;
+            }
+        )*
+
+        pub const ALL_PERMISSIONS: &[&str] = &[$($name),*];
+    };
+}
+
+permissions! {
+    StoresRead => "stores.read",
+    StoresWrite => "stores.write",
+    StoresDelete => "stores.delete",
+    ProductsRead => "products.read",
+    ProductsWrite => "products.write",
+    ProductsDelete => "products.delete",
+    RolesRead => "roles.read",
+    RolesWrite => "roles.write",
+    OrdersRead => "orders.read",
+    OrdersWrite => "orders.write",
+    PaymentsRead => "payments.read",
+    PaymentsWrite => "payments.write",
+    SalesRead => "sales.read",
+    SalesWrite => "sales.write",
+    InventoryRead => "inventory.read",
+    InventoryWrite => "inventory.write",
+}
+
+/// Rejects the request with 403 unless the caller's session role has been
+/// granted `P` through `role_permissions`.
+pub struct RequirePermission<P: Permission> {
+    _permission: PhantomData<P>,
+}
+
+impl<P: Permission + 'static> FromRequest for RequirePermission<P> {
+    type Error = ApiError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let auth_token = AuthenticationToken::from_request(req, payload);
+        let state = req.app_data::<web::Data<AppState>>().unwrap().clone();
+
+        Box::pin(async move {
+            let auth_token = auth_token.await?;
+
+            web::block(move || {
+                let mut conn = state.pool.get()?;
+                check_session_permission(&auth_token.id.to_string(), P::NAME, &mut conn)
+            })
+            .await??;
+
+            Ok(RequirePermission {
+                _permission: PhantomData,
+            })
+        })
+    }
+}
+
+fn check_session_permission(
+    session_id: &str,
+    permission: &'static str,
+    conn: &mut PgConnection,
+) -> Result<(), ApiError> {
+    use crate::schema::{permissions, role_permissions, session};
+
+    let role = session::table
+        .find(session_id)
+        .filter(session::expires_at.gt(chrono::Local::now().naive_local()))
+        .select(session::role_id)
+        .first::<String>(conn)
+        .optional()?
+        .ok_or(ApiError::SessionNotFound)?;
+
+    let count: i64 = role_permissions::table
+        .inner_join(permissions::table)
+        .filter(role_permissions::role_id.eq(role))
+        .filter(permissions::name.eq(permission))
+        .count()
+        .get_result(conn)?;
+
+    if count > 0 {
+        Ok(())
+    } else {
+        Err(ApiError::MissingPermission(permission))
+    }
+}
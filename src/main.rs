@@ -1,21 +1,45 @@
-use crate::scopes::{store::store_scope, user::user_scope};
+use crate::config::Config;
+use crate::errors::ApiError;
+use crate::password::PasswordHasher;
+use crate::payments::{mock::MockProvider, PaymentProviders};
+use crate::scopes::{
+    member::invitation_scope, payment::payment_scope, permission::permission_scope,
+    product::product_scope, role::role_scope, store::store_scope, user::user_scope,
+};
 use actix_cors::Cors;
 use actix_web::{middleware, web, App, HttpServer};
 use diesel::pg::PgConnection;
 use diesel::r2d2::{self, ConnectionManager};
 use dotenv::dotenv;
-use std::env;
 use std::io::Result;
+use std::sync::Arc;
 
 mod config;
+mod db;
+mod errors;
 mod extractors;
 mod models;
+mod money;
+mod order_status;
+mod pagination;
+mod password;
+mod payments;
 mod schema;
 mod scopes;
+mod seed;
+mod store_stage;
+mod token;
 
 struct AppState {
     secret: String,
     pool: DbPool,
+    hasher: PasswordHasher,
+    payments: PaymentProviders,
+    access_token_ttl: chrono::Duration,
+    session_ttl: chrono::Duration,
+    reservation_ttl: chrono::Duration,
+    reservation_max_units: i32,
+    invitation_ttl: chrono::Duration,
 }
 
 pub type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
@@ -24,31 +48,89 @@ pub type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
 async fn main() -> Result<()> {
     dotenv().ok();
 
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Invalid configuration: {err}");
+            std::process::exit(1);
+        }
+    };
 
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL");
-    let manager = ConnectionManager::<PgConnection>::new(database_url);
+    env_logger::init_from_env(env_logger::Env::new().default_filter_or(&config.log_level));
+
+    let manager = ConnectionManager::<PgConnection>::new(&config.database_url);
     let pool: DbPool = r2d2::Pool::builder()
+        .max_size(config.pool_size)
         .build(manager)
         .expect("Failed to create pool.");
+    seed::seed_defaults(
+        config.admin_email.as_deref(),
+        &mut pool.get().expect("Failed to get a connection."),
+    )
+    .expect("Failed to seed default roles and permissions.");
+    let hasher = PasswordHasher::new(
+        config.password_hash.memory_kib,
+        config.password_hash.iterations,
+        config.password_hash.parallelism,
+    )
+    .expect("Invalid password hashing parameters.");
+
+    let payments = PaymentProviders::new(
+        vec![Arc::new(MockProvider::new(
+            config.mock_payment_webhook_secret.clone(),
+        ))],
+        &config.payment_provider,
+    )
+    .expect("Invalid payment provider.");
+
+    let listen_address = config.listen_address;
+    log::info!("Listening on {listen_address}");
 
     HttpServer::new(move || {
-        let cors = Cors::default()
-            .allow_any_origin()
-            .allow_any_method()
-            .allow_any_header();
+        let mut cors = Cors::default().allow_any_method().allow_any_header();
+        for origin in &config.cors_origins {
+            cors = if origin == "*" {
+                cors.allow_any_origin()
+            } else {
+                cors.allowed_origin(origin)
+            };
+        }
 
         App::new()
             .wrap(cors)
             .app_data(web::Data::new(AppState {
-                secret: String::from("#Easy#Commerce#SecretKey#"),
+                secret: config.secret.clone(),
                 pool: pool.clone(),
+                hasher: hasher.clone(),
+                payments: payments.clone(),
+                access_token_ttl: config.access_token_ttl,
+                session_ttl: config.session_ttl,
+                reservation_ttl: config.reservation_ttl,
+                reservation_max_units: config.reservation_max_units,
+                invitation_ttl: config.invitation_ttl,
             }))
+            .app_data(
+                web::JsonConfig::default()
+                    .error_handler(|err, _| ApiError::BadRequest(err.to_string()).into()),
+            )
+            .app_data(
+                web::PathConfig::default()
+                    .error_handler(|err, _| ApiError::BadRequest(err.to_string()).into()),
+            )
+            .app_data(
+                web::QueryConfig::default()
+                    .error_handler(|err, _| ApiError::BadRequest(err.to_string()).into()),
+            )
             .wrap(middleware::Logger::default())
             .service(user_scope())
             .service(store_scope())
+            .service(product_scope())
+            .service(role_scope())
+            .service(permission_scope())
+            .service(payment_scope())
+            .service(invitation_scope())
     })
-    .bind(("127.0.0.1", 4000))?
+    .bind(listen_address)?
     .run()
     .await
 }
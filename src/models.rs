@@ -1,4 +1,12 @@
-use crate::schema::{inventory, products, roles, session, stores, user_stores, users};
+use crate::money::{Currency, Money, MoneyColumns};
+use crate::schema::{
+    audit_events, cart_items, carts, categories, collection_products, collection_rules,
+    collections, order_items, orders, payment_events, payments, permissions, product_categories,
+    product_option_values, product_options, product_tags, product_variants, products,
+    refresh_tokens, role_permissions, roles, sale_items, sale_refund_items, sale_refunds, sales,
+    session, stock_locations, stock_movements, stock_reservations, store_invitations,
+    store_settings, store_stage_transitions, stores, user_stores, users, variant_option_values,
+};
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 
@@ -15,6 +23,32 @@ pub struct NewRole<'a> {
     pub name: &'a str,
 }
 
+#[derive(Debug, Serialize, Deserialize, Queryable)]
+pub struct Permission {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = permissions)]
+pub struct NewPermission<'a> {
+    pub id: &'a str,
+    pub name: &'a str,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable)]
+pub struct RolePermission {
+    pub role_id: String,
+    pub permission_id: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = role_permissions)]
+pub struct NewRolePermission<'a> {
+    pub role_id: &'a str,
+    pub permission_id: &'a str,
+}
+
 #[derive(Debug, Serialize, Deserialize, Queryable)]
 pub struct User {
     pub id: String,
@@ -39,6 +73,9 @@ pub struct Session {
     pub role_id: String,
     pub access_token: String,
     pub expires_at: chrono::NaiveDateTime,
+    pub created_at: chrono::NaiveDateTime,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
 }
 
 #[derive(Insertable)]
@@ -49,6 +86,25 @@ pub struct NewSession<'a> {
     pub role_id: &'a str,
     pub access_token: &'a str,
     pub expires_at: chrono::NaiveDateTime,
+    pub user_agent: Option<&'a str>,
+    pub ip: Option<&'a str>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable)]
+pub struct RefreshToken {
+    pub id: String,
+    pub session_id: String,
+    pub token_hash: String,
+    pub used_at: Option<chrono::NaiveDateTime>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = refresh_tokens)]
+pub struct NewRefreshToken<'a> {
+    pub id: &'a str,
+    pub session_id: &'a str,
+    pub token_hash: &'a str,
 }
 
 #[derive(Debug, Serialize, Deserialize, Queryable)]
@@ -56,6 +112,7 @@ pub struct Store {
     pub id: String,
     pub name: String,
     pub stage: String,
+    pub created_at: chrono::NaiveDateTime,
 }
 
 #[derive(Insertable)]
@@ -66,10 +123,51 @@ pub struct NewStore<'a> {
     pub stage: &'a str,
 }
 
+#[derive(Debug, Serialize, Deserialize, Queryable)]
+pub struct StoreSettings {
+    pub store_id: String,
+    pub contact_email: Option<String>,
+    pub timezone: String,
+    /// Default for new products.
+    #[diesel(deserialize_as = String)]
+    pub currency: Currency,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = store_settings)]
+pub struct NewStoreSettings<'a> {
+    pub store_id: &'a str,
+    pub contact_email: Option<&'a str>,
+    pub timezone: &'a str,
+    pub currency: &'a str,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = store_stage_transitions)]
+pub struct StoreStageTransition {
+    pub id: String,
+    pub store_id: String,
+    pub from_stage: Option<String>,
+    pub to_stage: String,
+    pub user_id: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = store_stage_transitions)]
+pub struct NewStoreStageTransition<'a> {
+    pub id: &'a str,
+    pub store_id: &'a str,
+    pub from_stage: Option<&'a str>,
+    pub to_stage: &'a str,
+    pub user_id: Option<&'a str>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Queryable)]
 pub struct UserStore {
     pub user_id: String,
     pub store_id: String,
+    pub role: String,
 }
 
 #[derive(Insertable)]
@@ -77,15 +175,50 @@ pub struct UserStore {
 pub struct NewUserStore<'a> {
     pub user_id: &'a str,
     pub store_id: &'a str,
+    pub role: &'a str,
 }
 
-#[derive(Debug, Serialize, Deserialize, Queryable)]
+/// Leaves out `token_hash`, which is only ever matched against.
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = store_invitations)]
+pub struct StoreInvitation {
+    pub id: String,
+    pub store_id: String,
+    pub email: String,
+    pub role: String,
+    pub status: String,
+    pub invited_by: Option<String>,
+    pub expires_at: chrono::NaiveDateTime,
+    pub created_at: chrono::NaiveDateTime,
+    pub responded_at: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = store_invitations)]
+pub struct NewStoreInvitation<'a> {
+    pub id: &'a str,
+    pub store_id: &'a str,
+    pub email: &'a str,
+    pub role: &'a str,
+    pub token_hash: &'a str,
+    pub invited_by: Option<&'a str>,
+    pub expires_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = products)]
 pub struct Product {
     pub id: String,
     pub title: String,
-    pub description: String,
-    pub price: f64,
+    pub description: Option<String>,
+    #[diesel(
+        select_expression = (products::price, products::currency),
+        select_expression_type = (products::price, products::currency),
+        deserialize_as = MoneyColumns
+    )]
+    pub price: Money,
     pub quantity: i32,
+    pub store_id: String,
 }
 
 #[derive(Insertable)]
@@ -93,20 +226,535 @@ pub struct Product {
 pub struct NewProduct<'a> {
     pub id: &'a str,
     pub title: &'a str,
-    pub description: &'a str,
-    pub price: &'a f64,
+    pub description: Option<&'a str>,
+    pub price: &'a i64,
+    pub currency: &'a str,
     pub quantity: &'a i32,
+    pub store_id: &'a str,
 }
 
-#[derive(Debug, Serialize, Deserialize, Queryable)]
-pub struct Inventory {
-    pub user_id: String,
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = product_options)]
+pub struct ProductOption {
+    pub id: String,
+    pub product_id: String,
+    pub name: String,
+    pub position: i32,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = product_options)]
+pub struct NewProductOption<'a> {
+    pub id: &'a str,
+    pub product_id: &'a str,
+    pub name: &'a str,
+    pub position: &'a i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = product_option_values)]
+pub struct ProductOptionValue {
+    pub id: String,
+    pub option_id: String,
+    pub value: String,
+    pub position: i32,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = product_option_values)]
+pub struct NewProductOptionValue<'a> {
+    pub id: &'a str,
+    pub option_id: &'a str,
+    pub value: &'a str,
+    pub position: &'a i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = product_variants)]
+pub struct ProductVariant {
+    pub id: String,
+    pub product_id: String,
+    pub store_id: String,
+    pub sku: String,
+    /// Minor units of the product's currency; `None` sells at the product
+    /// price.
+    pub price: Option<i64>,
+    pub barcode: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = product_variants)]
+pub struct NewProductVariant<'a> {
+    pub id: &'a str,
+    pub product_id: &'a str,
+    pub store_id: &'a str,
+    pub sku: &'a str,
+    pub price: Option<&'a i64>,
+    pub barcode: Option<&'a str>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = variant_option_values)]
+pub struct NewVariantOptionValue<'a> {
+    pub variant_id: &'a str,
+    pub option_value_id: &'a str,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = categories)]
+pub struct Category {
+    pub id: String,
+    pub store_id: String,
+    pub parent_id: Option<String>,
+    pub name: String,
+    pub slug: String,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = categories)]
+pub struct NewCategory<'a> {
+    pub id: &'a str,
+    pub store_id: &'a str,
+    pub parent_id: Option<&'a str>,
+    pub name: &'a str,
+    pub slug: &'a str,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = product_categories)]
+pub struct NewProductCategory<'a> {
+    pub product_id: &'a str,
+    pub category_id: &'a str,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = product_tags)]
+pub struct NewProductTag<'a> {
+    pub product_id: &'a str,
+    pub tag: &'a str,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = collections)]
+pub struct Collection {
+    pub id: String,
+    pub store_id: String,
+    pub name: String,
+    pub slug: String,
+    pub kind: String,
+    pub match_all: bool,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = collections)]
+pub struct NewCollection<'a> {
+    pub id: &'a str,
+    pub store_id: &'a str,
+    pub name: &'a str,
+    pub slug: &'a str,
+    pub kind: &'a str,
+    pub match_all: &'a bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = collection_rules)]
+pub struct CollectionRule {
+    pub id: String,
+    pub collection_id: String,
+    pub field: String,
+    pub operator: String,
+    pub value: String,
+    pub position: i32,
+    /// The currency of a `price` rule's amount.
+    pub currency: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = collection_rules)]
+pub struct NewCollectionRule<'a> {
+    pub id: &'a str,
+    pub collection_id: &'a str,
+    pub field: &'a str,
+    pub operator: &'a str,
+    pub value: &'a str,
+    pub position: &'a i32,
+    pub currency: Option<&'a str>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = collection_products)]
+pub struct NewCollectionProduct<'a> {
+    pub collection_id: &'a str,
+    pub product_id: &'a str,
+    pub position: &'a i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = stock_locations)]
+pub struct StockLocation {
+    pub id: String,
+    pub store_id: String,
+    pub name: String,
+    pub is_default: bool,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = stock_locations)]
+pub struct NewStockLocation<'a> {
+    pub id: &'a str,
+    pub store_id: &'a str,
+    pub name: &'a str,
+    pub is_default: &'a bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = stock_movements)]
+pub struct StockMovement {
+    pub id: String,
+    pub store_id: String,
+    pub product_id: String,
+    pub location_id: String,
+    pub kind: String,
+    pub quantity: i32,
+    pub transfer_id: Option<String>,
+    pub reference: Option<String>,
+    pub note: Option<String>,
+    pub user_id: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = stock_movements)]
+pub struct NewStockMovement<'a> {
+    pub id: &'a str,
+    pub store_id: &'a str,
+    pub product_id: &'a str,
+    pub location_id: &'a str,
+    pub kind: &'a str,
+    pub quantity: &'a i32,
+    pub transfer_id: Option<&'a str>,
+    pub reference: Option<&'a str>,
+    pub note: Option<&'a str>,
+    pub user_id: Option<&'a str>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = stock_reservations)]
+pub struct StockReservation {
+    pub id: String,
+    pub store_id: String,
     pub product_id: String,
+    pub user_id: String,
+    pub quantity: i32,
+    pub status: String,
+    pub reference: Option<String>,
+    pub expires_at: chrono::NaiveDateTime,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
 }
 
 #[derive(Insertable)]
-#[diesel(table_name = inventory)]
-pub struct NewInventory<'a> {
+#[diesel(table_name = stock_reservations)]
+pub struct NewStockReservation<'a> {
+    pub id: &'a str,
+    pub store_id: &'a str,
+    pub product_id: &'a str,
     pub user_id: &'a str,
+    pub quantity: &'a i32,
+    pub expires_at: chrono::NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable)]
+pub struct Cart {
+    pub id: String,
+    pub store_id: String,
+    pub user_id: Option<String>,
+    pub token_hash: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = carts)]
+pub struct NewCart<'a> {
+    pub id: &'a str,
+    pub store_id: &'a str,
+    pub user_id: Option<&'a str>,
+    pub token_hash: Option<&'a str>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = cart_items)]
+pub struct NewCartItem<'a> {
+    pub cart_id: &'a str,
     pub product_id: &'a str,
+    pub quantity: &'a i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = orders)]
+pub struct Order {
+    pub id: String,
+    pub store_id: String,
+    pub user_id: String,
+    pub status: String,
+    #[diesel(
+        select_expression = (orders::total, orders::currency),
+        select_expression_type = (orders::total, orders::currency),
+        deserialize_as = MoneyColumns
+    )]
+    pub total: Money,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = orders)]
+pub struct NewOrder<'a> {
+    pub id: &'a str,
+    pub store_id: &'a str,
+    pub user_id: &'a str,
+    pub total: &'a i64,
+    pub currency: &'a str,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = order_items)]
+pub struct OrderItem {
+    pub id: String,
+    pub order_id: String,
+    pub product_id: Option<String>,
+    pub title: String,
+    #[diesel(
+        select_expression = (order_items::unit_price, order_items::currency),
+        select_expression_type = (order_items::unit_price, order_items::currency),
+        deserialize_as = MoneyColumns
+    )]
+    pub unit_price: Money,
+    pub quantity: i32,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = order_items)]
+pub struct NewOrderItem<'a> {
+    pub id: &'a str,
+    pub order_id: &'a str,
+    pub product_id: Option<&'a str>,
+    pub title: &'a str,
+    pub unit_price: &'a i64,
+    pub currency: &'a str,
+    pub quantity: &'a i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = payments)]
+pub struct Payment {
+    pub id: String,
+    pub store_id: String,
+    pub order_id: Option<String>,
+    pub user_id: String,
+    pub provider: String,
+    pub provider_reference: Option<String>,
+    #[diesel(
+        select_expression = (payments::amount, payments::currency),
+        select_expression_type = (payments::amount, payments::currency),
+        deserialize_as = MoneyColumns
+    )]
+    pub amount: Money,
+    #[diesel(
+        select_expression = (payments::refunded_amount, payments::currency),
+        select_expression_type = (payments::refunded_amount, payments::currency),
+        deserialize_as = MoneyColumns
+    )]
+    pub refunded_amount: Money,
+    pub status: String,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+    /// The provider call in flight for this payment, if any.
+    pub pending_action: Option<String>,
+    pub pending_since: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = payments)]
+pub struct NewPayment<'a> {
+    pub id: &'a str,
+    pub store_id: &'a str,
+    pub order_id: Option<&'a str>,
+    pub user_id: &'a str,
+    pub provider: &'a str,
+    pub amount: &'a i64,
+    pub currency: &'a str,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = payment_events)]
+pub struct PaymentEvent {
+    pub id: String,
+    pub payment_id: String,
+    pub action: String,
+    pub status: String,
+    /// Minor units of the payment's currency.
+    pub amount: Option<i64>,
+    pub message: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = payment_events)]
+pub struct NewPaymentEvent<'a> {
+    pub id: &'a str,
+    pub payment_id: &'a str,
+    pub action: &'a str,
+    pub status: &'a str,
+    pub amount: Option<&'a i64>,
+    pub message: Option<&'a str>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = sales)]
+pub struct Sale {
+    pub id: String,
+    pub store_id: String,
+    pub user_id: String,
+    pub note: Option<String>,
+    #[diesel(
+        select_expression = (sales::total, sales::currency),
+        select_expression_type = (sales::total, sales::currency),
+        deserialize_as = MoneyColumns
+    )]
+    pub total: Money,
+    #[diesel(
+        select_expression = (sales::refunded_total, sales::currency),
+        select_expression_type = (sales::refunded_total, sales::currency),
+        deserialize_as = MoneyColumns
+    )]
+    pub refunded_total: Money,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = sales)]
+pub struct NewSale<'a> {
+    pub id: &'a str,
+    pub store_id: &'a str,
+    pub user_id: &'a str,
+    pub note: Option<&'a str>,
+    pub total: &'a i64,
+    pub currency: &'a str,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = sale_items)]
+pub struct SaleItem {
+    pub id: String,
+    pub sale_id: String,
+    pub product_id: Option<String>,
+    pub title: String,
+    #[diesel(
+        select_expression = (sale_items::unit_price, sale_items::currency),
+        select_expression_type = (sale_items::unit_price, sale_items::currency),
+        deserialize_as = MoneyColumns
+    )]
+    pub unit_price: Money,
+    pub quantity: i32,
+    pub refunded_quantity: i32,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = sale_items)]
+pub struct NewSaleItem<'a> {
+    pub id: &'a str,
+    pub sale_id: &'a str,
+    pub product_id: Option<&'a str>,
+    pub title: &'a str,
+    pub unit_price: &'a i64,
+    pub currency: &'a str,
+    pub quantity: &'a i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = sale_refunds)]
+pub struct SaleRefund {
+    pub id: String,
+    pub sale_id: String,
+    pub user_id: String,
+    pub reason: Option<String>,
+    #[diesel(
+        select_expression = (sale_refunds::amount, sale_refunds::currency),
+        select_expression_type = (sale_refunds::amount, sale_refunds::currency),
+        deserialize_as = MoneyColumns
+    )]
+    pub amount: Money,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = sale_refunds)]
+pub struct NewSaleRefund<'a> {
+    pub id: &'a str,
+    pub sale_id: &'a str,
+    pub user_id: &'a str,
+    pub reason: Option<&'a str>,
+    pub amount: &'a i64,
+    pub currency: &'a str,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = sale_refund_items)]
+pub struct SaleRefundItem {
+    pub refund_id: String,
+    pub sale_item_id: String,
+    pub quantity: i32,
+    #[diesel(
+        select_expression = (sale_refund_items::amount, sale_refund_items::currency),
+        select_expression_type = (sale_refund_items::amount, sale_refund_items::currency),
+        deserialize_as = MoneyColumns
+    )]
+    pub amount: Money,
+    pub restocked: bool,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = sale_refund_items)]
+pub struct NewSaleRefundItem<'a> {
+    pub refund_id: &'a str,
+    pub sale_item_id: &'a str,
+    pub quantity: &'a i32,
+    pub amount: &'a i64,
+    pub currency: &'a str,
+    pub restocked: &'a bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable)]
+#[diesel(table_name = audit_events)]
+pub struct AuditEvent {
+    pub id: String,
+    pub actor_id: Option<String>,
+    pub session_id: Option<String>,
+    pub store_id: Option<String>,
+    pub action: String,
+    pub target_id: Option<String>,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+    pub ip: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = audit_events)]
+pub struct NewAuditEvent<'a> {
+    pub id: &'a str,
+    pub actor_id: Option<&'a str>,
+    pub session_id: Option<&'a str>,
+    pub store_id: Option<&'a str>,
+    pub action: &'a str,
+    pub target_id: Option<&'a str>,
+    pub before: Option<&'a serde_json::Value>,
+    pub after: Option<&'a serde_json::Value>,
+    pub ip: Option<&'a str>,
 }
@@ -0,0 +1,598 @@
+use crate::errors::ApiError;
+use serde::{de, ser::SerializeStruct, Deserialize, Deserializer, Serialize, Serializer};
+use std::{fmt, str::FromStr};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MoneyError {
+    InvalidCurrency(String),
+    InvalidAmount(String),
+    CurrencyMismatch(Currency, Currency),
+    Overflow,
+}
+
+impl fmt::Display for MoneyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoneyError::InvalidCurrency(code) => {
+                write!(f, "\"{code}\" is not an ISO 4217 currency code")
+            }
+            MoneyError::InvalidAmount(reason) => write!(f, "Invalid amount: {reason}"),
+            MoneyError::CurrencyMismatch(a, b) => write!(f, "Cannot combine {a} and {b} amounts"),
+            MoneyError::Overflow => write!(f, "Amount is too large"),
+        }
+    }
+}
+
+impl std::error::Error for MoneyError {}
+
+impl From<MoneyError> for ApiError {
+    fn from(err: MoneyError) -> Self {
+        ApiError::Validation(err.to_string())
+    }
+}
+
+/// Three letter ISO 4217 code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Currency([u8; 3]);
+
+impl Currency {
+    pub const USD: Currency = Currency(*b"USD");
+
+    pub fn as_str(&self) -> &str {
+        // Only ever built from ASCII uppercase letters.
+        std::str::from_utf8(&self.0).unwrap_or("???")
+    }
+
+    /// Digits after the decimal point in the currency's minor unit.
+    pub fn exponent(&self) -> u32 {
+        // Every `Currency` is checked against the table when it is built.
+        find_currency(self.as_str()).unwrap_or(2)
+    }
+}
+
+/// ISO 4217 codes in use, with the digits in each one's minor unit, sorted by
+/// code. Precious metals and the testing codes have no minor unit and are left
+/// out.
+const CURRENCIES: &[(&str, u32)] = &[
+    ("AED", 2),
+    ("AFN", 2),
+    ("ALL", 2),
+    ("AMD", 2),
+    ("ANG", 2),
+    ("AOA", 2),
+    ("ARS", 2),
+    ("AUD", 2),
+    ("AWG", 2),
+    ("AZN", 2),
+    ("BAM", 2),
+    ("BBD", 2),
+    ("BDT", 2),
+    ("BGN", 2),
+    ("BHD", 3),
+    ("BIF", 0),
+    ("BMD", 2),
+    ("BND", 2),
+    ("BOB", 2),
+    ("BOV", 2),
+    ("BRL", 2),
+    ("BSD", 2),
+    ("BTN", 2),
+    ("BWP", 2),
+    ("BYN", 2),
+    ("BZD", 2),
+    ("CAD", 2),
+    ("CDF", 2),
+    ("CHE", 2),
+    ("CHF", 2),
+    ("CHW", 2),
+    ("CLF", 4),
+    ("CLP", 0),
+    ("CNY", 2),
+    ("COP", 2),
+    ("COU", 2),
+    ("CRC", 2),
+    ("CUP", 2),
+    ("CVE", 2),
+    ("CZK", 2),
+    ("DJF", 0),
+    ("DKK", 2),
+    ("DOP", 2),
+    ("DZD", 2),
+    ("EGP", 2),
+    ("ERN", 2),
+    ("ETB", 2),
+    ("EUR", 2),
+    ("FJD", 2),
+    ("FKP", 2),
+    ("GBP", 2),
+    ("GEL", 2),
+    ("GHS", 2),
+    ("GIP", 2),
+    ("GMD", 2),
+    ("GNF", 0),
+    ("GTQ", 2),
+    ("GYD", 2),
+    ("HKD", 2),
+    ("HNL", 2),
+    ("HTG", 2),
+    ("HUF", 2),
+    ("IDR", 2),
+    ("ILS", 2),
+    ("INR", 2),
+    ("IQD", 3),
+    ("IRR", 2),
+    ("ISK", 0),
+    ("JMD", 2),
+    ("JOD", 3),
+    ("JPY", 0),
+    ("KES", 2),
+    ("KGS", 2),
+    ("KHR", 2),
+    ("KMF", 0),
+    ("KPW", 2),
+    ("KRW", 0),
+    ("KWD", 3),
+    ("KYD", 2),
+    ("KZT", 2),
+    ("LAK", 2),
+    ("LBP", 2),
+    ("LKR", 2),
+    ("LRD", 2),
+    ("LSL", 2),
+    ("LYD", 3),
+    ("MAD", 2),
+    ("MDL", 2),
+    ("MGA", 2),
+    ("MKD", 2),
+    ("MMK", 2),
+    ("MNT", 2),
+    ("MOP", 2),
+    ("MRU", 2),
+    ("MUR", 2),
+    ("MVR", 2),
+    ("MWK", 2),
+    ("MXN", 2),
+    ("MXV", 2),
+    ("MYR", 2),
+    ("MZN", 2),
+    ("NAD", 2),
+    ("NGN", 2),
+    ("NIO", 2),
+    ("NOK", 2),
+    ("NPR", 2),
+    ("NZD", 2),
+    ("OMR", 3),
+    ("PAB", 2),
+    ("PEN", 2),
+    ("PGK", 2),
+    ("PHP", 2),
+    ("PKR", 2),
+    ("PLN", 2),
+    ("PYG", 0),
+    ("QAR", 2),
+    ("RON", 2),
+    ("RSD", 2),
+    ("RUB", 2),
+    ("RWF", 0),
+    ("SAR", 2),
+    ("SBD", 2),
+    ("SCR", 2),
+    ("SDG", 2),
+    ("SEK", 2),
+    ("SGD", 2),
+    ("SHP", 2),
+    ("SLE", 2),
+    ("SOS", 2),
+    ("SRD", 2),
+    ("SSP", 2),
+    ("STN", 2),
+    ("SVC", 2),
+    ("SYP", 2),
+    ("SZL", 2),
+    ("THB", 2),
+    ("TJS", 2),
+    ("TMT", 2),
+    ("TND", 3),
+    ("TOP", 2),
+    ("TRY", 2),
+    ("TTD", 2),
+    ("TWD", 2),
+    ("TZS", 2),
+    ("UAH", 2),
+    ("UGX", 0),
+    ("USD", 2),
+    ("USN", 2),
+    ("UYI", 0),
+    ("UYU", 2),
+    ("UYW", 4),
+    ("UZS", 2),
+    ("VED", 2),
+    ("VES", 2),
+    ("VND", 0),
+    ("VUV", 0),
+    ("WST", 2),
+    ("XAF", 0),
+    ("XCD", 2),
+    ("XCG", 2),
+    ("XOF", 0),
+    ("XPF", 0),
+    ("YER", 2),
+    ("ZAR", 2),
+    ("ZMW", 2),
+    ("ZWG", 2),
+];
+
+fn find_currency(code: &str) -> Option<u32> {
+    CURRENCIES
+        .binary_search_by(|(known, _)| (*known).cmp(code))
+        .ok()
+        .map(|index| CURRENCIES[index].1)
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Currency {
+    type Err = MoneyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.as_bytes() {
+            &[a, b, c] if find_currency(s).is_some() => Ok(Currency([a, b, c])),
+            _ => Err(MoneyError::InvalidCurrency(s.to_string())),
+        }
+    }
+}
+
+impl TryFrom<String> for Currency {
+    type Error = MoneyError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Serialize for Currency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Currency {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let code = String::deserialize(deserializer)?;
+        code.parse().map_err(de::Error::custom)
+    }
+}
+
+/// An exact amount in the minor unit (cents, pence, ...) of its currency.
+/// Serialized as `{"amount": "12.50", "currency": "EUR"}` so clients never
+/// see a float.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Money {
+    minor: i64,
+    currency: Currency,
+}
+
+impl Money {
+    pub fn from_minor(minor: i64, currency: Currency) -> Money {
+        Money { minor, currency }
+    }
+
+    pub fn zero(currency: Currency) -> Money {
+        Money::from_minor(0, currency)
+    }
+
+    /// Parses a decimal string such as `"12.5"` with no more fraction digits
+    /// than the currency allows.
+    pub fn parse(amount: &str, currency: Currency) -> Result<Money, MoneyError> {
+        let invalid = || MoneyError::InvalidAmount(format!("\"{amount}\" is not a decimal number"));
+
+        let (negative, digits) = match amount.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, amount),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        if whole.is_empty()
+            || !whole.bytes().all(|byte| byte.is_ascii_digit())
+            || !fraction.bytes().all(|byte| byte.is_ascii_digit())
+            || (digits.contains('.') && fraction.is_empty())
+        {
+            return Err(invalid());
+        }
+
+        let exponent = currency.exponent();
+        if fraction.len() > exponent as usize {
+            return Err(MoneyError::InvalidAmount(format!(
+                "{currency} amounts have at most {exponent} decimal places"
+            )));
+        }
+
+        let scale = 10i64.pow(exponent);
+        let whole = whole.parse::<i64>().map_err(|_| MoneyError::Overflow)?;
+        let fraction = if fraction.is_empty() {
+            0
+        } else {
+            fraction.parse::<i64>().map_err(|_| invalid())?
+                * 10i64.pow(exponent - fraction.len() as u32)
+        };
+        let minor = whole
+            .checked_mul(scale)
+            .and_then(|minor| minor.checked_add(fraction))
+            .ok_or(MoneyError::Overflow)?;
+
+        Ok(Money::from_minor(
+            if negative { -minor } else { minor },
+            currency,
+        ))
+    }
+
+    pub fn minor(&self) -> i64 {
+        self.minor
+    }
+
+    pub fn currency(&self) -> Currency {
+        self.currency
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.minor < 0
+    }
+
+    pub fn checked_add(self, other: Money) -> Result<Money, MoneyError> {
+        self.same_currency(other)?;
+        let minor = self
+            .minor
+            .checked_add(other.minor)
+            .ok_or(MoneyError::Overflow)?;
+        Ok(Money::from_minor(minor, self.currency))
+    }
+
+    pub fn checked_sub(self, other: Money) -> Result<Money, MoneyError> {
+        self.same_currency(other)?;
+        let minor = self
+            .minor
+            .checked_sub(other.minor)
+            .ok_or(MoneyError::Overflow)?;
+        Ok(Money::from_minor(minor, self.currency))
+    }
+
+    pub fn checked_mul(self, quantity: i32) -> Result<Money, MoneyError> {
+        let minor = self
+            .minor
+            .checked_mul(i64::from(quantity))
+            .ok_or(MoneyError::Overflow)?;
+        Ok(Money::from_minor(minor, self.currency))
+    }
+
+    /// Sums `amounts`, which must all be in `currency`.
+    pub fn sum(
+        currency: Currency,
+        amounts: impl IntoIterator<Item = Money>,
+    ) -> Result<Money, MoneyError> {
+        amounts
+            .into_iter()
+            .try_fold(Money::zero(currency), Money::checked_add)
+    }
+
+    /// The amount alone as a decimal string, e.g. `"12.50"`.
+    pub fn amount(&self) -> String {
+        let exponent = self.currency.exponent();
+        let sign = if self.minor < 0 { "-" } else { "" };
+        let minor = self.minor.unsigned_abs();
+        if exponent == 0 {
+            return format!("{sign}{minor}");
+        }
+        let scale = 10u64.pow(exponent);
+        format!(
+            "{sign}{}.{:0width$}",
+            minor / scale,
+            minor % scale,
+            width = exponent as usize
+        )
+    }
+
+    fn same_currency(&self, other: Money) -> Result<(), MoneyError> {
+        if self.currency != other.currency {
+            return Err(MoneyError::CurrencyMismatch(self.currency, other.currency));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.amount(), self.currency)
+    }
+}
+
+/// Rows store an amount column next to a currency column; models load both
+/// into a single `Money` field through `deserialize_as = MoneyColumns`.
+pub type MoneyColumns = (i64, String);
+
+impl TryFrom<MoneyColumns> for Money {
+    type Error = MoneyError;
+
+    fn try_from((minor, currency): MoneyColumns) -> Result<Self, Self::Error> {
+        Ok(Money::from_minor(minor, currency.parse()?))
+    }
+}
+
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut money = serializer.serialize_struct("Money", 2)?;
+        money.serialize_field("amount", &self.amount())?;
+        money.serialize_field("currency", &self.currency)?;
+        money.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Raw {
+            amount: String,
+            currency: Currency,
+        }
+
+        let raw = Raw::deserialize(deserializer)?;
+        Money::parse(&raw.amount, raw.currency).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn currency(code: &str) -> Currency {
+        code.parse().unwrap()
+    }
+
+    fn usd(minor: i64) -> Money {
+        Money::from_minor(minor, Currency::USD)
+    }
+
+    #[test]
+    fn parses_to_the_currency_minor_unit() {
+        let cases = [
+            ("JPY", "1200", 1200, "1200"),
+            ("USD", "12", 1200, "12.00"),
+            ("USD", "12.5", 1250, "12.50"),
+            ("USD", "0.05", 5, "0.05"),
+            ("EUR", "12.34", 1234, "12.34"),
+            ("KWD", "1.234", 1234, "1.234"),
+            ("KWD", "1.2", 1200, "1.200"),
+            ("CLF", "1.2345", 12345, "1.2345"),
+            ("USD", "-3.10", -310, "-3.10"),
+            ("USD", "-0.01", -1, "-0.01"),
+            ("USD", "007.5", 750, "7.50"),
+        ];
+
+        for (code, amount, minor, formatted) in cases {
+            let money = Money::parse(amount, currency(code)).unwrap();
+            assert_eq!(money.minor(), minor, "{amount} {code}");
+            assert_eq!(money.amount(), formatted, "{amount} {code}");
+            assert_eq!(Money::parse(&money.amount(), currency(code)), Ok(money));
+        }
+    }
+
+    #[test]
+    fn rejects_more_decimals_than_the_currency_has() {
+        let cases = [
+            ("JPY", "1.0", "JPY amounts have at most 0 decimal places"),
+            ("USD", "1.005", "USD amounts have at most 2 decimal places"),
+            ("KWD", "1.0005", "KWD amounts have at most 3 decimal places"),
+            (
+                "CLF",
+                "1.00005",
+                "CLF amounts have at most 4 decimal places",
+            ),
+        ];
+
+        for (code, amount, message) in cases {
+            let err = Money::parse(amount, currency(code)).unwrap_err();
+            assert_eq!(err, MoneyError::InvalidAmount(message.to_string()));
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_amounts() {
+        for amount in [
+            "", " 1.00", "1.00 ", "1 000", "+1", "--1", "- 1", "1.", ".5", "-", "1.2.3", "1,00",
+            "1e3", "0x10", "1.-5",
+        ] {
+            let err = Money::parse(amount, Currency::USD).unwrap_err();
+            assert_eq!(
+                err,
+                MoneyError::InvalidAmount(format!("\"{amount}\" is not a decimal number"))
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_amounts_that_overflow() {
+        let max = i64::MAX.to_string();
+        assert_eq!(
+            Money::parse(&max, currency("JPY")).map(|money| money.minor()),
+            Ok(i64::MAX)
+        );
+
+        for amount in [max.as_str(), "92233720368547758.08", "99999999999999999999"] {
+            assert_eq!(
+                Money::parse(amount, Currency::USD),
+                Err(MoneyError::Overflow),
+                "{amount}"
+            );
+        }
+    }
+
+    #[test]
+    fn parses_currency_codes() {
+        assert_eq!(currency("EUR").as_str(), "EUR");
+        for code in ["eur", "EU", "EURO", "E1R", "", "ABC", "ZZZ", "XAU", "XXX"] {
+            assert_eq!(
+                code.parse::<Currency>(),
+                Err(MoneyError::InvalidCurrency(code.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn currency_table_is_sorted() {
+        assert!(CURRENCIES.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert_eq!(currency("USD"), Currency::USD);
+    }
+
+    #[test]
+    fn arithmetic_stays_in_one_currency() {
+        let eur = Money::from_minor(100, currency("EUR"));
+
+        assert_eq!(usd(150).checked_add(usd(75)), Ok(usd(225)));
+        assert_eq!(usd(150).checked_sub(usd(200)), Ok(usd(-50)));
+        assert_eq!(usd(150).checked_mul(3), Ok(usd(450)));
+        assert_eq!(
+            usd(150).checked_add(eur),
+            Err(MoneyError::CurrencyMismatch(Currency::USD, currency("EUR")))
+        );
+        assert_eq!(
+            usd(150).checked_sub(eur),
+            Err(MoneyError::CurrencyMismatch(Currency::USD, currency("EUR")))
+        );
+    }
+
+    #[test]
+    fn arithmetic_reports_overflow() {
+        assert_eq!(usd(i64::MAX).checked_add(usd(1)), Err(MoneyError::Overflow));
+        assert_eq!(usd(i64::MIN).checked_sub(usd(1)), Err(MoneyError::Overflow));
+        assert_eq!(usd(i64::MAX).checked_mul(2), Err(MoneyError::Overflow));
+        assert_eq!(
+            Money::sum(Currency::USD, [usd(i64::MAX), usd(1)]),
+            Err(MoneyError::Overflow)
+        );
+    }
+
+    #[test]
+    fn sums_in_the_given_currency() {
+        assert_eq!(
+            Money::sum(Currency::USD, []),
+            Ok(Money::zero(Currency::USD))
+        );
+        assert_eq!(
+            Money::sum(Currency::USD, [usd(1), usd(2), usd(3)]),
+            Ok(usd(6))
+        );
+        assert!(Money::sum(currency("EUR"), [usd(1)]).is_err());
+    }
+
+    #[test]
+    fn serializes_as_a_decimal_string() {
+        let money = Money::from_minor(-1234, currency("KWD"));
+        let json = serde_json::to_string(&money).unwrap();
+
+        assert_eq!(json, r#"{"amount":"-1.234","currency":"KWD"}"#);
+        assert_eq!(serde_json::from_str::<Money>(&json).unwrap(), money);
+        assert!(serde_json::from_str::<Money>(r#"{"amount":"1.5","currency":"JPY"}"#).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use crate::errors::ApiError;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// Lifecycle of an order. Only the moves listed in `OrderStatus::next` are
+/// allowed; cancelled and refunded orders are final.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderStatus {
+    Pending,
+    Paid,
+    Fulfilled,
+    Completed,
+    Cancelled,
+    Refunded,
+}
+
+impl OrderStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OrderStatus::Pending => "pending",
+            OrderStatus::Paid => "paid",
+            OrderStatus::Fulfilled => "fulfilled",
+            OrderStatus::Completed => "completed",
+            OrderStatus::Cancelled => "cancelled",
+            OrderStatus::Refunded => "refunded",
+        }
+    }
+
+    pub fn next(self) -> &'static [OrderStatus] {
+        match self {
+            OrderStatus::Pending => &[OrderStatus::Paid, OrderStatus::Cancelled],
+            OrderStatus::Paid => &[OrderStatus::Fulfilled, OrderStatus::Refunded],
+            OrderStatus::Fulfilled => &[OrderStatus::Completed, OrderStatus::Refunded],
+            OrderStatus::Completed => &[OrderStatus::Refunded],
+            OrderStatus::Cancelled | OrderStatus::Refunded => &[],
+        }
+    }
+
+    pub fn transition(self, to: OrderStatus) -> Result<OrderStatus, ApiError> {
+        if self.next().contains(&to) {
+            Ok(to)
+        } else {
+            Err(ApiError::InvalidTransition(format!(
+                "Order cannot move from {self} to {to}"
+            )))
+        }
+    }
+}
+
+impl fmt::Display for OrderStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for OrderStatus {
+    type Err = ApiError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "pending" => Ok(OrderStatus::Pending),
+            "paid" => Ok(OrderStatus::Paid),
+            "fulfilled" => Ok(OrderStatus::Fulfilled),
+            "completed" => Ok(OrderStatus::Completed),
+            "cancelled" => Ok(OrderStatus::Cancelled),
+            "refunded" => Ok(OrderStatus::Refunded),
+            _ => Err(ApiError::Internal(format!(
+                "Unknown order status \"{value}\""
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use OrderStatus::*;
+
+    const ALL: [OrderStatus; 6] = [Pending, Paid, Fulfilled, Completed, Cancelled, Refunded];
+
+    const ALLOWED: &[(OrderStatus, OrderStatus)] = &[
+        (Pending, Paid),
+        (Pending, Cancelled),
+        (Paid, Fulfilled),
+        (Paid, Refunded),
+        (Fulfilled, Completed),
+        (Fulfilled, Refunded),
+        (Completed, Refunded),
+    ];
+
+    #[test]
+    fn only_listed_moves_are_allowed() {
+        for from in ALL {
+            for to in ALL {
+                let result = from.transition(to);
+                if ALLOWED.contains(&(from, to)) {
+                    assert_eq!(result.ok(), Some(to), "{from} -> {to}");
+                } else {
+                    assert_eq!(
+                        result.unwrap_err().to_string(),
+                        format!("Order cannot move from {from} to {to}")
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cancelled_and_refunded_orders_are_final() {
+        for status in [Cancelled, Refunded] {
+            assert!(status.next().is_empty(), "{status}");
+        }
+        for status in ALL {
+            assert!(status.transition(status).is_err(), "{status}");
+        }
+    }
+
+    #[test]
+    fn statuses_round_trip_through_their_names() {
+        for status in ALL {
+            assert_eq!(status.as_str().parse::<OrderStatus>().ok(), Some(status));
+            assert_eq!(
+                serde_json::to_string(&status).unwrap(),
+                format!("\"{status}\"")
+            );
+        }
+        assert!("Paid".parse::<OrderStatus>().is_err());
+        assert!("shipped".parse::<OrderStatus>().is_err());
+    }
+}
@@ -0,0 +1,467 @@
+use crate::errors::ApiError;
+use diesel::{
+    dsl,
+    expression::{AsExpression, TypedExpressionType},
+    pg::Pg,
+    sql_types::{Bool, SqlType},
+    BoolExpressionMethods, BoxableExpression, ExpressionMethods,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const DEFAULT_LIMIT: i64 = 20;
+const MAX_LIMIT: i64 = 100;
+
+/// How timestamp sort values are written into and read back from cursors.
+pub const CURSOR_TIMESTAMP: &str = "%Y-%m-%dT%H:%M:%S%.f";
+
+/// A boolean SQL condition over the query source `QS`.
+pub type Condition<QS> = Box<dyn BoxableExpression<QS, Pg, SqlType = Bool>>;
+
+/// What a list endpoint lets clients sort and filter on.
+pub struct ListSpec {
+    pub sorts: &'static [&'static str],
+    pub filters: &'static [&'static str],
+    /// Used when the request has no `sort`.
+    pub default_sort: &'static str,
+}
+
+/// The raw query string of a list request: `limit`, `cursor`,
+/// `sort=name,-created` and any number of `filter[field]=value`.
+#[derive(Debug, Deserialize)]
+#[serde(transparent)]
+pub struct ListQuery(HashMap<String, String>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortKey {
+    pub field: &'static str,
+    pub descending: bool,
+}
+
+#[derive(Debug)]
+pub struct ListParams {
+    pub limit: i64,
+    /// Always ends with the tiebreaking `id` key, so the order is total.
+    pub sort: Vec<SortKey>,
+    pub filters: Vec<(&'static str, String)>,
+    /// Sort key values of the last row of the previous page, one per key.
+    pub after: Option<Vec<String>>,
+}
+
+/// One page of a list plus the cursor of the next one, absent on the last
+/// page.
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+/// What a cursor carries: the sort it was issued for and where the page
+/// ended. Clients only ever see it hex encoded.
+#[derive(Debug, Serialize, Deserialize)]
+struct CursorData {
+    sort: String,
+    after: Vec<String>,
+}
+
+impl ListQuery {
+    pub fn parse(&self, spec: &ListSpec) -> Result<ListParams, ApiError> {
+        let mut limit = DEFAULT_LIMIT;
+        let mut sort_text = spec.default_sort;
+        let mut cursor = None;
+        let mut filters = Vec::new();
+
+        for (key, value) in &self.0 {
+            match key.as_str() {
+                "limit" => {
+                    limit = value
+                        .parse()
+                        .ok()
+                        .filter(|limit| (1..=MAX_LIMIT).contains(limit))
+                        .ok_or_else(|| {
+                            ApiError::Validation(format!(
+                                "limit must be a number between 1 and {MAX_LIMIT}"
+                            ))
+                        })?;
+                }
+                "sort" => sort_text = value,
+                "cursor" => cursor = Some(value),
+                _ => {
+                    let field = key
+                        .strip_prefix("filter[")
+                        .and_then(|rest| rest.strip_suffix(']'))
+                        .ok_or_else(|| {
+                            ApiError::Validation(format!("Unknown query parameter \"{key}\""))
+                        })?;
+                    let field = spec
+                        .filters
+                        .iter()
+                        .find(|allowed| **allowed == field)
+                        .ok_or_else(|| {
+                            ApiError::Validation(format!(
+                                "Cannot filter on \"{field}\"; use one of {}",
+                                spec.filters.join(", ")
+                            ))
+                        })?;
+                    filters.push((*field, value.clone()));
+                }
+            }
+        }
+
+        let sort = parse_sort(sort_text, spec)?;
+        let after = cursor
+            .map(|cursor| decode_cursor(cursor, &sort))
+            .transpose()?;
+
+        Ok(ListParams {
+            limit,
+            sort,
+            filters,
+            after,
+        })
+    }
+}
+
+/// The "sorts after" and "sorts equal" conditions of one column for
+/// `ListParams::after_condition`.
+pub fn keyset_column<QS, C, V>(
+    column: C,
+    value: V,
+    descending: bool,
+) -> (Condition<QS>, Condition<QS>)
+where
+    C: ExpressionMethods + Copy,
+    C::SqlType: SqlType + TypedExpressionType,
+    V: AsExpression<C::SqlType> + Clone,
+    dsl::Gt<C, V>: BoxableExpression<QS, Pg, SqlType = Bool> + 'static,
+    dsl::Lt<C, V>: BoxableExpression<QS, Pg, SqlType = Bool> + 'static,
+    dsl::Eq<C, V>: BoxableExpression<QS, Pg, SqlType = Bool> + 'static,
+{
+    let beyond: Condition<QS> = if descending {
+        Box::new(column.lt(value.clone()))
+    } else {
+        Box::new(column.gt(value.clone()))
+    };
+    (beyond, Box::new(column.eq(value)))
+}
+
+fn parse_sort(text: &str, spec: &ListSpec) -> Result<Vec<SortKey>, ApiError> {
+    let mut sort = Vec::new();
+    for term in text.split(',').map(str::trim) {
+        let (name, descending) = match term.strip_prefix('-') {
+            Some(name) => (name, true),
+            None => (term, false),
+        };
+        let field = spec
+            .sorts
+            .iter()
+            .find(|allowed| **allowed == name)
+            .ok_or_else(|| {
+                ApiError::Validation(format!(
+                    "Cannot sort by \"{name}\"; use one of {}",
+                    spec.sorts.join(", ")
+                ))
+            })?;
+        if sort.iter().any(|key: &SortKey| key.field == *field) {
+            return Err(ApiError::Validation(format!(
+                "\"{name}\" appears twice in sort"
+            )));
+        }
+        sort.push(SortKey { field, descending });
+    }
+    if !sort.iter().any(|key| key.field == "id") {
+        sort.push(SortKey {
+            field: "id",
+            descending: false,
+        });
+    }
+    Ok(sort)
+}
+
+fn sort_signature(sort: &[SortKey]) -> String {
+    sort.iter()
+        .map(|key| {
+            if key.descending {
+                format!("-{}", key.field)
+            } else {
+                key.field.to_string()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+fn decode_cursor(cursor: &str, sort: &[SortKey]) -> Result<Vec<String>, ApiError> {
+    let invalid = || ApiError::Validation("cursor is invalid".to_string());
+
+    if !cursor.len().is_multiple_of(2) {
+        return Err(invalid());
+    }
+    let bytes = (0..cursor.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(cursor.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(invalid)?;
+    let data = serde_json::from_slice::<CursorData>(&bytes).map_err(|_| invalid())?;
+
+    if data.sort != sort_signature(sort) {
+        return Err(ApiError::Validation(
+            "cursor was issued for a different sort".to_string(),
+        ));
+    }
+    if data.after.len() != sort.len() {
+        return Err(invalid());
+    }
+    Ok(data.after)
+}
+
+fn encode_cursor(sort: &[SortKey], after: Vec<String>) -> String {
+    let data = CursorData {
+        sort: sort_signature(sort),
+        after,
+    };
+    // Serializing strings into JSON cannot fail.
+    let json = serde_json::to_vec(&data).unwrap_or_default();
+    json.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+impl ListParams {
+    /// Rows to fetch: one more than the page, to learn whether another page
+    /// follows.
+    pub fn fetch_limit(&self) -> i64 {
+        self.limit + 1
+    }
+
+    /// The keyset condition selecting rows after the cursor. `key` gives,
+    /// for a sort key and its cursor value, the conditions "sorts after" and
+    /// "sorts equal" on that one column.
+    pub fn after_condition<QS, F>(&self, mut key: F) -> Result<Option<Condition<QS>>, ApiError>
+    where
+        QS: 'static,
+        F: FnMut(SortKey, &str) -> Result<(Condition<QS>, Condition<QS>), ApiError>,
+    {
+        let after = match &self.after {
+            Some(after) => after,
+            None => return Ok(None),
+        };
+
+        // (k1 > v1) OR (k1 = v1 AND k2 > v2) OR ... built from the last key
+        // inwards: rest = beyond_i OR (equal_i AND rest).
+        let mut condition: Option<Condition<QS>> = None;
+        for (sort_key, value) in self.sort.iter().zip(after).rev() {
+            let (beyond, equal) = key(*sort_key, value)?;
+            condition = Some(match condition {
+                None => beyond,
+                Some(rest) => Box::new(beyond.or(equal.and(rest))),
+            });
+        }
+        Ok(condition)
+    }
+
+    /// Cuts the extra row off `rows` and issues the cursor of the next page
+    /// from the last row kept, using `values` to read its sort key values.
+    pub fn page<T, F>(&self, mut rows: Vec<T>, values: F) -> Page<T>
+    where
+        F: Fn(&T, SortKey) -> String,
+    {
+        let more = rows.len() as i64 > self.limit;
+        rows.truncate(self.limit as usize);
+
+        let next_cursor = match rows.last() {
+            Some(last) if more => Some(encode_cursor(
+                &self.sort,
+                self.sort.iter().map(|key| values(last, *key)).collect(),
+            )),
+            _ => None,
+        };
+
+        Page {
+            items: rows,
+            next_cursor,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::stores;
+    use diesel::{debug_query, QueryDsl};
+
+    const SPEC: ListSpec = ListSpec {
+        sorts: &["name", "created", "id"],
+        filters: &["stage"],
+        default_sort: "-created",
+    };
+
+    fn query(pairs: &[(&str, &str)]) -> ListQuery {
+        ListQuery(
+            pairs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        )
+    }
+
+    fn key(field: &'static str, descending: bool) -> SortKey {
+        SortKey { field, descending }
+    }
+
+    fn message(err: ApiError) -> String {
+        err.to_string()
+    }
+
+    #[test]
+    fn defaults_apply_to_an_empty_query() {
+        let params = query(&[]).parse(&SPEC).unwrap();
+
+        assert_eq!(params.limit, DEFAULT_LIMIT);
+        assert_eq!(params.sort, [key("created", true), key("id", false)]);
+        assert!(params.filters.is_empty());
+        assert!(params.after.is_none());
+    }
+
+    #[test]
+    fn id_breaks_ties_unless_already_sorted_on() {
+        let cases: &[(&str, &[SortKey])] = &[
+            ("name", &[key("name", false), key("id", false)]),
+            (
+                "-name,created",
+                &[key("name", true), key("created", false), key("id", false)],
+            ),
+            ("-id", &[key("id", true)]),
+            ("id,name", &[key("id", false), key("name", false)]),
+        ];
+
+        for (text, expected) in cases {
+            assert_eq!(parse_sort(text, &SPEC).unwrap(), *expected, "{text}");
+        }
+    }
+
+    #[test]
+    fn unknown_and_duplicate_sort_fields_are_rejected() {
+        let cases = [
+            (
+                "price",
+                "Cannot sort by \"price\"; use one of name, created, id",
+            ),
+            ("name,-name", "\"name\" appears twice in sort"),
+            ("id,id", "\"id\" appears twice in sort"),
+            ("", "Cannot sort by \"\"; use one of name, created, id"),
+        ];
+
+        for (text, expected) in cases {
+            assert_eq!(message(parse_sort(text, &SPEC).unwrap_err()), expected);
+        }
+    }
+
+    #[test]
+    fn filters_must_be_allowed() {
+        let params = query(&[("filter[stage]", "live")]).parse(&SPEC).unwrap();
+        assert_eq!(params.filters, [("stage", "live".to_string())]);
+
+        let cases = [
+            (
+                "filter[name]",
+                "Cannot filter on \"name\"; use one of stage",
+            ),
+            ("filter[stage", "Unknown query parameter \"filter[stage\""),
+            ("stage", "Unknown query parameter \"stage\""),
+        ];
+        for (param, expected) in cases {
+            let err = query(&[(param, "live")]).parse(&SPEC).unwrap_err();
+            assert_eq!(message(err), expected);
+        }
+    }
+
+    #[test]
+    fn limit_must_be_within_bounds() {
+        for (limit, expected) in [("1", Some(1)), ("100", Some(MAX_LIMIT))] {
+            let params = query(&[("limit", limit)]).parse(&SPEC);
+            assert_eq!(params.ok().map(|params| params.limit), expected);
+        }
+        for limit in ["0", "-1", "101", "ten", ""] {
+            let err = query(&[("limit", limit)]).parse(&SPEC).unwrap_err();
+            assert_eq!(message(err), "limit must be a number between 1 and 100");
+        }
+    }
+
+    #[test]
+    fn cursors_round_trip() {
+        let sort = parse_sort("-created", &SPEC).unwrap();
+        let after = vec!["2023-04-01T10:00:00.5".to_string(), "a\"b,c".to_string()];
+        let cursor = encode_cursor(&sort, after.clone());
+
+        assert_eq!(decode_cursor(&cursor, &sort).unwrap(), after);
+
+        let params = query(&[("sort", "-created"), ("cursor", &cursor)])
+            .parse(&SPEC)
+            .unwrap();
+        assert_eq!(params.after, Some(after));
+    }
+
+    #[test]
+    fn cursors_are_tied_to_their_sort() {
+        let sort = parse_sort("-created", &SPEC).unwrap();
+        let cursor = encode_cursor(&sort, vec!["x".to_string(), "y".to_string()]);
+
+        for other in ["created", "-created,-id", "name"] {
+            let other = parse_sort(other, &SPEC).unwrap();
+            let err = decode_cursor(&cursor, &other).unwrap_err();
+            assert_eq!(message(err), "cursor was issued for a different sort");
+        }
+
+        // The default sort is what a cursor from a request without `sort`
+        // was issued for.
+        let err = query(&[("sort", "name"), ("cursor", &cursor)])
+            .parse(&SPEC)
+            .unwrap_err();
+        assert_eq!(message(err), "cursor was issued for a different sort");
+        assert!(query(&[("cursor", &cursor)]).parse(&SPEC).is_ok());
+    }
+
+    #[test]
+    fn malformed_cursors_are_rejected() {
+        let sort = parse_sort("-created", &SPEC).unwrap();
+        let short = encode_cursor(&sort, vec!["x".to_string()]);
+        let not_json: String = b"[]".iter().map(|byte| format!("{byte:02x}")).collect();
+
+        for cursor in ["abc", "zz", &not_json, &short] {
+            let err = decode_cursor(cursor, &sort).unwrap_err();
+            assert_eq!(message(err), "cursor is invalid", "{cursor}");
+        }
+    }
+
+    #[test]
+    fn after_condition_compares_keys_in_order() {
+        let params = query(&[]).parse(&SPEC).unwrap();
+        let none: Option<Condition<stores::table>> = params
+            .after_condition(|key, value| {
+                Ok(keyset_column(stores::id, value.to_string(), key.descending))
+            })
+            .unwrap();
+        assert!(none.is_none());
+
+        let sort = parse_sort("-name", &SPEC).unwrap();
+        let cursor = encode_cursor(&sort, vec!["b".to_string(), "s1".to_string()]);
+        let params = query(&[("sort", "-name"), ("cursor", &cursor)])
+            .parse(&SPEC)
+            .unwrap();
+        let condition: Condition<stores::table> = params
+            .after_condition(|key, value| {
+                Ok(match key.field {
+                    "name" => keyset_column(stores::name, value.to_string(), key.descending),
+                    _ => keyset_column(stores::id, value.to_string(), key.descending),
+                })
+            })
+            .unwrap()
+            .unwrap();
+
+        let sql =
+            debug_query::<Pg, _>(&stores::table.select(stores::id).filter(condition)).to_string();
+        assert_eq!(
+            sql,
+            r#"SELECT "stores"."id" FROM "stores" WHERE (("stores"."name" < $1) OR (("stores"."name" = $2) AND ("stores"."id" > $3))) -- binds: ["b", "b", "s1"]"#
+        );
+    }
+}
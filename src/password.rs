@@ -0,0 +1,82 @@
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher as _, PasswordVerifier as _, SaltString},
+    Algorithm, Argon2, Params, Version,
+};
+use rand::rngs::OsRng;
+
+pub type PasswordError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verification {
+    Invalid,
+    Valid,
+    /// The password matched, but the stored value is plaintext or was hashed
+    /// with different parameters and should be replaced.
+    ValidNeedsRehash,
+}
+
+#[derive(Clone)]
+pub struct PasswordHasher {
+    params: Params,
+}
+
+impl PasswordHasher {
+    pub fn new(memory_kib: u32, iterations: u32, parallelism: u32) -> Result<Self, PasswordError> {
+        let params = Params::new(memory_kib, iterations, parallelism, None)
+            .map_err(|e| format!("Invalid Argon2 parameters: {e}"))?;
+        Ok(PasswordHasher { params })
+    }
+
+    fn argon2(&self) -> Argon2<'_> {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+    }
+
+    pub fn hash(&self, password: &str) -> Result<String, PasswordError> {
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = self.argon2().hash_password(password.as_bytes(), &salt)?;
+        Ok(hash.to_string())
+    }
+
+    pub fn verify(&self, password: &str, stored: &str) -> Result<Verification, PasswordError> {
+        if !stored.starts_with("$argon2") {
+            // Rows created before hashing was introduced hold the password verbatim.
+            return Ok(
+                if constant_time_eq(password.as_bytes(), stored.as_bytes()) {
+                    Verification::ValidNeedsRehash
+                } else {
+                    Verification::Invalid
+                },
+            );
+        }
+
+        let parsed = PasswordHash::new(stored)?;
+        if self
+            .argon2()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_err()
+        {
+            return Ok(Verification::Invalid);
+        }
+
+        let current = parsed.algorithm == Algorithm::Argon2id.ident()
+            && parsed.version == Some(Version::V0x13.into())
+            && Params::try_from(&parsed).is_ok_and(|params| {
+                params.m_cost() == self.params.m_cost()
+                    && params.t_cost() == self.params.t_cost()
+                    && params.p_cost() == self.params.p_cost()
+            });
+
+        Ok(if current {
+            Verification::Valid
+        } else {
+            Verification::ValidNeedsRehash
+        })
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,287 @@
+use crate::payments::{
+    AuthorizeRequest, PaymentProvider, PaymentStatus, ProviderError, ProviderResponse, WebhookEvent,
+};
+use actix_web::http::header::HeaderMap;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+pub const SIGNATURE_HEADER: &str = "X-Mock-Signature";
+pub const CARD_LIMIT: i64 = 1_000_000;
+
+/// Deterministic in-process gateway so checkout can be exercised offline.
+/// The payment method picks the outcome:
+///
+/// - `mock_success` is authorized straight away
+/// - `mock_decline` is declined
+/// - `mock_3ds` requires confirmation, which then succeeds
+/// - `mock_3ds_decline` requires confirmation, which is then declined
+/// - `mock_unavailable` fails as if the gateway could not be reached
+///
+/// Amounts over `CARD_LIMIT` minor units are declined whatever the method.
+///
+/// Webhooks are a JSON `{"reference", "status"}` body whose hex encoded
+/// HMAC-SHA256 under the configured secret is sent in `X-Mock-Signature`.
+pub struct MockProvider {
+    webhook_secret: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct MockWebhook {
+    reference: String,
+    status: PaymentStatus,
+    message: Option<String>,
+}
+
+impl MockProvider {
+    pub fn new(webhook_secret: Option<String>) -> Self {
+        MockProvider { webhook_secret }
+    }
+
+    fn respond(reference: &str, status: PaymentStatus, message: Option<&str>) -> ProviderResponse {
+        ProviderResponse {
+            reference: reference.to_string(),
+            status,
+            message: message.map(String::from),
+        }
+    }
+}
+
+impl PaymentProvider for MockProvider {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn authorize(&self, request: &AuthorizeRequest) -> Result<ProviderResponse, ProviderError> {
+        let (prefix, status, message) = match request.payment_method {
+            "mock_success" => ("mock_ok", PaymentStatus::Authorized, None),
+            "mock_decline" => (
+                "mock_declined",
+                PaymentStatus::Declined,
+                Some("Card declined"),
+            ),
+            "mock_3ds" => ("mock_3ds", PaymentStatus::RequiresAction, None),
+            "mock_3ds_decline" => ("mock_3ds_fail", PaymentStatus::RequiresAction, None),
+            "mock_unavailable" => {
+                return Err(ProviderError::Unavailable(
+                    "Mock gateway is unavailable".to_string(),
+                ))
+            }
+            other => {
+                return Err(ProviderError::InvalidRequest(format!(
+                    "Unknown mock payment method \"{other}\""
+                )))
+            }
+        };
+
+        let reference = format!("{prefix}_{}", request.payment_id);
+        if request.amount > CARD_LIMIT {
+            let message = format!("Card limit exceeded for {}", request.currency);
+            return Ok(Self::respond(
+                &reference,
+                PaymentStatus::Declined,
+                Some(&message),
+            ));
+        }
+        Ok(Self::respond(&reference, status, message))
+    }
+
+    fn confirm(&self, reference: &str) -> Result<ProviderResponse, ProviderError> {
+        if reference.starts_with("mock_3ds_fail_") {
+            Ok(Self::respond(
+                reference,
+                PaymentStatus::Declined,
+                Some("Authentication failed"),
+            ))
+        } else {
+            Ok(Self::respond(reference, PaymentStatus::Authorized, None))
+        }
+    }
+
+    fn capture(&self, reference: &str, _amount: i64) -> Result<ProviderResponse, ProviderError> {
+        Ok(Self::respond(reference, PaymentStatus::Captured, None))
+    }
+
+    fn void(&self, reference: &str) -> Result<ProviderResponse, ProviderError> {
+        Ok(Self::respond(reference, PaymentStatus::Voided, None))
+    }
+
+    fn refund(&self, reference: &str, _amount: i64) -> Result<ProviderResponse, ProviderError> {
+        Ok(Self::respond(reference, PaymentStatus::Refunded, None))
+    }
+
+    fn verify_webhook(
+        &self,
+        headers: &HeaderMap,
+        payload: &[u8],
+    ) -> Result<WebhookEvent, ProviderError> {
+        let secret = self
+            .webhook_secret
+            .as_deref()
+            .ok_or(ProviderError::InvalidSignature)?;
+        let signature = headers
+            .get(SIGNATURE_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(decode_hex)
+            .ok_or(ProviderError::InvalidSignature)?;
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+            .map_err(|_| ProviderError::InvalidSignature)?;
+        mac.update(payload);
+        mac.verify_slice(&signature)
+            .map_err(|_| ProviderError::InvalidSignature)?;
+
+        let webhook: MockWebhook = serde_json::from_slice(payload)
+            .map_err(|err| ProviderError::InvalidRequest(err.to_string()))?;
+        Ok(WebhookEvent {
+            reference: webhook.reference,
+            status: webhook.status,
+            message: webhook.message,
+        })
+    }
+}
+
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    if !value.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{HeaderName, HeaderValue};
+
+    fn authorize(payment_method: &str, amount: i64) -> Result<ProviderResponse, ProviderError> {
+        MockProvider::new(None).authorize(&AuthorizeRequest {
+            payment_id: "pay_1",
+            amount,
+            currency: "USD",
+            payment_method,
+        })
+    }
+
+    fn sign(secret: &str, payload: &[u8]) -> HeaderMap {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(payload);
+        let signature: String = mac
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_static("x-mock-signature"),
+            HeaderValue::from_str(&signature).unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn payment_method_picks_the_outcome() {
+        let cases = [
+            ("mock_success", "mock_ok_pay_1", PaymentStatus::Authorized),
+            (
+                "mock_decline",
+                "mock_declined_pay_1",
+                PaymentStatus::Declined,
+            ),
+            ("mock_3ds", "mock_3ds_pay_1", PaymentStatus::RequiresAction),
+            (
+                "mock_3ds_decline",
+                "mock_3ds_fail_pay_1",
+                PaymentStatus::RequiresAction,
+            ),
+        ];
+
+        for (method, reference, status) in cases {
+            for _ in 0..2 {
+                let response = authorize(method, 100).unwrap();
+                assert_eq!(response.reference, reference, "{method}");
+                assert_eq!(response.status, status, "{method}");
+            }
+        }
+    }
+
+    #[test]
+    fn unavailable_and_unknown_methods_fail() {
+        assert!(matches!(
+            authorize("mock_unavailable", 100),
+            Err(ProviderError::Unavailable(_))
+        ));
+        assert!(matches!(
+            authorize("visa", 100),
+            Err(ProviderError::InvalidRequest(_))
+        ));
+    }
+
+    #[test]
+    fn amounts_over_the_card_limit_are_declined() {
+        let response = authorize("mock_success", CARD_LIMIT).unwrap();
+        assert_eq!(response.status, PaymentStatus::Authorized);
+
+        let response = authorize("mock_success", CARD_LIMIT + 1).unwrap();
+        assert_eq!(response.status, PaymentStatus::Declined);
+        assert_eq!(response.reference, "mock_ok_pay_1");
+    }
+
+    #[test]
+    fn confirm_follows_the_authorization_reference() {
+        let provider = MockProvider::new(None);
+
+        let reference = authorize("mock_3ds", 100).unwrap().reference;
+        let response = provider.confirm(&reference).unwrap();
+        assert_eq!(response.status, PaymentStatus::Authorized);
+
+        let reference = authorize("mock_3ds_decline", 100).unwrap().reference;
+        let response = provider.confirm(&reference).unwrap();
+        assert_eq!(response.status, PaymentStatus::Declined);
+    }
+
+    #[test]
+    fn follow_up_calls_keep_the_reference() {
+        let provider = MockProvider::new(None);
+
+        let capture = provider.capture("mock_ok_pay_1", 100).unwrap();
+        let void = provider.void("mock_ok_pay_1").unwrap();
+        let refund = provider.refund("mock_ok_pay_1", 50).unwrap();
+
+        assert_eq!(capture.status, PaymentStatus::Captured);
+        assert_eq!(void.status, PaymentStatus::Voided);
+        assert_eq!(refund.status, PaymentStatus::Refunded);
+        for response in [capture, void, refund] {
+            assert_eq!(response.reference, "mock_ok_pay_1");
+        }
+    }
+
+    #[test]
+    fn webhooks_need_a_valid_signature() {
+        let payload = br#"{"reference":"mock_ok_pay_1","status":"captured"}"#;
+        let provider = MockProvider::new(Some("secret".to_string()));
+
+        let event = provider
+            .verify_webhook(&sign("secret", payload), payload)
+            .unwrap();
+        assert_eq!(event.reference, "mock_ok_pay_1");
+        assert_eq!(event.status, PaymentStatus::Captured);
+
+        assert!(matches!(
+            provider.verify_webhook(&sign("other", payload), payload),
+            Err(ProviderError::InvalidSignature)
+        ));
+        assert!(matches!(
+            provider.verify_webhook(&HeaderMap::new(), payload),
+            Err(ProviderError::InvalidSignature)
+        ));
+        assert!(matches!(
+            MockProvider::new(None).verify_webhook(&sign("secret", payload), payload),
+            Err(ProviderError::InvalidSignature)
+        ));
+    }
+}
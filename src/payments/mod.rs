@@ -0,0 +1,196 @@
+pub mod mock;
+
+use crate::errors::ApiError;
+use actix_web::http::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr, sync::Arc};
+
+/// Lifecycle of a payment attempt. `PaymentStatus::next` lists the moves a
+/// provider response or webhook may make; anything else is rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentStatus {
+    Created,
+    RequiresAction,
+    Authorized,
+    Captured,
+    PartiallyRefunded,
+    Refunded,
+    Voided,
+    Declined,
+    Failed,
+}
+
+impl PaymentStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            PaymentStatus::Created => "created",
+            PaymentStatus::RequiresAction => "requires_action",
+            PaymentStatus::Authorized => "authorized",
+            PaymentStatus::Captured => "captured",
+            PaymentStatus::PartiallyRefunded => "partially_refunded",
+            PaymentStatus::Refunded => "refunded",
+            PaymentStatus::Voided => "voided",
+            PaymentStatus::Declined => "declined",
+            PaymentStatus::Failed => "failed",
+        }
+    }
+
+    pub fn next(self) -> &'static [PaymentStatus] {
+        use PaymentStatus::*;
+
+        match self {
+            Created => &[RequiresAction, Authorized, Captured, Declined, Failed],
+            RequiresAction => &[Authorized, Captured, Declined, Failed],
+            Authorized => &[Captured, Voided],
+            Captured | PartiallyRefunded => &[PartiallyRefunded, Refunded],
+            Refunded | Voided | Declined | Failed => &[],
+        }
+    }
+
+    pub fn transition(self, to: PaymentStatus) -> Result<PaymentStatus, ApiError> {
+        if self.next().contains(&to) {
+            Ok(to)
+        } else {
+            Err(ApiError::InvalidTransition(format!(
+                "Payment cannot move from {self} to {to}"
+            )))
+        }
+    }
+}
+
+impl fmt::Display for PaymentStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for PaymentStatus {
+    type Err = ApiError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        use PaymentStatus::*;
+
+        [
+            Created,
+            RequiresAction,
+            Authorized,
+            Captured,
+            PartiallyRefunded,
+            Refunded,
+            Voided,
+            Declined,
+            Failed,
+        ]
+        .into_iter()
+        .find(|status| status.as_str() == value)
+        .ok_or_else(|| ApiError::Internal(format!("Unknown payment status \"{value}\"")))
+    }
+}
+
+pub struct AuthorizeRequest<'a> {
+    /// Our payment id, sent along so providers can deduplicate retries.
+    pub payment_id: &'a str,
+    pub amount: i64,
+    pub currency: &'a str,
+    /// Provider specific token for the card or wallet being charged.
+    pub payment_method: &'a str,
+}
+
+pub struct ProviderResponse {
+    pub reference: String,
+    pub status: PaymentStatus,
+    pub message: Option<String>,
+}
+
+/// A verified notification from the provider about one of its payments.
+pub struct WebhookEvent {
+    pub reference: String,
+    pub status: PaymentStatus,
+    pub message: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum ProviderError {
+    InvalidRequest(String),
+    InvalidSignature,
+    Unavailable(String),
+}
+
+impl fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProviderError::InvalidRequest(message) | ProviderError::Unavailable(message) => {
+                f.write_str(message)
+            }
+            ProviderError::InvalidSignature => f.write_str("Invalid webhook signature"),
+        }
+    }
+}
+
+impl From<ProviderError> for ApiError {
+    fn from(err: ProviderError) -> Self {
+        match err {
+            ProviderError::InvalidRequest(message) => ApiError::Validation(message),
+            ProviderError::InvalidSignature => ApiError::Unauthorized("Invalid webhook signature"),
+            ProviderError::Unavailable(message) => ApiError::PaymentProvider(message),
+        }
+    }
+}
+
+/// A payment gateway. Calls are blocking and made from inside `web::block`.
+/// Amounts are in minor units of the payment's currency.
+pub trait PaymentProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn authorize(&self, request: &AuthorizeRequest) -> Result<ProviderResponse, ProviderError>;
+
+    /// Completes an authorization that was left in `requires_action` once the
+    /// customer has gone through the provider's challenge.
+    fn confirm(&self, reference: &str) -> Result<ProviderResponse, ProviderError>;
+
+    fn capture(&self, reference: &str, amount: i64) -> Result<ProviderResponse, ProviderError>;
+
+    fn void(&self, reference: &str) -> Result<ProviderResponse, ProviderError>;
+
+    fn refund(&self, reference: &str, amount: i64) -> Result<ProviderResponse, ProviderError>;
+
+    /// Checks the signature on a webhook request and decodes its body.
+    fn verify_webhook(
+        &self,
+        headers: &HeaderMap,
+        payload: &[u8],
+    ) -> Result<WebhookEvent, ProviderError>;
+}
+
+/// The providers this instance can talk to, one of which is used for new
+/// payments. Existing payments keep going through the provider that took them.
+#[derive(Clone)]
+pub struct PaymentProviders {
+    providers: Vec<Arc<dyn PaymentProvider>>,
+    default: &'static str,
+}
+
+impl PaymentProviders {
+    pub fn new(providers: Vec<Arc<dyn PaymentProvider>>, default: &str) -> Result<Self, String> {
+        let default = providers
+            .iter()
+            .map(|provider| provider.name())
+            .find(|name| *name == default)
+            .ok_or_else(|| format!("Unknown payment provider \"{default}\""))?;
+
+        Ok(PaymentProviders { providers, default })
+    }
+
+    pub fn default_provider(&self) -> Arc<dyn PaymentProvider> {
+        self.find(self.default)
+            .expect("default provider is checked in PaymentProviders::new")
+    }
+
+    pub fn find(&self, name: &str) -> Option<Arc<dyn PaymentProvider>> {
+        self.providers
+            .iter()
+            .find(|provider| provider.name() == name)
+            .cloned()
+    }
+}
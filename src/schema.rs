@@ -1,9 +1,141 @@
 // @generated automatically by Diesel CLI.
 
+pub mod sql_types {
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "tsvector", schema = "pg_catalog"))]
+    pub struct Tsvector;
+}
+
 diesel::table! {
-    inventory (user_id, product_id) {
-        user_id -> Varchar,
+    audit_events (id) {
+        id -> Varchar,
+        actor_id -> Nullable<Varchar>,
+        session_id -> Nullable<Varchar>,
+        store_id -> Nullable<Varchar>,
+        action -> Varchar,
+        target_id -> Nullable<Varchar>,
+        before -> Nullable<Jsonb>,
+        after -> Nullable<Jsonb>,
+        ip -> Nullable<Varchar>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    cart_items (cart_id, product_id) {
+        cart_id -> Varchar,
+        product_id -> Varchar,
+        quantity -> Int4,
+    }
+}
+
+diesel::table! {
+    carts (id) {
+        id -> Varchar,
+        store_id -> Varchar,
+        user_id -> Nullable<Varchar>,
+        token_hash -> Nullable<Varchar>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    categories (id) {
+        id -> Varchar,
+        store_id -> Varchar,
+        parent_id -> Nullable<Varchar>,
+        name -> Varchar,
+        slug -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    collection_products (collection_id, product_id) {
+        collection_id -> Varchar,
         product_id -> Varchar,
+        position -> Int4,
+    }
+}
+
+diesel::table! {
+    collection_rules (id) {
+        id -> Varchar,
+        collection_id -> Varchar,
+        field -> Varchar,
+        operator -> Varchar,
+        value -> Varchar,
+        position -> Int4,
+        currency -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    collections (id) {
+        id -> Varchar,
+        store_id -> Varchar,
+        name -> Varchar,
+        slug -> Varchar,
+        kind -> Varchar,
+        match_all -> Bool,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    order_items (id) {
+        id -> Varchar,
+        order_id -> Varchar,
+        product_id -> Nullable<Varchar>,
+        title -> Varchar,
+        unit_price -> Int8,
+        quantity -> Int4,
+        currency -> Varchar,
+    }
+}
+
+diesel::table! {
+    orders (id) {
+        id -> Varchar,
+        store_id -> Varchar,
+        user_id -> Varchar,
+        status -> Varchar,
+        total -> Int8,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        currency -> Varchar,
+    }
+}
+
+diesel::table! {
+    payment_events (id) {
+        id -> Varchar,
+        payment_id -> Varchar,
+        action -> Varchar,
+        status -> Varchar,
+        amount -> Nullable<Int8>,
+        message -> Nullable<Varchar>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    payments (id) {
+        id -> Varchar,
+        store_id -> Varchar,
+        order_id -> Nullable<Varchar>,
+        user_id -> Varchar,
+        provider -> Varchar,
+        provider_reference -> Nullable<Varchar>,
+        amount -> Int8,
+        currency -> Varchar,
+        refunded_amount -> Int8,
+        status -> Varchar,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        pending_action -> Nullable<Varchar>,
+        pending_since -> Nullable<Timestamp>,
     }
 }
 
@@ -15,12 +147,72 @@ diesel::table! {
 }
 
 diesel::table! {
+    product_categories (product_id, category_id) {
+        product_id -> Varchar,
+        category_id -> Varchar,
+    }
+}
+
+diesel::table! {
+    product_option_values (id) {
+        id -> Varchar,
+        option_id -> Varchar,
+        value -> Varchar,
+        position -> Int4,
+    }
+}
+
+diesel::table! {
+    product_options (id) {
+        id -> Varchar,
+        product_id -> Varchar,
+        name -> Varchar,
+        position -> Int4,
+    }
+}
+
+diesel::table! {
+    product_tags (product_id, tag) {
+        product_id -> Varchar,
+        tag -> Varchar,
+    }
+}
+
+diesel::table! {
+    product_variants (id) {
+        id -> Varchar,
+        product_id -> Varchar,
+        store_id -> Varchar,
+        sku -> Varchar,
+        price -> Nullable<Int8>,
+        barcode -> Nullable<Varchar>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::Tsvector;
+
     products (id) {
         id -> Varchar,
         title -> Varchar,
         description -> Nullable<Varchar>,
-        price -> Float8,
+        price -> Int8,
         quantity -> Int4,
+        store_id -> Varchar,
+        currency -> Varchar,
+        search_vector -> Tsvector,
+    }
+}
+
+diesel::table! {
+    refresh_tokens (id) {
+        id -> Varchar,
+        session_id -> Varchar,
+        token_hash -> Varchar,
+        used_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
     }
 }
 
@@ -38,6 +230,55 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    sale_items (id) {
+        id -> Varchar,
+        sale_id -> Varchar,
+        product_id -> Nullable<Varchar>,
+        title -> Varchar,
+        unit_price -> Int8,
+        quantity -> Int4,
+        refunded_quantity -> Int4,
+        currency -> Varchar,
+    }
+}
+
+diesel::table! {
+    sale_refund_items (refund_id, sale_item_id) {
+        refund_id -> Varchar,
+        sale_item_id -> Varchar,
+        quantity -> Int4,
+        amount -> Int8,
+        restocked -> Bool,
+        currency -> Varchar,
+    }
+}
+
+diesel::table! {
+    sale_refunds (id) {
+        id -> Varchar,
+        sale_id -> Varchar,
+        user_id -> Varchar,
+        reason -> Nullable<Varchar>,
+        amount -> Int8,
+        created_at -> Timestamp,
+        currency -> Varchar,
+    }
+}
+
+diesel::table! {
+    sales (id) {
+        id -> Varchar,
+        store_id -> Varchar,
+        user_id -> Varchar,
+        note -> Nullable<Varchar>,
+        total -> Int8,
+        refunded_total -> Int8,
+        created_at -> Timestamp,
+        currency -> Varchar,
+    }
+}
+
 diesel::table! {
     session (id) {
         id -> Varchar,
@@ -45,6 +286,85 @@ diesel::table! {
         role_id -> Varchar,
         access_token -> Varchar,
         expires_at -> Timestamp,
+        created_at -> Timestamp,
+        user_agent -> Nullable<Varchar>,
+        ip -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    stock_locations (id) {
+        id -> Varchar,
+        store_id -> Varchar,
+        name -> Varchar,
+        is_default -> Bool,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    stock_movements (id) {
+        id -> Varchar,
+        store_id -> Varchar,
+        product_id -> Varchar,
+        location_id -> Varchar,
+        kind -> Varchar,
+        quantity -> Int4,
+        transfer_id -> Nullable<Varchar>,
+        reference -> Nullable<Varchar>,
+        note -> Nullable<Varchar>,
+        user_id -> Nullable<Varchar>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    stock_reservations (id) {
+        id -> Varchar,
+        store_id -> Varchar,
+        product_id -> Varchar,
+        user_id -> Varchar,
+        quantity -> Int4,
+        status -> Varchar,
+        reference -> Nullable<Varchar>,
+        expires_at -> Timestamp,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    store_invitations (id) {
+        id -> Varchar,
+        store_id -> Varchar,
+        email -> Varchar,
+        role -> Varchar,
+        token_hash -> Varchar,
+        status -> Varchar,
+        invited_by -> Nullable<Varchar>,
+        expires_at -> Timestamp,
+        created_at -> Timestamp,
+        responded_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    store_settings (store_id) {
+        store_id -> Varchar,
+        contact_email -> Nullable<Varchar>,
+        timezone -> Varchar,
+        currency -> Varchar,
+    }
+}
+
+diesel::table! {
+    store_stage_transitions (id) {
+        id -> Varchar,
+        store_id -> Varchar,
+        from_stage -> Nullable<Varchar>,
+        to_stage -> Varchar,
+        user_id -> Nullable<Varchar>,
+        created_at -> Timestamp,
     }
 }
 
@@ -53,6 +373,7 @@ diesel::table! {
         id -> Varchar,
         name -> Varchar,
         stage -> Varchar,
+        created_at -> Timestamp,
     }
 }
 
@@ -60,6 +381,7 @@ diesel::table! {
     user_stores (user_id, store_id) {
         user_id -> Varchar,
         store_id -> Varchar,
+        role -> Varchar,
     }
 }
 
@@ -72,24 +394,105 @@ diesel::table! {
     }
 }
 
-diesel::joinable!(inventory -> products (product_id));
-diesel::joinable!(inventory -> users (user_id));
+diesel::table! {
+    variant_option_values (variant_id, option_value_id) {
+        variant_id -> Varchar,
+        option_value_id -> Varchar,
+    }
+}
+
+diesel::joinable!(cart_items -> carts (cart_id));
+diesel::joinable!(cart_items -> products (product_id));
+diesel::joinable!(carts -> stores (store_id));
+diesel::joinable!(carts -> users (user_id));
+diesel::joinable!(categories -> stores (store_id));
+diesel::joinable!(collection_products -> collections (collection_id));
+diesel::joinable!(collection_products -> products (product_id));
+diesel::joinable!(collection_rules -> collections (collection_id));
+diesel::joinable!(collections -> stores (store_id));
+diesel::joinable!(order_items -> orders (order_id));
+diesel::joinable!(order_items -> products (product_id));
+diesel::joinable!(orders -> stores (store_id));
+diesel::joinable!(orders -> users (user_id));
+diesel::joinable!(payment_events -> payments (payment_id));
+diesel::joinable!(payments -> orders (order_id));
+diesel::joinable!(payments -> stores (store_id));
+diesel::joinable!(payments -> users (user_id));
+diesel::joinable!(product_categories -> categories (category_id));
+diesel::joinable!(product_categories -> products (product_id));
+diesel::joinable!(product_option_values -> product_options (option_id));
+diesel::joinable!(product_options -> products (product_id));
+diesel::joinable!(product_tags -> products (product_id));
+diesel::joinable!(product_variants -> products (product_id));
+diesel::joinable!(product_variants -> stores (store_id));
+diesel::joinable!(products -> stores (store_id));
+diesel::joinable!(refresh_tokens -> session (session_id));
 diesel::joinable!(role_permissions -> permissions (permission_id));
 diesel::joinable!(role_permissions -> roles (role_id));
+diesel::joinable!(sale_items -> products (product_id));
+diesel::joinable!(sale_items -> sales (sale_id));
+diesel::joinable!(sale_refund_items -> sale_items (sale_item_id));
+diesel::joinable!(sale_refund_items -> sale_refunds (refund_id));
+diesel::joinable!(sale_refunds -> sales (sale_id));
+diesel::joinable!(sale_refunds -> users (user_id));
+diesel::joinable!(sales -> stores (store_id));
+diesel::joinable!(sales -> users (user_id));
 diesel::joinable!(session -> roles (role_id));
 diesel::joinable!(session -> users (user_id));
+diesel::joinable!(stock_locations -> stores (store_id));
+diesel::joinable!(stock_movements -> products (product_id));
+diesel::joinable!(stock_movements -> stock_locations (location_id));
+diesel::joinable!(stock_movements -> stores (store_id));
+diesel::joinable!(stock_movements -> users (user_id));
+diesel::joinable!(stock_reservations -> products (product_id));
+diesel::joinable!(stock_reservations -> stores (store_id));
+diesel::joinable!(stock_reservations -> users (user_id));
+diesel::joinable!(store_invitations -> stores (store_id));
+diesel::joinable!(store_invitations -> users (invited_by));
+diesel::joinable!(store_settings -> stores (store_id));
+diesel::joinable!(store_stage_transitions -> stores (store_id));
+diesel::joinable!(store_stage_transitions -> users (user_id));
 diesel::joinable!(user_stores -> stores (store_id));
 diesel::joinable!(user_stores -> users (user_id));
 diesel::joinable!(users -> roles (role_id));
+diesel::joinable!(variant_option_values -> product_option_values (option_value_id));
+diesel::joinable!(variant_option_values -> product_variants (variant_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
-    inventory,
+    audit_events,
+    cart_items,
+    carts,
+    categories,
+    collection_products,
+    collection_rules,
+    collections,
+    order_items,
+    orders,
+    payment_events,
+    payments,
     permissions,
+    product_categories,
+    product_option_values,
+    product_options,
+    product_tags,
+    product_variants,
     products,
+    refresh_tokens,
     role_permissions,
     roles,
+    sale_items,
+    sale_refund_items,
+    sale_refunds,
+    sales,
     session,
+    stock_locations,
+    stock_movements,
+    stock_reservations,
+    store_invitations,
+    store_settings,
+    store_stage_transitions,
     stores,
     user_stores,
     users,
+    variant_option_values,
 );
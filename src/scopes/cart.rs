@@ -0,0 +1,475 @@
+use crate::{
+    errors::ApiError,
+    extractors::cart_owner::CartOwner,
+    models::{Cart, NewCart, NewCartItem, Product, Store},
+    money::Money,
+    scopes::store::{find_live_store, store_currency},
+    token::{hash_token, random_token},
+    AppState,
+};
+use actix_web::{web, HttpResponse};
+use diesel::{
+    Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl,
+    SelectableHelper,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct CartItemPayload {
+    product_id: String,
+    quantity: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct CartQuantityPayload {
+    quantity: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CartLine {
+    product_id: String,
+    title: String,
+    unit_price: Money,
+    quantity: i32,
+    line_total: Money,
+    in_stock: bool,
+}
+
+/// Prices are read from `products` every time a cart is rendered, so a cart
+/// never shows a price the store no longer charges.
+#[derive(Debug, Serialize, Deserialize)]
+struct CartView {
+    id: Option<String>,
+    store_id: String,
+    /// Only sent when a guest cart is created; the client passes it back in
+    /// `X-Cart-Token` from then on.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+    items: Vec<CartLine>,
+    subtotal: Money,
+}
+
+pub(crate) async fn get_cart(
+    owner: CartOwner,
+    store_id: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let cart = web::block(move || {
+        let mut conn = state.pool.get()?;
+        find_store(&store_id, &mut conn)?;
+        let cart = find_cart(&store_id, &owner, &mut conn)?;
+        render_cart(&store_id, cart, None, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(cart))
+}
+
+pub(crate) async fn add_cart_item(
+    owner: CartOwner,
+    store_id: web::Path<String>,
+    body: web::Json<CartItemPayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let cart = web::block(move || {
+        let mut conn = state.pool.get()?;
+        add_item(&store_id, &owner, &body, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(cart))
+}
+
+pub(crate) async fn update_cart_item(
+    owner: CartOwner,
+    path: web::Path<(String, String)>,
+    body: web::Json<CartQuantityPayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let (store_id, product_id) = path.into_inner();
+
+    let cart = web::block(move || {
+        let mut conn = state.pool.get()?;
+        edit_item(&store_id, &owner, &product_id, body.quantity, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(cart))
+}
+
+pub(crate) async fn delete_cart_item(
+    owner: CartOwner,
+    path: web::Path<(String, String)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let (store_id, product_id) = path.into_inner();
+
+    let cart = web::block(move || {
+        let mut conn = state.pool.get()?;
+        remove_item(&store_id, &owner, &product_id, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(cart))
+}
+
+fn find_store(store: &str, conn: &mut PgConnection) -> Result<Store, ApiError> {
+    use crate::schema::stores::dsl::*;
+
+    let res = stores
+        .find(store)
+        .first::<Store>(conn)
+        .optional()?
+        .ok_or(ApiError::NotFound("Store not found"))?;
+    Ok(res)
+}
+
+fn find_store_product(
+    store: &str,
+    product: &str,
+    conn: &mut PgConnection,
+) -> Result<Product, ApiError> {
+    use crate::schema::products::dsl::*;
+
+    let res = products
+        .filter(id.eq(product))
+        .filter(store_id.eq(store))
+        .select(Product::as_select())
+        .first::<Product>(conn)
+        .optional()?
+        .ok_or(ApiError::NotFound("Product not found"))?;
+    Ok(res)
+}
+
+fn find_cart(
+    store: &str,
+    owner: &CartOwner,
+    conn: &mut PgConnection,
+) -> Result<Option<Cart>, ApiError> {
+    use crate::schema::carts::dsl::*;
+
+    let query = carts.filter(store_id.eq(store)).into_boxed();
+    let query = match owner {
+        CartOwner::Customer { user_id: user } => query.filter(user_id.eq(user)),
+        CartOwner::Guest { token: Some(token) } => query.filter(token_hash.eq(hash_token(token))),
+        CartOwner::Guest { token: None } => return Ok(None),
+    };
+
+    let res = query.first::<Cart>(conn).optional()?;
+    Ok(res)
+}
+
+/// Returns the owner's cart for the store, creating it on first use. The
+/// second value is the raw token of a newly created guest cart.
+fn find_or_create_cart(
+    store: &str,
+    owner: &CartOwner,
+    conn: &mut PgConnection,
+) -> Result<(Cart, Option<String>), ApiError> {
+    use crate::schema::carts::dsl::*;
+
+    if let Some(cart) = find_cart(store, owner, conn)? {
+        return Ok((cart, None));
+    }
+
+    let (user, token) = match owner {
+        CartOwner::Customer { user_id: user } => (Some(user.as_str()), None),
+        CartOwner::Guest { .. } => (None, Some(random_token())),
+    };
+    let hashed_token = token.as_deref().map(hash_token);
+
+    let new_cart = NewCart {
+        id: &Uuid::new_v4().to_string(),
+        store_id: store,
+        user_id: user,
+        token_hash: hashed_token.as_deref(),
+    };
+
+    let cart = diesel::insert_into(carts)
+        .values(&new_cart)
+        .get_result::<Cart>(conn)?;
+    Ok((cart, token))
+}
+
+fn check_quantity(product: &Product, requested: i32) -> Result<(), ApiError> {
+    if requested <= 0 {
+        return Err(ApiError::Validation(
+            "Quantity must be greater than zero".to_string(),
+        ));
+    }
+    if requested > product.quantity {
+        return Err(ApiError::Validation(format!(
+            "Only {} of \"{}\" in stock",
+            product.quantity, product.title
+        )));
+    }
+    Ok(())
+}
+
+fn add_item(
+    store: &str,
+    owner: &CartOwner,
+    body: &CartItemPayload,
+    conn: &mut PgConnection,
+) -> Result<CartView, ApiError> {
+    use crate::schema::{cart_items, carts, products};
+
+    conn.transaction(|conn| {
+        find_live_store(store, conn)?;
+        let product = find_store_product(store, &body.product_id, conn)?;
+        if body.quantity <= 0 {
+            return Err(ApiError::Validation(
+                "Quantity must be greater than zero".to_string(),
+            ));
+        }
+
+        let (cart, token) = find_or_create_cart(store, owner, conn)?;
+
+        let other_currency = cart_items::table
+            .inner_join(products::table)
+            .filter(cart_items::cart_id.eq(&cart.id))
+            .filter(products::currency.ne(product.price.currency().to_string()))
+            .count()
+            .get_result::<i64>(conn)?;
+        if other_currency > 0 {
+            return Err(ApiError::Validation(format!(
+                "Cart already holds items not priced in {}",
+                product.price.currency()
+            )));
+        }
+
+        let in_cart = cart_items::table
+            .find((&cart.id, &product.id))
+            .select(cart_items::quantity)
+            .first::<i32>(conn)
+            .optional()?
+            .unwrap_or(0);
+        let total = in_cart.saturating_add(body.quantity);
+        check_quantity(&product, total)?;
+
+        upsert_item(&cart.id, &product.id, total, conn)?;
+        diesel::update(carts::table.find(&cart.id))
+            .set(carts::updated_at.eq(chrono::Local::now().naive_local()))
+            .execute(conn)?;
+
+        render_cart(store, Some(cart), token, conn)
+    })
+}
+
+fn edit_item(
+    store: &str,
+    owner: &CartOwner,
+    product: &str,
+    requested: i32,
+    conn: &mut PgConnection,
+) -> Result<CartView, ApiError> {
+    use crate::schema::{cart_items, carts};
+
+    conn.transaction(|conn| {
+        find_live_store(store, conn)?;
+        let cart = find_cart(store, owner, conn)?.ok_or(ApiError::NotFound("Cart not found"))?;
+        let product = find_store_product(store, product, conn)?;
+        check_quantity(&product, requested)?;
+
+        let updated = diesel::update(cart_items::table.find((&cart.id, &product.id)))
+            .set(cart_items::quantity.eq(requested))
+            .execute(conn)?;
+        if updated == 0 {
+            return Err(ApiError::NotFound("Cart item not found"));
+        }
+
+        diesel::update(carts::table.find(&cart.id))
+            .set(carts::updated_at.eq(chrono::Local::now().naive_local()))
+            .execute(conn)?;
+
+        render_cart(store, Some(cart), None, conn)
+    })
+}
+
+fn remove_item(
+    store: &str,
+    owner: &CartOwner,
+    product: &str,
+    conn: &mut PgConnection,
+) -> Result<CartView, ApiError> {
+    use crate::schema::{cart_items, carts};
+
+    conn.transaction(|conn| {
+        let cart = find_cart(store, owner, conn)?.ok_or(ApiError::NotFound("Cart not found"))?;
+
+        let deleted = diesel::delete(cart_items::table.find((&cart.id, product))).execute(conn)?;
+        if deleted == 0 {
+            return Err(ApiError::NotFound("Cart item not found"));
+        }
+
+        diesel::update(carts::table.find(&cart.id))
+            .set(carts::updated_at.eq(chrono::Local::now().naive_local()))
+            .execute(conn)?;
+
+        render_cart(store, Some(cart), None, conn)
+    })
+}
+
+fn upsert_item(
+    cart: &str,
+    product: &str,
+    requested: i32,
+    conn: &mut PgConnection,
+) -> Result<(), ApiError> {
+    use crate::schema::cart_items::dsl::*;
+
+    let new_item = NewCartItem {
+        cart_id: cart,
+        product_id: product,
+        quantity: &requested,
+    };
+
+    diesel::insert_into(cart_items)
+        .values(&new_item)
+        .on_conflict((cart_id, product_id))
+        .do_update()
+        .set(quantity.eq(requested))
+        .execute(conn)?;
+    Ok(())
+}
+
+fn render_cart(
+    store: &str,
+    cart: Option<Cart>,
+    token: Option<String>,
+    conn: &mut PgConnection,
+) -> Result<CartView, ApiError> {
+    use crate::schema::{cart_items, products};
+
+    let cart_id = match cart {
+        Some(cart) => cart.id,
+        None => {
+            return Ok(CartView {
+                id: None,
+                store_id: store.to_string(),
+                token: None,
+                items: Vec::new(),
+                subtotal: Money::zero(store_currency(store, conn)?),
+            })
+        }
+    };
+
+    let items = cart_items::table
+        .inner_join(products::table)
+        .filter(cart_items::cart_id.eq(&cart_id))
+        .order(products::title.asc())
+        .select((cart_items::quantity, Product::as_select()))
+        .load::<(i32, Product)>(conn)?
+        .into_iter()
+        .map(|(quantity, product)| {
+            Ok(CartLine {
+                line_total: product.price.checked_mul(quantity)?,
+                in_stock: quantity <= product.quantity,
+                product_id: product.id,
+                title: product.title,
+                unit_price: product.price,
+                quantity,
+            })
+        })
+        .collect::<Result<Vec<CartLine>, ApiError>>()?;
+
+    let currency = match items.first() {
+        Some(item) => item.unit_price.currency(),
+        None => store_currency(store, conn)?,
+    };
+
+    Ok(CartView {
+        id: Some(cart_id),
+        store_id: store.to_string(),
+        token,
+        subtotal: Money::sum(currency, items.iter().map(|item| item.line_total))?,
+        items,
+    })
+}
+
+/// Folds the guest cart behind `token` into the user's cart for the same
+/// store, or simply hands it over when the user has none there yet. Merged
+/// quantities are capped at the product's stock, and guest lines priced in
+/// another currency than the user's cart are dropped, as `add_item` would
+/// have refused them.
+pub(crate) fn merge_guest_cart(
+    token: &str,
+    user: &str,
+    conn: &mut PgConnection,
+) -> Result<(), ApiError> {
+    use crate::schema::{cart_items, carts, products};
+
+    conn.transaction(|conn| {
+        let guest_cart = match carts::table
+            .filter(carts::token_hash.eq(hash_token(token)))
+            .first::<Cart>(conn)
+            .optional()?
+        {
+            Some(cart) => cart,
+            None => return Ok(()),
+        };
+
+        let user_cart = carts::table
+            .filter(carts::store_id.eq(&guest_cart.store_id))
+            .filter(carts::user_id.eq(user))
+            .first::<Cart>(conn)
+            .optional()?;
+
+        let now = chrono::Local::now().naive_local();
+
+        let user_cart = match user_cart {
+            Some(cart) => cart,
+            None => {
+                diesel::update(carts::table.find(&guest_cart.id))
+                    .set((
+                        carts::user_id.eq(user),
+                        carts::token_hash.eq(None::<String>),
+                        carts::updated_at.eq(now),
+                    ))
+                    .execute(conn)?;
+                return Ok(());
+            }
+        };
+
+        let user_currency = cart_items::table
+            .inner_join(products::table)
+            .filter(cart_items::cart_id.eq(&user_cart.id))
+            .select(products::currency)
+            .first::<String>(conn)
+            .optional()?;
+
+        let guest_items = cart_items::table
+            .inner_join(products::table)
+            .filter(cart_items::cart_id.eq(&guest_cart.id))
+            .select((
+                cart_items::product_id,
+                cart_items::quantity,
+                products::quantity,
+                products::currency,
+            ))
+            .load::<(String, i32, i32, String)>(conn)?;
+
+        for (product, guest_quantity, stock, currency) in guest_items {
+            if user_currency.as_ref().is_some_and(|cart| *cart != currency) {
+                continue;
+            }
+            let in_cart = cart_items::table
+                .find((&user_cart.id, &product))
+                .select(cart_items::quantity)
+                .first::<i32>(conn)
+                .optional()?
+                .unwrap_or(0);
+            let total = in_cart.saturating_add(guest_quantity).min(stock);
+            if total > 0 {
+                upsert_item(&user_cart.id, &product, total, conn)?;
+            }
+        }
+
+        diesel::delete(carts::table.find(&guest_cart.id)).execute(conn)?;
+        diesel::update(carts::table.find(&user_cart.id))
+            .set(carts::updated_at.eq(now))
+            .execute(conn)?;
+        Ok(())
+    })
+}
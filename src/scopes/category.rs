@@ -0,0 +1,558 @@
+use crate::{
+    errors::ApiError,
+    extractors::{
+        authentication_token::AuthenticationToken,
+        require_permission::{ProductsRead, ProductsWrite, RequirePermission},
+    },
+    models::{Category, NewCategory, NewProductCategory, NewProductTag, Product},
+    scopes::{
+        member::{check_store_role, StoreRole},
+        product::check_product_role,
+        store::{check_store_access, get_session},
+        variant::{product_detail, product_details, ProductDetail},
+    },
+    AppState,
+};
+use actix_web::{web, HttpResponse};
+use diesel::{
+    sql_types::Varchar, Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl,
+    QueryableByName, RunQueryDsl, SelectableHelper,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct CategoryPayload {
+    name: String,
+    /// Derived from the name when omitted.
+    slug: Option<String>,
+    parent_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct ProductCategoriesPayload {
+    category_ids: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct ProductTagsPayload {
+    tags: Vec<String>,
+}
+
+#[derive(QueryableByName)]
+struct CategoryId {
+    #[diesel(sql_type = Varchar)]
+    id: String,
+}
+
+/// Checks `slug`, or builds one from `name` when there is none: lowercase
+/// ASCII letters and digits separated by single dashes.
+pub(crate) fn make_slug(name: &str, slug: Option<&str>) -> Result<String, ApiError> {
+    if name.trim().is_empty() {
+        return Err(ApiError::Validation("Name must not be empty".to_string()));
+    }
+
+    match slug {
+        Some(slug) => {
+            let valid = !slug.is_empty()
+                && slug.split('-').all(|part| {
+                    !part.is_empty()
+                        && part
+                            .bytes()
+                            .all(|byte| byte.is_ascii_lowercase() || byte.is_ascii_digit())
+                });
+            if !valid {
+                return Err(ApiError::Validation(format!(
+                    "\"{slug}\" is not a valid slug; use lowercase letters, digits and dashes"
+                )));
+            }
+            Ok(slug.to_string())
+        }
+        None => {
+            let slug = name
+                .to_lowercase()
+                .split(|c: char| !c.is_ascii_alphanumeric())
+                .filter(|part| !part.is_empty())
+                .collect::<Vec<&str>>()
+                .join("-");
+            if slug.is_empty() {
+                return Err(ApiError::Validation(format!(
+                    "Cannot derive a slug from \"{name}\"; pass one explicitly"
+                )));
+            }
+            Ok(slug)
+        }
+    }
+}
+
+pub(crate) async fn get_store_categories(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<ProductsRead>,
+    store_id: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let pool = state.pool.clone();
+    let id_str = store_id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_access(&sessions[0].user_id, &id_str, &mut conn)
+    })
+    .await??;
+
+    let categories = web::block(move || {
+        let mut conn = state.pool.get()?;
+        list_categories(&store_id, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(categories))
+}
+
+pub(crate) async fn create_category(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<ProductsWrite>,
+    store_id: web::Path<String>,
+    body: web::Json<CategoryPayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let pool = state.pool.clone();
+    let id_str = store_id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_role(&sessions[0].user_id, &id_str, StoreRole::Staff, &mut conn)
+    })
+    .await??;
+
+    let category = web::block(move || {
+        let mut conn = state.pool.get()?;
+        add_category(&store_id, &body, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(category))
+}
+
+pub(crate) async fn update_category(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<ProductsWrite>,
+    path: web::Path<(String, String)>,
+    body: web::Json<CategoryPayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let (store_id, category_id) = path.into_inner();
+
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let pool = state.pool.clone();
+    let id_str = store_id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_role(&sessions[0].user_id, &id_str, StoreRole::Staff, &mut conn)
+    })
+    .await??;
+
+    let category = web::block(move || {
+        let mut conn = state.pool.get()?;
+        edit_category(&store_id, &category_id, &body, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(category))
+}
+
+pub(crate) async fn delete_category(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<ProductsWrite>,
+    path: web::Path<(String, String)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let (store_id, category_id) = path.into_inner();
+
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let pool = state.pool.clone();
+    let id_str = store_id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_role(&sessions[0].user_id, &id_str, StoreRole::Staff, &mut conn)
+    })
+    .await??;
+
+    web::block(move || {
+        let mut conn = state.pool.get()?;
+        remove_category(&store_id, &category_id, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json("success"))
+}
+
+pub(crate) async fn get_category_products(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<ProductsRead>,
+    path: web::Path<(String, String)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let (store_id, category_id) = path.into_inner();
+
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let pool = state.pool.clone();
+    let id_str = store_id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_access(&sessions[0].user_id, &id_str, &mut conn)
+    })
+    .await??;
+
+    let products = web::block(move || {
+        let mut conn = state.pool.get()?;
+        list_category_products(&store_id, &category_id, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(products))
+}
+
+pub(crate) async fn set_product_categories(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<ProductsWrite>,
+    id: web::Path<String>,
+    body: web::Json<ProductCategoriesPayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let product = web::block(move || {
+        let mut conn = state.pool.get()?;
+        let product = check_product_role(&sessions[0].user_id, &id, StoreRole::Staff, &mut conn)?;
+        assign_categories(product, &body.category_ids, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(product))
+}
+
+pub(crate) async fn set_product_tags(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<ProductsWrite>,
+    id: web::Path<String>,
+    body: web::Json<ProductTagsPayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let product = web::block(move || {
+        let mut conn = state.pool.get()?;
+        let product = check_product_role(&sessions[0].user_id, &id, StoreRole::Staff, &mut conn)?;
+        assign_tags(product, &body.tags, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(product))
+}
+
+fn list_categories(store: &str, conn: &mut PgConnection) -> Result<Vec<Category>, ApiError> {
+    use crate::schema::categories::dsl::*;
+
+    let res = categories
+        .filter(store_id.eq(store))
+        .order(name.asc())
+        .select(Category::as_select())
+        .load::<Category>(conn)?;
+    Ok(res)
+}
+
+fn find_store_category(
+    store: &str,
+    category: &str,
+    conn: &mut PgConnection,
+) -> Result<Category, ApiError> {
+    use crate::schema::categories::dsl::*;
+
+    let res = categories
+        .filter(id.eq(category))
+        .filter(store_id.eq(store))
+        .select(Category::as_select())
+        .first::<Category>(conn)
+        .optional()?
+        .ok_or(ApiError::NotFound("Category not found"))?;
+    Ok(res)
+}
+
+/// Ids of `category` and every category below it. `UNION` drops rows already
+/// seen, so the walk ends even if the tree somehow contains a cycle.
+pub(crate) fn descendants(
+    category: &str,
+    conn: &mut PgConnection,
+) -> Result<Vec<String>, ApiError> {
+    let res = diesel::sql_query(
+        "WITH RECURSIVE tree AS ( \
+             SELECT id FROM categories WHERE id = $1 \
+             UNION \
+             SELECT c.id FROM categories c JOIN tree t ON c.parent_id = t.id \
+         ) \
+         SELECT id FROM tree",
+    )
+    .bind::<Varchar, _>(category)
+    .load::<CategoryId>(conn)?
+    .into_iter()
+    .map(|row| row.id)
+    .collect();
+    Ok(res)
+}
+
+fn check_slug_free(
+    store: &str,
+    category_slug: &str,
+    except: Option<&str>,
+    conn: &mut PgConnection,
+) -> Result<(), ApiError> {
+    use crate::schema::categories::dsl::*;
+
+    let taken = categories
+        .filter(store_id.eq(store))
+        .filter(slug.eq(category_slug))
+        .filter(id.ne(except.unwrap_or_default()))
+        .count()
+        .get_result::<i64>(conn)?;
+    if taken > 0 {
+        return Err(ApiError::Conflict(format!(
+            "Category slug \"{category_slug}\" is already used"
+        )));
+    }
+    Ok(())
+}
+
+fn add_category(
+    store: &str,
+    body: &CategoryPayload,
+    conn: &mut PgConnection,
+) -> Result<Category, ApiError> {
+    use crate::schema::categories::dsl::*;
+
+    let category_slug = make_slug(&body.name, body.slug.as_deref())?;
+
+    conn.transaction(|conn| {
+        if let Some(parent) = &body.parent_id {
+            find_store_category(store, parent, conn)?;
+        }
+        check_slug_free(store, &category_slug, None, conn)?;
+
+        let new_category = NewCategory {
+            id: &Uuid::new_v4().to_string(),
+            store_id: store,
+            parent_id: body.parent_id.as_deref(),
+            name: body.name.trim(),
+            slug: &category_slug,
+        };
+
+        let res = diesel::insert_into(categories)
+            .values(&new_category)
+            .returning(Category::as_returning())
+            .get_result(conn)?;
+        Ok(res)
+    })
+}
+
+fn edit_category(
+    store: &str,
+    category: &str,
+    body: &CategoryPayload,
+    conn: &mut PgConnection,
+) -> Result<Category, ApiError> {
+    use crate::schema::categories::dsl::*;
+
+    let category_slug = make_slug(&body.name, body.slug.as_deref())?;
+
+    conn.transaction(|conn| {
+        if body.parent_id.is_some() {
+            // Hold the store's tree still while checking for cycles, so two
+            // concurrent moves can't each pass the check and form a loop.
+            categories
+                .filter(store_id.eq(store))
+                .order(id.asc())
+                .select(id)
+                .for_update()
+                .load::<String>(conn)?;
+        }
+        let current = find_store_category(store, category, conn)?;
+        if let Some(parent) = &body.parent_id {
+            find_store_category(store, parent, conn)?;
+            if descendants(&current.id, conn)?.contains(parent) {
+                return Err(ApiError::Validation(
+                    "A category cannot be moved under itself or one of its children".to_string(),
+                ));
+            }
+        }
+        check_slug_free(store, &category_slug, Some(&current.id), conn)?;
+
+        let res = diesel::update(categories.find(&current.id))
+            .set((
+                parent_id.eq(body.parent_id.as_deref()),
+                name.eq(body.name.trim()),
+                slug.eq(&category_slug),
+            ))
+            .returning(Category::as_returning())
+            .get_result(conn)?;
+        Ok(res)
+    })
+}
+
+fn remove_category(store: &str, category: &str, conn: &mut PgConnection) -> Result<(), ApiError> {
+    use crate::schema::categories::dsl::*;
+
+    conn.transaction(|conn| {
+        let current = find_store_category(store, category, conn)?;
+
+        let children = categories
+            .filter(parent_id.eq(&current.id))
+            .count()
+            .get_result::<i64>(conn)?;
+        if children > 0 {
+            return Err(ApiError::Conflict(
+                "Move or delete the category's subcategories first".to_string(),
+            ));
+        }
+
+        diesel::delete(categories.find(&current.id)).execute(conn)?;
+        Ok(())
+    })
+}
+
+/// Products filed under the category or any of its descendants.
+fn list_category_products(
+    store: &str,
+    category: &str,
+    conn: &mut PgConnection,
+) -> Result<Vec<ProductDetail>, ApiError> {
+    use crate::schema::{product_categories, products};
+
+    let current = find_store_category(store, category, conn)?;
+    let tree = descendants(&current.id, conn)?;
+
+    let res = products::table
+        .filter(products::store_id.eq(store))
+        .filter(
+            products::id.eq_any(
+                product_categories::table
+                    .filter(product_categories::category_id.eq_any(&tree))
+                    .select(product_categories::product_id),
+            ),
+        )
+        .order(products::title.asc())
+        .select(Product::as_select())
+        .load::<Product>(conn)?;
+    product_details(res, conn)
+}
+
+fn assign_categories(
+    product: Product,
+    category_ids: &[String],
+    conn: &mut PgConnection,
+) -> Result<ProductDetail, ApiError> {
+    use crate::schema::product_categories;
+
+    let wanted = category_ids
+        .iter()
+        .map(String::as_str)
+        .collect::<BTreeSet<&str>>();
+
+    conn.transaction(|conn| {
+        for category in &wanted {
+            find_store_category(&product.store_id, category, conn)?;
+        }
+
+        diesel::delete(
+            product_categories::table.filter(product_categories::product_id.eq(&product.id)),
+        )
+        .execute(conn)?;
+
+        let rows = wanted
+            .iter()
+            .map(|category| NewProductCategory {
+                product_id: &product.id,
+                category_id: category,
+            })
+            .collect::<Vec<NewProductCategory>>();
+        diesel::insert_into(product_categories::table)
+            .values(&rows)
+            .execute(conn)?;
+
+        product_detail(product, conn)
+    })
+}
+
+fn assign_tags(
+    product: Product,
+    tags: &[String],
+    conn: &mut PgConnection,
+) -> Result<ProductDetail, ApiError> {
+    use crate::schema::product_tags;
+
+    let wanted = tags
+        .iter()
+        .map(|tag| tag.trim().to_lowercase())
+        .collect::<BTreeSet<String>>();
+    if wanted.contains("") {
+        return Err(ApiError::Validation("Tags must not be empty".to_string()));
+    }
+
+    conn.transaction(|conn| {
+        diesel::delete(product_tags::table.filter(product_tags::product_id.eq(&product.id)))
+            .execute(conn)?;
+
+        let rows = wanted
+            .iter()
+            .map(|tag| NewProductTag {
+                product_id: &product.id,
+                tag,
+            })
+            .collect::<Vec<NewProductTag>>();
+        diesel::insert_into(product_tags::table)
+            .values(&rows)
+            .execute(conn)?;
+
+        product_detail(product, conn)
+    })
+}
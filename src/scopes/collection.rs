@@ -0,0 +1,682 @@
+use crate::{
+    errors::ApiError,
+    extractors::{
+        authentication_token::AuthenticationToken,
+        require_permission::{ProductsRead, ProductsWrite, RequirePermission},
+    },
+    models::{
+        Collection, CollectionRule, NewCollection, NewCollectionProduct, NewCollectionRule, Product,
+    },
+    money::{Currency, Money},
+    schema::products,
+    scopes::{
+        category::make_slug,
+        member::{check_store_role, StoreRole},
+        store::{check_store_access, get_session, store_currency},
+        variant::{product_details, ProductDetail},
+    },
+    AppState,
+};
+use actix_web::{web, HttpResponse};
+use diesel::{
+    dsl::not, pg::Pg, sql_types::Bool, BoolExpressionMethods, BoxableExpression, Connection,
+    ExpressionMethods, OptionalExtension, PgConnection, PgTextExpressionMethods, QueryDsl,
+    RunQueryDsl, SelectableHelper,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, str::FromStr};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CollectionKind {
+    Manual,
+    Rule,
+}
+
+impl CollectionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CollectionKind::Manual => "manual",
+            CollectionKind::Rule => "rule",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RuleField {
+    Price,
+    Tag,
+    Title,
+    Quantity,
+}
+
+impl RuleField {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RuleField::Price => "price",
+            RuleField::Tag => "tag",
+            RuleField::Title => "title",
+            RuleField::Quantity => "quantity",
+        }
+    }
+}
+
+impl FromStr for RuleField {
+    type Err = ApiError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "price" => Ok(RuleField::Price),
+            "tag" => Ok(RuleField::Tag),
+            "title" => Ok(RuleField::Title),
+            "quantity" => Ok(RuleField::Quantity),
+            _ => Err(ApiError::Internal(format!(
+                "Unknown collection rule field \"{value}\""
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RuleOperator {
+    Eq,
+    Ne,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    Contains,
+}
+
+impl RuleOperator {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RuleOperator::Eq => "eq",
+            RuleOperator::Ne => "ne",
+            RuleOperator::Lt => "lt",
+            RuleOperator::Lte => "lte",
+            RuleOperator::Gt => "gt",
+            RuleOperator::Gte => "gte",
+            RuleOperator::Contains => "contains",
+        }
+    }
+}
+
+impl FromStr for RuleOperator {
+    type Err = ApiError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "eq" => Ok(RuleOperator::Eq),
+            "ne" => Ok(RuleOperator::Ne),
+            "lt" => Ok(RuleOperator::Lt),
+            "lte" => Ok(RuleOperator::Lte),
+            "gt" => Ok(RuleOperator::Gt),
+            "gte" => Ok(RuleOperator::Gte),
+            "contains" => Ok(RuleOperator::Contains),
+            _ => Err(ApiError::Internal(format!(
+                "Unknown collection rule operator \"{value}\""
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct RulePayload {
+    field: RuleField,
+    operator: RuleOperator,
+    /// A decimal amount in the store currency for `price`, a whole number
+    /// for `quantity`, text otherwise. Price rules keep the currency they
+    /// were saved in.
+    value: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct CollectionPayload {
+    name: String,
+    /// Derived from the name when omitted.
+    slug: Option<String>,
+    kind: CollectionKind,
+    /// Rule collections only: whether a product has to satisfy every rule
+    /// or just one. Defaults to every rule.
+    match_all: Option<bool>,
+    #[serde(default)]
+    rules: Vec<RulePayload>,
+}
+
+impl CollectionPayload {
+    fn validate(&self) -> Result<(), ApiError> {
+        match self.kind {
+            CollectionKind::Manual if !self.rules.is_empty() => {
+                return Err(ApiError::Validation(
+                    "Manual collections cannot have rules".to_string(),
+                ))
+            }
+            CollectionKind::Rule if self.rules.is_empty() => {
+                return Err(ApiError::Validation(
+                    "A rule collection needs at least one rule".to_string(),
+                ))
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct CollectionProductsPayload {
+    /// In display order.
+    product_ids: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CollectionDetail {
+    #[serde(flatten)]
+    collection: Collection,
+    rules: Vec<CollectionRule>,
+}
+
+type Condition = Box<dyn BoxableExpression<products::table, Pg, SqlType = Bool>>;
+
+pub(crate) async fn get_store_collections(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<ProductsRead>,
+    store_id: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let pool = state.pool.clone();
+    let id_str = store_id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_access(&sessions[0].user_id, &id_str, &mut conn)
+    })
+    .await??;
+
+    let collections = web::block(move || {
+        let mut conn = state.pool.get()?;
+        list_collections(&store_id, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(collections))
+}
+
+pub(crate) async fn create_collection(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<ProductsWrite>,
+    store_id: web::Path<String>,
+    body: web::Json<CollectionPayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let pool = state.pool.clone();
+    let id_str = store_id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_role(&sessions[0].user_id, &id_str, StoreRole::Staff, &mut conn)
+    })
+    .await??;
+
+    let collection = web::block(move || {
+        let mut conn = state.pool.get()?;
+        add_collection(&store_id, &body, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(collection))
+}
+
+pub(crate) async fn get_store_collection(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<ProductsRead>,
+    path: web::Path<(String, String)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let (store_id, collection_id) = path.into_inner();
+
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let pool = state.pool.clone();
+    let id_str = store_id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_access(&sessions[0].user_id, &id_str, &mut conn)
+    })
+    .await??;
+
+    let collection = web::block(move || {
+        let mut conn = state.pool.get()?;
+        let collection = find_store_collection(&store_id, &collection_id, &mut conn)?;
+        collection_detail(collection, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(collection))
+}
+
+pub(crate) async fn delete_collection(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<ProductsWrite>,
+    path: web::Path<(String, String)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let (store_id, collection_id) = path.into_inner();
+
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let pool = state.pool.clone();
+    let id_str = store_id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_role(&sessions[0].user_id, &id_str, StoreRole::Staff, &mut conn)
+    })
+    .await??;
+
+    web::block(move || {
+        let mut conn = state.pool.get()?;
+        remove_collection(&store_id, &collection_id, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json("success"))
+}
+
+pub(crate) async fn get_collection_products(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<ProductsRead>,
+    path: web::Path<(String, String)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let (store_id, collection_id) = path.into_inner();
+
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let pool = state.pool.clone();
+    let id_str = store_id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_access(&sessions[0].user_id, &id_str, &mut conn)
+    })
+    .await??;
+
+    let products = web::block(move || {
+        let mut conn = state.pool.get()?;
+        list_collection_products(&store_id, &collection_id, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(products))
+}
+
+pub(crate) async fn set_collection_products(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<ProductsWrite>,
+    path: web::Path<(String, String)>,
+    body: web::Json<CollectionProductsPayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let (store_id, collection_id) = path.into_inner();
+
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let pool = state.pool.clone();
+    let id_str = store_id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_role(&sessions[0].user_id, &id_str, StoreRole::Staff, &mut conn)
+    })
+    .await??;
+
+    let products = web::block(move || {
+        let mut conn = state.pool.get()?;
+        assign_products(&store_id, &collection_id, &body.product_ids, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(products))
+}
+
+fn list_collections(store: &str, conn: &mut PgConnection) -> Result<Vec<Collection>, ApiError> {
+    use crate::schema::collections::dsl::*;
+
+    let res = collections
+        .filter(store_id.eq(store))
+        .order(name.asc())
+        .select(Collection::as_select())
+        .load::<Collection>(conn)?;
+    Ok(res)
+}
+
+fn find_store_collection(
+    store: &str,
+    collection: &str,
+    conn: &mut PgConnection,
+) -> Result<Collection, ApiError> {
+    use crate::schema::collections::dsl::*;
+
+    let res = collections
+        .filter(id.eq(collection))
+        .filter(store_id.eq(store))
+        .select(Collection::as_select())
+        .first::<Collection>(conn)
+        .optional()?
+        .ok_or(ApiError::NotFound("Collection not found"))?;
+    Ok(res)
+}
+
+fn load_rules(collection: &str, conn: &mut PgConnection) -> Result<Vec<CollectionRule>, ApiError> {
+    use crate::schema::collection_rules::dsl::*;
+
+    let res = collection_rules
+        .filter(collection_id.eq(collection))
+        .order(position.asc())
+        .select(CollectionRule::as_select())
+        .load::<CollectionRule>(conn)?;
+    Ok(res)
+}
+
+fn collection_detail(
+    collection: Collection,
+    conn: &mut PgConnection,
+) -> Result<CollectionDetail, ApiError> {
+    let rules = load_rules(&collection.id, conn)?;
+    Ok(CollectionDetail { collection, rules })
+}
+
+/// Rules are stored as text; the value is checked against its field here so
+/// a rule that cannot be evaluated is never saved. `currency` is the one a
+/// price rule's amount is in.
+fn rule_condition(
+    field: &str,
+    operator: &str,
+    value: &str,
+    currency: Option<Currency>,
+) -> Result<Condition, ApiError> {
+    use crate::schema::product_tags;
+
+    let field = field.parse::<RuleField>()?;
+    let operator = operator.parse::<RuleOperator>()?;
+
+    let condition: Condition = match field {
+        RuleField::Price => {
+            let currency = currency
+                .ok_or_else(|| ApiError::Internal("Price rule has no currency".to_string()))?;
+            let minor = Money::parse(value.trim(), currency)?.minor();
+            let same_currency = products::currency.eq(currency.to_string());
+            match operator {
+                RuleOperator::Eq => Box::new(same_currency.and(products::price.eq(minor))),
+                RuleOperator::Ne => Box::new(same_currency.and(products::price.ne(minor))),
+                RuleOperator::Lt => Box::new(same_currency.and(products::price.lt(minor))),
+                RuleOperator::Lte => Box::new(same_currency.and(products::price.le(minor))),
+                RuleOperator::Gt => Box::new(same_currency.and(products::price.gt(minor))),
+                RuleOperator::Gte => Box::new(same_currency.and(products::price.ge(minor))),
+                RuleOperator::Contains => return Err(bad_operator(field, operator)),
+            }
+        }
+        RuleField::Quantity => {
+            let units = value
+                .trim()
+                .parse::<i32>()
+                .map_err(|_| ApiError::Validation(format!("\"{value}\" is not a whole number")))?;
+            match operator {
+                RuleOperator::Eq => Box::new(products::quantity.eq(units)),
+                RuleOperator::Ne => Box::new(products::quantity.ne(units)),
+                RuleOperator::Lt => Box::new(products::quantity.lt(units)),
+                RuleOperator::Lte => Box::new(products::quantity.le(units)),
+                RuleOperator::Gt => Box::new(products::quantity.gt(units)),
+                RuleOperator::Gte => Box::new(products::quantity.ge(units)),
+                RuleOperator::Contains => return Err(bad_operator(field, operator)),
+            }
+        }
+        RuleField::Tag => {
+            let tagged = products::id.eq_any(
+                product_tags::table
+                    .filter(product_tags::tag.eq(value.trim().to_lowercase()))
+                    .select(product_tags::product_id),
+            );
+            match operator {
+                RuleOperator::Eq => Box::new(tagged),
+                RuleOperator::Ne => Box::new(not(tagged)),
+                _ => return Err(bad_operator(field, operator)),
+            }
+        }
+        RuleField::Title => match operator {
+            RuleOperator::Eq => Box::new(products::title.ilike(escape_like(value))),
+            RuleOperator::Ne => Box::new(products::title.not_ilike(escape_like(value))),
+            RuleOperator::Contains => {
+                Box::new(products::title.ilike(format!("%{}%", escape_like(value))))
+            }
+            _ => return Err(bad_operator(field, operator)),
+        },
+    };
+    Ok(condition)
+}
+
+fn bad_operator(field: RuleField, operator: RuleOperator) -> ApiError {
+    ApiError::Validation(format!(
+        "Operator \"{}\" does not apply to {}",
+        operator.as_str(),
+        field.as_str()
+    ))
+}
+
+/// Makes `value` match literally in a LIKE pattern.
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+fn add_collection(
+    store: &str,
+    body: &CollectionPayload,
+    conn: &mut PgConnection,
+) -> Result<CollectionDetail, ApiError> {
+    use crate::schema::{collection_rules, collections};
+
+    body.validate()?;
+    let collection_slug = make_slug(&body.name, body.slug.as_deref())?;
+    let currency = store_currency(store, conn)?;
+    for rule in &body.rules {
+        rule_condition(
+            rule.field.as_str(),
+            rule.operator.as_str(),
+            &rule.value,
+            Some(currency),
+        )?;
+    }
+
+    conn.transaction(|conn| {
+        let taken = collections::table
+            .filter(collections::store_id.eq(store))
+            .filter(collections::slug.eq(&collection_slug))
+            .count()
+            .get_result::<i64>(conn)?;
+        if taken > 0 {
+            return Err(ApiError::Conflict(format!(
+                "Collection slug \"{collection_slug}\" is already used"
+            )));
+        }
+
+        let new_collection = NewCollection {
+            id: &Uuid::new_v4().to_string(),
+            store_id: store,
+            name: body.name.trim(),
+            slug: &collection_slug,
+            kind: body.kind.as_str(),
+            match_all: &body.match_all.unwrap_or(true),
+        };
+        let collection = diesel::insert_into(collections::table)
+            .values(&new_collection)
+            .returning(Collection::as_returning())
+            .get_result(conn)?;
+
+        for (position, rule) in (0i32..).zip(&body.rules) {
+            diesel::insert_into(collection_rules::table)
+                .values(&NewCollectionRule {
+                    id: &Uuid::new_v4().to_string(),
+                    collection_id: &collection.id,
+                    field: rule.field.as_str(),
+                    operator: rule.operator.as_str(),
+                    value: rule.value.trim(),
+                    position: &position,
+                    currency: (rule.field == RuleField::Price).then(|| currency.as_str()),
+                })
+                .execute(conn)?;
+        }
+
+        collection_detail(collection, conn)
+    })
+}
+
+fn remove_collection(
+    store: &str,
+    collection: &str,
+    conn: &mut PgConnection,
+) -> Result<(), ApiError> {
+    use crate::schema::collections;
+
+    let current = find_store_collection(store, collection, conn)?;
+    diesel::delete(collections::table.find(&current.id)).execute(conn)?;
+    Ok(())
+}
+
+/// A manual collection's products in their set order; a rule collection's
+/// products as matched right now, by title.
+fn list_collection_products(
+    store: &str,
+    collection: &str,
+    conn: &mut PgConnection,
+) -> Result<Vec<ProductDetail>, ApiError> {
+    use crate::schema::collection_products;
+
+    let current = find_store_collection(store, collection, conn)?;
+
+    if current.kind == CollectionKind::Manual.as_str() {
+        let res = products::table
+            .inner_join(collection_products::table)
+            .filter(collection_products::collection_id.eq(&current.id))
+            .order(collection_products::position.asc())
+            .select(Product::as_select())
+            .load::<Product>(conn)?;
+        return product_details(res, conn);
+    }
+
+    let mut condition: Option<Condition> = None;
+    for rule in load_rules(&current.id, conn)? {
+        let currency = rule
+            .currency
+            .as_deref()
+            .map(str::parse::<Currency>)
+            .transpose()?;
+        let next = rule_condition(&rule.field, &rule.operator, &rule.value, currency)?;
+        condition = Some(match condition {
+            None => next,
+            Some(prev) if current.match_all => Box::new(prev.and(next)),
+            Some(prev) => Box::new(prev.or(next)),
+        });
+    }
+
+    let mut query = products::table
+        .filter(products::store_id.eq(store))
+        .select(Product::as_select())
+        .into_boxed();
+    if let Some(condition) = condition {
+        query = query.filter(condition);
+    }
+
+    let res = query.order(products::title.asc()).load::<Product>(conn)?;
+    product_details(res, conn)
+}
+
+fn assign_products(
+    store: &str,
+    collection: &str,
+    product_ids: &[String],
+    conn: &mut PgConnection,
+) -> Result<Vec<ProductDetail>, ApiError> {
+    use crate::schema::collection_products;
+
+    let mut seen = HashSet::new();
+    if let Some(repeated) = product_ids.iter().find(|product| !seen.insert(*product)) {
+        return Err(ApiError::Validation(format!(
+            "Product {repeated} is listed twice"
+        )));
+    }
+
+    conn.transaction(|conn| {
+        let current = find_store_collection(store, collection, conn)?;
+        if current.kind != CollectionKind::Manual.as_str() {
+            return Err(ApiError::Conflict(
+                "Products of a rule collection follow from its rules".to_string(),
+            ));
+        }
+
+        let found = products::table
+            .filter(products::store_id.eq(store))
+            .filter(products::id.eq_any(product_ids))
+            .count()
+            .get_result::<i64>(conn)?;
+        if found != product_ids.len() as i64 {
+            return Err(ApiError::NotFound("Product not found"));
+        }
+
+        diesel::delete(
+            collection_products::table.filter(collection_products::collection_id.eq(&current.id)),
+        )
+        .execute(conn)?;
+
+        let positions = (0i32..).take(product_ids.len()).collect::<Vec<i32>>();
+        let rows = product_ids
+            .iter()
+            .zip(&positions)
+            .map(|(product, position)| NewCollectionProduct {
+                collection_id: &current.id,
+                product_id: product,
+                position,
+            })
+            .collect::<Vec<NewCollectionProduct>>();
+        diesel::insert_into(collection_products::table)
+            .values(&rows)
+            .execute(conn)?;
+
+        list_collection_products(store, collection, conn)
+    })
+}
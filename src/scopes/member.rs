@@ -0,0 +1,794 @@
+use crate::{
+    db::unit_of_work,
+    errors::ApiError,
+    extractors::{
+        authentication_token::AuthenticationToken,
+        require_permission::{RequirePermission, StoresRead, StoresWrite},
+    },
+    models::{NewStoreInvitation, NewUserStore, Store, StoreInvitation, UserStore},
+    scopes::{
+        audit::{record, snapshot, Actor, Event},
+        store::{check_store_access, get_session},
+    },
+    token::{hash_token, random_token},
+    AppState,
+};
+use actix_web::{web, HttpRequest, HttpResponse, Scope};
+use diesel::{
+    sql_types::Text, Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl,
+    RunQueryDsl, SelectableHelper,
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+use uuid::Uuid;
+
+diesel::sql_function!(fn lower(x: Text) -> Text);
+
+/// What a member may do within one store. Variants are ordered by authority,
+/// so `role >= StoreRole::Manager` reads "manager or above".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum StoreRole {
+    Viewer,
+    Staff,
+    Manager,
+    Owner,
+}
+
+impl StoreRole {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StoreRole::Viewer => "viewer",
+            StoreRole::Staff => "staff",
+            StoreRole::Manager => "manager",
+            StoreRole::Owner => "owner",
+        }
+    }
+}
+
+impl fmt::Display for StoreRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for StoreRole {
+    type Err = ApiError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "viewer" => Ok(StoreRole::Viewer),
+            "staff" => Ok(StoreRole::Staff),
+            "manager" => Ok(StoreRole::Manager),
+            "owner" => Ok(StoreRole::Owner),
+            _ => Err(ApiError::Internal(format!(
+                "Unknown store role \"{value}\""
+            ))),
+        }
+    }
+}
+
+/// Lifecycle of an invitation. Only pending invitations can be answered or
+/// revoked; the other states are final.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum InvitationStatus {
+    Pending,
+    Accepted,
+    Declined,
+    Revoked,
+    Expired,
+}
+
+impl InvitationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InvitationStatus::Pending => "pending",
+            InvitationStatus::Accepted => "accepted",
+            InvitationStatus::Declined => "declined",
+            InvitationStatus::Revoked => "revoked",
+            InvitationStatus::Expired => "expired",
+        }
+    }
+}
+
+pub fn invitation_scope() -> Scope {
+    web::scope("invitations")
+        .route("/accept", web::post().to(accept_invitation))
+        .route("/decline", web::post().to(decline_invitation))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct StoreMember {
+    user_id: String,
+    email: String,
+    role: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct InvitationPayload {
+    email: String,
+    role: StoreRole,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct InvitationTokenPayload {
+    token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct TransferPayload {
+    user_id: String,
+}
+
+/// A new invitation with its token, which is shown only this once and has to
+/// reach the invitee out of band.
+#[derive(Debug, Serialize)]
+struct IssuedInvitation {
+    invitation: StoreInvitation,
+    token: String,
+}
+
+pub(crate) async fn get_store_members(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<StoresRead>,
+    id: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let pool = state.pool.clone();
+    let id_str = id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_access(&sessions[0].user_id, &id_str, &mut conn)
+    })
+    .await??;
+
+    let members = web::block(move || {
+        let mut conn = state.pool.get()?;
+        list_members(&id, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(members))
+}
+
+pub(crate) async fn remove_store_member(
+    req: HttpRequest,
+    auth_token: AuthenticationToken,
+    _: RequirePermission<StoresWrite>,
+    path: web::Path<(String, String)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let (store_id, member_id) = path.into_inner();
+
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+    let actor = Actor::from_request(&req).signed_in(&sessions[0].user_id, &sessions[0].id);
+
+    let pool = state.pool.clone();
+    let id_str = store_id.clone();
+    let user_id = sessions[0].user_id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_access(&user_id, &id_str, &mut conn)
+    })
+    .await??;
+
+    unit_of_work(&state.pool, move |conn| {
+        let removed = remove_member(&sessions[0].user_id, &store_id, &member_id, conn)?;
+        record(
+            &actor,
+            Event {
+                action: "member.remove",
+                store_id: Some(&store_id),
+                target_id: Some(&member_id),
+                before: Some(serde_json::json!({ "role": removed.role })),
+                after: None,
+            },
+            conn,
+        )
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json("success"))
+}
+
+pub(crate) async fn transfer_store_ownership(
+    req: HttpRequest,
+    auth_token: AuthenticationToken,
+    _: RequirePermission<StoresWrite>,
+    id: web::Path<String>,
+    body: web::Json<TransferPayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+    let actor = Actor::from_request(&req).signed_in(&sessions[0].user_id, &sessions[0].id);
+
+    let pool = state.pool.clone();
+    let id_str = id.clone();
+    let user_id = sessions[0].user_id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_role(&user_id, &id_str, StoreRole::Owner, &mut conn)
+    })
+    .await??;
+
+    let members = unit_of_work(&state.pool, move |conn| {
+        let members = transfer_ownership(&sessions[0].user_id, &id, &body.user_id, conn)?;
+        record(
+            &actor,
+            Event {
+                action: "store.transfer",
+                store_id: Some(&id),
+                target_id: Some(&body.user_id),
+                before: Some(serde_json::json!({ "owner": sessions[0].user_id })),
+                after: Some(serde_json::json!({ "owner": body.user_id })),
+            },
+            conn,
+        )?;
+        Ok(members)
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(members))
+}
+
+pub(crate) async fn get_store_invitations(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<StoresRead>,
+    id: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let pool = state.pool.clone();
+    let id_str = id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_role(&sessions[0].user_id, &id_str, StoreRole::Manager, &mut conn)
+    })
+    .await??;
+
+    let invitations = web::block(move || {
+        let mut conn = state.pool.get()?;
+        list_invitations(&id, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(invitations))
+}
+
+pub(crate) async fn create_invitation(
+    req: HttpRequest,
+    auth_token: AuthenticationToken,
+    _: RequirePermission<StoresWrite>,
+    id: web::Path<String>,
+    body: web::Json<InvitationPayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+    let actor = Actor::from_request(&req).signed_in(&sessions[0].user_id, &sessions[0].id);
+
+    let pool = state.pool.clone();
+    let id_str = id.clone();
+    let user_id = sessions[0].user_id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_role(&user_id, &id_str, StoreRole::Manager, &mut conn)
+    })
+    .await??;
+
+    let ttl = state.invitation_ttl;
+    let issued = unit_of_work(&state.pool, move |conn| {
+        let issued = invite(&sessions[0].user_id, &id, &body, ttl, conn)?;
+        record(
+            &actor,
+            Event {
+                action: "invitation.create",
+                store_id: Some(&id),
+                target_id: Some(&issued.invitation.id),
+                before: None,
+                after: snapshot(&issued.invitation),
+            },
+            conn,
+        )?;
+        Ok(issued)
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(issued))
+}
+
+pub(crate) async fn revoke_invitation(
+    req: HttpRequest,
+    auth_token: AuthenticationToken,
+    _: RequirePermission<StoresWrite>,
+    path: web::Path<(String, String)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let (store_id, invitation_id) = path.into_inner();
+
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+    let actor = Actor::from_request(&req).signed_in(&sessions[0].user_id, &sessions[0].id);
+
+    let pool = state.pool.clone();
+    let id_str = store_id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_role(&sessions[0].user_id, &id_str, StoreRole::Manager, &mut conn)
+    })
+    .await??;
+
+    let invitation = unit_of_work(&state.pool, move |conn| {
+        let invitation = revoke(&store_id, &invitation_id, conn)?;
+        record(
+            &actor,
+            Event {
+                action: "invitation.revoke",
+                store_id: Some(&store_id),
+                target_id: Some(&invitation_id),
+                before: Some(serde_json::json!({ "status": InvitationStatus::Pending.as_str() })),
+                after: Some(serde_json::json!({ "status": invitation.status })),
+            },
+            conn,
+        )?;
+        Ok(invitation)
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(invitation))
+}
+
+async fn accept_invitation(
+    req: HttpRequest,
+    auth_token: AuthenticationToken,
+    _: RequirePermission<StoresRead>,
+    body: web::Json<InvitationTokenPayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+    let actor = Actor::from_request(&req).signed_in(&sessions[0].user_id, &sessions[0].id);
+
+    let invitation = unit_of_work(&state.pool, move |conn| {
+        let invitation = respond(
+            &sessions[0].user_id,
+            &body.token,
+            InvitationStatus::Accepted,
+            conn,
+        )?;
+        record(
+            &actor,
+            Event {
+                action: "invitation.accept",
+                store_id: Some(&invitation.store_id),
+                target_id: Some(&invitation.id),
+                before: Some(serde_json::json!({ "status": InvitationStatus::Pending.as_str() })),
+                after: Some(serde_json::json!({ "status": invitation.status })),
+            },
+            conn,
+        )?;
+        Ok(invitation)
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(invitation))
+}
+
+async fn decline_invitation(
+    req: HttpRequest,
+    auth_token: AuthenticationToken,
+    _: RequirePermission<StoresRead>,
+    body: web::Json<InvitationTokenPayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+    let actor = Actor::from_request(&req).signed_in(&sessions[0].user_id, &sessions[0].id);
+
+    let invitation = unit_of_work(&state.pool, move |conn| {
+        let invitation = respond(
+            &sessions[0].user_id,
+            &body.token,
+            InvitationStatus::Declined,
+            conn,
+        )?;
+        record(
+            &actor,
+            Event {
+                action: "invitation.decline",
+                store_id: Some(&invitation.store_id),
+                target_id: Some(&invitation.id),
+                before: Some(serde_json::json!({ "status": InvitationStatus::Pending.as_str() })),
+                after: Some(serde_json::json!({ "status": invitation.status })),
+            },
+            conn,
+        )?;
+        Ok(invitation)
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(invitation))
+}
+
+/// The role `user` holds in `store`, if they are a member at all.
+fn member_role(
+    user: &str,
+    store: &str,
+    conn: &mut PgConnection,
+) -> Result<Option<StoreRole>, ApiError> {
+    use crate::schema::user_stores::dsl::*;
+
+    user_stores
+        .find((user, store))
+        .select(role)
+        .first::<String>(conn)
+        .optional()?
+        .map(|value| value.parse())
+        .transpose()
+}
+
+/// Like `check_store_access`, but also requires the member to hold at least
+/// `required` in the store.
+pub(crate) fn check_store_role(
+    user: &str,
+    store: &str,
+    required: StoreRole,
+    conn: &mut PgConnection,
+) -> Result<Store, ApiError> {
+    let res = check_store_access(user, store, conn)?;
+    if member_role(user, store, conn)?.is_some_and(|held| held >= required) {
+        return Ok(res);
+    }
+    Err(ApiError::Forbidden(match required {
+        StoreRole::Owner => "Only the store owner can do this",
+        StoreRole::Manager => "Only the store owner and managers can do this",
+        StoreRole::Staff | StoreRole::Viewer => "Store viewers cannot do this",
+    }))
+}
+
+pub(crate) fn list_members(
+    store: &str,
+    conn: &mut PgConnection,
+) -> Result<Vec<StoreMember>, ApiError> {
+    use crate::schema::{user_stores, users};
+
+    let res = user_stores::table
+        .inner_join(users::table)
+        .filter(user_stores::store_id.eq(store))
+        .select((users::id, users::email, user_stores::role))
+        .order(users::email.asc())
+        .load::<(String, String, String)>(conn)?
+        .into_iter()
+        .map(|(user_id, email, role)| StoreMember {
+            user_id,
+            email,
+            role,
+        })
+        .collect();
+    Ok(res)
+}
+
+/// Members may leave a store themselves; removing someone else takes a role
+/// above theirs. The owner has to hand over ownership before leaving. Returns
+/// the membership that was removed.
+fn remove_member(
+    actor: &str,
+    store: &str,
+    member: &str,
+    conn: &mut PgConnection,
+) -> Result<UserStore, ApiError> {
+    use crate::schema::user_stores::dsl::*;
+
+    conn.transaction(|conn| {
+        let target = user_stores
+            .find((member, store))
+            .for_update()
+            .first::<UserStore>(conn)
+            .optional()?
+            .ok_or(ApiError::NotFound("Member not found"))?;
+        let target_role = target.role.parse::<StoreRole>()?;
+
+        if target_role == StoreRole::Owner {
+            return Err(ApiError::Conflict(
+                "Transfer ownership before removing the store owner".to_string(),
+            ));
+        }
+        if actor != member {
+            let actor_role = member_role(actor, store, conn)?;
+            if !actor_role.is_some_and(|held| held >= StoreRole::Manager && held > target_role) {
+                return Err(ApiError::Forbidden(
+                    "Only members with a higher role can remove this member",
+                ));
+            }
+        }
+
+        diesel::delete(user_stores.find((member, store))).execute(conn)?;
+        Ok(target)
+    })
+}
+
+/// Makes `member` the owner of `store`; the previous owner stays on as a
+/// manager.
+fn transfer_ownership(
+    owner: &str,
+    store: &str,
+    member: &str,
+    conn: &mut PgConnection,
+) -> Result<Vec<StoreMember>, ApiError> {
+    use crate::schema::user_stores::dsl::*;
+
+    if owner == member {
+        return Err(ApiError::Validation(
+            "You already own this store".to_string(),
+        ));
+    }
+
+    conn.transaction(|conn| {
+        let links = user_stores
+            .filter(store_id.eq(store))
+            .filter(user_id.eq_any([owner, member]))
+            .for_update()
+            .load::<UserStore>(conn)?;
+
+        if !links
+            .iter()
+            .any(|link| link.user_id == owner && link.role == StoreRole::Owner.as_str())
+        {
+            return Err(ApiError::Forbidden("Only the store owner can do this"));
+        }
+        if !links.iter().any(|link| link.user_id == member) {
+            return Err(ApiError::NotFound("Member not found"));
+        }
+
+        // Demoted first: a store has at most one owner at any time.
+        diesel::update(user_stores.find((owner, store)))
+            .set(role.eq(StoreRole::Manager.as_str()))
+            .execute(conn)?;
+        diesel::update(user_stores.find((member, store)))
+            .set(role.eq(StoreRole::Owner.as_str()))
+            .execute(conn)?;
+
+        list_members(store, conn)
+    })
+}
+
+fn list_invitations(
+    store: &str,
+    conn: &mut PgConnection,
+) -> Result<Vec<StoreInvitation>, ApiError> {
+    use crate::schema::store_invitations::dsl::*;
+
+    let res = store_invitations
+        .filter(store_id.eq(store))
+        .order(created_at.desc())
+        .select(StoreInvitation::as_select())
+        .load::<StoreInvitation>(conn)?;
+    Ok(res)
+}
+
+/// Marks a store's lapsed pending invitations expired, so the address can be
+/// invited again.
+fn expire_invitations(store: &str, conn: &mut PgConnection) -> Result<(), ApiError> {
+    use crate::schema::store_invitations::dsl::*;
+
+    diesel::update(
+        store_invitations
+            .filter(store_id.eq(store))
+            .filter(status.eq(InvitationStatus::Pending.as_str()))
+            .filter(expires_at.le(chrono::Local::now().naive_local())),
+    )
+    .set(status.eq(InvitationStatus::Expired.as_str()))
+    .execute(conn)?;
+    Ok(())
+}
+
+/// Invites an email address into `store`. Members can only hand out roles
+/// below their own, and ownership is never handed out this way.
+fn invite(
+    actor: &str,
+    store: &str,
+    body: &InvitationPayload,
+    ttl: chrono::Duration,
+    conn: &mut PgConnection,
+) -> Result<IssuedInvitation, ApiError> {
+    use crate::schema::{store_invitations, user_stores, users};
+
+    let address = body.email.trim().to_lowercase();
+    if !address.contains('@') {
+        return Err(ApiError::Validation(
+            "Invitation email is not a valid address".to_string(),
+        ));
+    }
+    if body.role == StoreRole::Owner {
+        return Err(ApiError::Validation(
+            "Ownership is handed over with a transfer, not an invitation".to_string(),
+        ));
+    }
+
+    conn.transaction(|conn| {
+        if member_role(actor, store, conn)?.is_none_or(|held| held <= body.role) {
+            return Err(ApiError::Forbidden(
+                "You can only invite members with a role below your own",
+            ));
+        }
+
+        let already_member: i64 = user_stores::table
+            .inner_join(users::table)
+            .filter(user_stores::store_id.eq(store))
+            .filter(lower(users::email).eq(&address))
+            .count()
+            .get_result(conn)?;
+        if already_member > 0 {
+            return Err(ApiError::Conflict(format!(
+                "{address} is already a member of this store"
+            )));
+        }
+
+        expire_invitations(store, conn)?;
+        let pending: i64 = store_invitations::table
+            .filter(store_invitations::store_id.eq(store))
+            .filter(store_invitations::email.eq(&address))
+            .filter(store_invitations::status.eq(InvitationStatus::Pending.as_str()))
+            .count()
+            .get_result(conn)?;
+        if pending > 0 {
+            return Err(ApiError::Conflict(format!(
+                "{address} already has a pending invitation"
+            )));
+        }
+
+        let token = random_token();
+        let invitation = diesel::insert_into(store_invitations::table)
+            .values(&NewStoreInvitation {
+                id: &Uuid::new_v4().to_string(),
+                store_id: store,
+                email: &address,
+                role: body.role.as_str(),
+                token_hash: &hash_token(&token),
+                invited_by: Some(actor),
+                expires_at: chrono::Local::now().naive_local() + ttl,
+            })
+            .returning(StoreInvitation::as_returning())
+            .get_result(conn)?;
+
+        Ok(IssuedInvitation { invitation, token })
+    })
+}
+
+fn revoke(
+    store: &str,
+    invitation: &str,
+    conn: &mut PgConnection,
+) -> Result<StoreInvitation, ApiError> {
+    use crate::schema::store_invitations::dsl::*;
+
+    conn.transaction(|conn| {
+        let current = store_invitations
+            .filter(id.eq(invitation))
+            .filter(store_id.eq(store))
+            .for_update()
+            .select(StoreInvitation::as_select())
+            .first::<StoreInvitation>(conn)
+            .optional()?
+            .ok_or(ApiError::NotFound("Invitation not found"))?;
+
+        if current.status != InvitationStatus::Pending.as_str() {
+            return Err(ApiError::InvalidTransition(format!(
+                "Cannot revoke an invitation that is {}",
+                current.status
+            )));
+        }
+
+        let res = diesel::update(store_invitations.find(&current.id))
+            .set(status.eq(InvitationStatus::Revoked.as_str()))
+            .returning(StoreInvitation::as_returning())
+            .get_result(conn)?;
+        Ok(res)
+    })
+}
+
+/// Accepts or declines the invitation behind `token` for `user`, who must be
+/// signed in with the address it was sent to. Accepting adds them to the
+/// store with the invited role.
+fn respond(
+    user: &str,
+    token: &str,
+    to: InvitationStatus,
+    conn: &mut PgConnection,
+) -> Result<StoreInvitation, ApiError> {
+    use crate::schema::{store_invitations, user_stores, users};
+
+    conn.transaction(|conn| {
+        let current = store_invitations::table
+            .filter(store_invitations::token_hash.eq(hash_token(token)))
+            .for_update()
+            .select(StoreInvitation::as_select())
+            .first::<StoreInvitation>(conn)
+            .optional()?
+            .ok_or(ApiError::NotFound("Invitation not found"))?;
+
+        let address = users::table
+            .find(user)
+            .select(users::email)
+            .first::<String>(conn)?;
+        if address.to_lowercase() != current.email {
+            return Err(ApiError::Forbidden(
+                "This invitation was sent to a different email address",
+            ));
+        }
+
+        if current.status != InvitationStatus::Pending.as_str() {
+            return Err(ApiError::InvalidTransition(format!(
+                "Cannot move an invitation from {} to {}",
+                current.status,
+                to.as_str()
+            )));
+        }
+        let now = chrono::Local::now().naive_local();
+        if current.expires_at <= now {
+            return Err(ApiError::InvalidTransition(
+                "Invitation has expired".to_string(),
+            ));
+        }
+
+        if to == InvitationStatus::Accepted {
+            diesel::insert_into(user_stores::table)
+                .values(&NewUserStore {
+                    user_id: user,
+                    store_id: &current.store_id,
+                    role: &current.role,
+                })
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+        }
+
+        let res = diesel::update(store_invitations::table.find(&current.id))
+            .set((
+                store_invitations::status.eq(to.as_str()),
+                store_invitations::responded_at.eq(now),
+            ))
+            .returning(StoreInvitation::as_returning())
+            .get_result(conn)?;
+        Ok(res)
+    })
+}
@@ -1,2 +1,17 @@
+pub mod audit;
+pub mod cart;
+pub mod category;
+pub mod collection;
+pub mod member;
+pub mod order;
+pub mod payment;
+pub mod permission;
+pub mod product;
+pub mod reservation;
+pub mod role;
+pub mod sale;
+pub mod search;
+pub mod stock;
 pub mod store;
 pub mod user;
+pub mod variant;
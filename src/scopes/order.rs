@@ -0,0 +1,398 @@
+use crate::{
+    db::unit_of_work,
+    errors::ApiError,
+    extractors::{
+        authentication_token::AuthenticationToken,
+        require_permission::{OrdersRead, OrdersWrite, RequirePermission},
+    },
+    models::{NewOrder, NewOrderItem, Order, OrderItem},
+    money::Money,
+    order_status::OrderStatus,
+    scopes::{
+        audit::{record, snapshot, Actor, Event},
+        member::{check_store_role, StoreRole},
+        reservation::commit_user_reservations,
+        stock::{lock_store_product, put_stock, take_stock, MovementKind},
+        store::{check_store_access, find_live_store, get_session},
+    },
+    AppState,
+};
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{Duration, NaiveDate, NaiveTime};
+use diesel::{
+    Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl,
+    SelectableHelper,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct CheckoutLine {
+    product_id: String,
+    quantity: i32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct CheckoutPayload {
+    items: Vec<CheckoutLine>,
+}
+
+impl CheckoutPayload {
+    /// Folds repeated products into one line. The map is ordered by product
+    /// id so concurrent checkouts always lock products in the same order.
+    fn lines(&self) -> Result<BTreeMap<&str, i32>, ApiError> {
+        if self.items.is_empty() {
+            return Err(ApiError::Validation(
+                "An order needs at least one item".to_string(),
+            ));
+        }
+
+        let mut lines = BTreeMap::new();
+        for item in &self.items {
+            if item.quantity <= 0 {
+                return Err(ApiError::Validation(
+                    "Quantity must be greater than zero".to_string(),
+                ));
+            }
+            let quantity = lines.entry(item.product_id.as_str()).or_insert(0i32);
+            *quantity = quantity.saturating_add(item.quantity);
+        }
+        Ok(lines)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct OrderFilter {
+    status: Option<OrderStatus>,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct OrderStatusPayload {
+    status: OrderStatus,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OrderDetail {
+    #[serde(flatten)]
+    order: Order,
+    items: Vec<OrderItem>,
+}
+
+pub(crate) async fn checkout(
+    auth_token: AuthenticationToken,
+    store_id: web::Path<String>,
+    body: web::Json<CheckoutPayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let order = web::block(move || {
+        let mut conn = state.pool.get()?;
+        place_order(&store_id, &sessions[0].user_id, &body, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(order))
+}
+
+pub(crate) async fn get_store_orders(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<OrdersRead>,
+    store_id: web::Path<String>,
+    filter: web::Query<OrderFilter>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let pool = state.pool.clone();
+    let id_str = store_id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_access(&sessions[0].user_id, &id_str, &mut conn)
+    })
+    .await??;
+
+    let orders = web::block(move || {
+        let mut conn = state.pool.get()?;
+        list_store_orders(&store_id, &filter, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(orders))
+}
+
+pub(crate) async fn get_store_order(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<OrdersRead>,
+    path: web::Path<(String, String)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let (store_id, order_id) = path.into_inner();
+
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let pool = state.pool.clone();
+    let id_str = store_id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_access(&sessions[0].user_id, &id_str, &mut conn)
+    })
+    .await??;
+
+    let order = web::block(move || {
+        let mut conn = state.pool.get()?;
+        let order = find_store_order(&store_id, &order_id, &mut conn)?;
+        get_order_detail(order, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(order))
+}
+
+pub(crate) async fn update_order_status(
+    req: HttpRequest,
+    auth_token: AuthenticationToken,
+    _: RequirePermission<OrdersWrite>,
+    path: web::Path<(String, String)>,
+    body: web::Json<OrderStatusPayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let (store_id, order_id) = path.into_inner();
+
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+    let actor = Actor::from_request(&req).signed_in(&sessions[0].user_id, &sessions[0].id);
+
+    let pool = state.pool.clone();
+    let id_str = store_id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_role(&sessions[0].user_id, &id_str, StoreRole::Staff, &mut conn)
+    })
+    .await??;
+
+    let order = unit_of_work(&state.pool, move |conn| {
+        let before = lock_store_order(&store_id, &order_id, conn)?;
+        let order = move_order(&before, body.status, conn)?;
+        record(
+            &actor,
+            Event {
+                action: "order.status.update",
+                store_id: Some(&store_id),
+                target_id: Some(&order.id),
+                before: snapshot(&before),
+                after: snapshot(&order),
+            },
+            conn,
+        )?;
+        get_order_detail(order, conn)
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(order))
+}
+
+/// Snapshots each product's title and price into the order and takes the
+/// ordered quantity out of stock, all or nothing. Units the buyer reserved are
+/// committed into the order.
+fn place_order(
+    store: &str,
+    user: &str,
+    body: &CheckoutPayload,
+    conn: &mut PgConnection,
+) -> Result<OrderDetail, ApiError> {
+    use crate::schema::{order_items, orders};
+
+    let lines = body.lines()?;
+
+    conn.transaction(|conn| {
+        find_live_store(store, conn)?;
+
+        let order_id = Uuid::new_v4().to_string();
+        let mut purchased = Vec::with_capacity(lines.len());
+        for (product_id, quantity) in lines {
+            let product = lock_store_product(store, product_id, conn)?;
+
+            commit_user_reservations(&product.id, user, &order_id, conn)?;
+            take_stock(
+                &product,
+                quantity,
+                MovementKind::Sale,
+                Some(&order_id),
+                Some(user),
+                conn,
+            )?;
+
+            purchased.push((product, quantity));
+        }
+
+        // Lines are never empty, and every line must share the first one's
+        // currency.
+        let currency = purchased[0].0.price.currency();
+        let total = Money::sum(
+            currency,
+            purchased
+                .iter()
+                .map(|(product, quantity)| product.price.checked_mul(*quantity))
+                .collect::<Result<Vec<Money>, _>>()?,
+        )?;
+
+        let new_order = NewOrder {
+            id: &order_id,
+            store_id: store,
+            user_id: user,
+            total: &total.minor(),
+            currency: currency.as_str(),
+        };
+        let order = diesel::insert_into(orders::table)
+            .values(&new_order)
+            .returning(Order::as_returning())
+            .get_result(conn)?;
+
+        for (product, quantity) in &purchased {
+            let new_item = NewOrderItem {
+                id: &Uuid::new_v4().to_string(),
+                order_id: &order.id,
+                product_id: Some(&product.id),
+                title: &product.title,
+                unit_price: &product.price.minor(),
+                currency: currency.as_str(),
+                quantity,
+            };
+            diesel::insert_into(order_items::table)
+                .values(&new_item)
+                .execute(conn)?;
+        }
+
+        get_order_detail(order, conn)
+    })
+}
+
+fn list_store_orders(
+    store: &str,
+    filter: &OrderFilter,
+    conn: &mut PgConnection,
+) -> Result<Vec<Order>, ApiError> {
+    use crate::schema::orders::dsl::*;
+
+    let mut query = orders
+        .filter(store_id.eq(store))
+        .select(Order::as_select())
+        .into_boxed();
+
+    if let Some(order_status) = filter.status {
+        query = query.filter(status.eq(order_status.as_str()));
+    }
+    if let Some(from) = filter.from {
+        query = query.filter(created_at.ge(from.and_time(NaiveTime::default())));
+    }
+    if let Some(to) = filter.to {
+        let until = (to + Duration::days(1)).and_time(NaiveTime::default());
+        query = query.filter(created_at.lt(until));
+    }
+
+    let res = query.order(created_at.desc()).load::<Order>(conn)?;
+    Ok(res)
+}
+
+fn find_store_order(store: &str, order: &str, conn: &mut PgConnection) -> Result<Order, ApiError> {
+    use crate::schema::orders::dsl::*;
+
+    let res = orders
+        .filter(id.eq(order))
+        .filter(store_id.eq(store))
+        .select(Order::as_select())
+        .first::<Order>(conn)
+        .optional()?
+        .ok_or(ApiError::NotFound("Order not found"))?;
+    Ok(res)
+}
+
+fn get_order_detail(order: Order, conn: &mut PgConnection) -> Result<OrderDetail, ApiError> {
+    use crate::schema::order_items::dsl::*;
+
+    let items = order_items
+        .filter(order_id.eq(&order.id))
+        .order(title.asc())
+        .select(OrderItem::as_select())
+        .load::<OrderItem>(conn)?;
+
+    Ok(OrderDetail { order, items })
+}
+
+fn lock_store_order(store: &str, order: &str, conn: &mut PgConnection) -> Result<Order, ApiError> {
+    use crate::schema::orders::dsl::*;
+
+    let res = orders
+        .filter(id.eq(order))
+        .filter(store_id.eq(store))
+        .for_update()
+        .select(Order::as_select())
+        .first::<Order>(conn)
+        .optional()?
+        .ok_or(ApiError::NotFound("Order not found"))?;
+    Ok(res)
+}
+
+/// Moves an already locked order along the state machine; a cancelled order
+/// puts its items back in stock.
+pub(crate) fn move_order(
+    current: &Order,
+    to: OrderStatus,
+    conn: &mut PgConnection,
+) -> Result<Order, ApiError> {
+    use crate::schema::{order_items, orders};
+
+    let next = current.status.parse::<OrderStatus>()?.transition(to)?;
+
+    if next == OrderStatus::Cancelled {
+        let items = order_items::table
+            .filter(order_items::order_id.eq(&current.id))
+            .select(OrderItem::as_select())
+            .load::<OrderItem>(conn)?;
+        for item in items {
+            if let Some(product) = &item.product_id {
+                put_stock(
+                    &current.store_id,
+                    product,
+                    item.quantity,
+                    MovementKind::Return,
+                    Some(&current.id),
+                    None,
+                    conn,
+                )?;
+            }
+        }
+    }
+
+    let res = diesel::update(orders::table.find(&current.id))
+        .set((
+            orders::status.eq(next.as_str()),
+            orders::updated_at.eq(chrono::Local::now().naive_local()),
+        ))
+        .returning(Order::as_returning())
+        .get_result(conn)?;
+    Ok(res)
+}
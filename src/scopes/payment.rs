@@ -0,0 +1,820 @@
+use crate::{
+    errors::ApiError,
+    extractors::{
+        authentication_token::AuthenticationToken,
+        require_permission::{PaymentsRead, PaymentsWrite, RequirePermission},
+    },
+    models::{NewPayment, NewPaymentEvent, Order, Payment, PaymentEvent},
+    money::{Currency, Money},
+    order_status::OrderStatus,
+    payments::{
+        AuthorizeRequest, PaymentProvider, PaymentProviders, PaymentStatus, ProviderError,
+        ProviderResponse,
+    },
+    scopes::{
+        audit::{record, snapshot, Actor, Event},
+        member::{check_store_role, StoreRole},
+        order::move_order,
+        store::{check_store_access, find_live_store, get_session},
+    },
+    AppState,
+};
+use actix_web::{web, HttpRequest, HttpResponse, Scope};
+use chrono::Duration;
+use diesel::{
+    Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl,
+    SelectableHelper,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub fn payment_scope() -> Scope {
+    web::scope("payments").route("/webhooks/{provider}", web::post().to(receive_webhook))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct PaymentPayload {
+    /// Decimal string in `currency`, e.g. `"42.00"`.
+    amount: String,
+    currency: Currency,
+    payment_method: String,
+    order_id: Option<String>,
+}
+
+impl PaymentPayload {
+    fn validate(&self) -> Result<Money, ApiError> {
+        let amount = Money::parse(&self.amount, self.currency)?;
+        if amount.minor() <= 0 {
+            return Err(ApiError::Validation(
+                "Payment amount must be greater than zero".to_string(),
+            ));
+        }
+        if self.payment_method.trim().is_empty() {
+            return Err(ApiError::Validation(
+                "Payment method must not be empty".to_string(),
+            ));
+        }
+        Ok(amount)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct RefundPayload {
+    /// Decimal string in the payment's currency; refunds whatever is left
+    /// when omitted.
+    amount: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PaymentDetail {
+    #[serde(flatten)]
+    payment: Payment,
+    events: Vec<PaymentEvent>,
+}
+
+enum Action {
+    Authorize(String),
+    Confirm,
+    Capture,
+    Void,
+    Refund(Option<String>),
+}
+
+impl Action {
+    fn name(&self) -> &'static str {
+        match self {
+            Action::Authorize(_) => "authorize",
+            Action::Confirm => "confirm",
+            Action::Capture => "capture",
+            Action::Void => "void",
+            Action::Refund(_) => "refund",
+        }
+    }
+}
+
+pub(crate) async fn create_payment(
+    req: HttpRequest,
+    auth_token: AuthenticationToken,
+    store_id: web::Path<String>,
+    body: web::Json<PaymentPayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+    let actor = Actor::from_request(&req).signed_in(&sessions[0].user_id, &sessions[0].id);
+
+    let payment = web::block(move || {
+        let mut conn = state.pool.get()?;
+        let payment = add_payment(
+            &store_id,
+            &sessions[0].user_id,
+            &body,
+            &state.payments,
+            &mut conn,
+        )?;
+        run_action(
+            &store_id,
+            &payment.id,
+            Action::Authorize(body.payment_method.clone()),
+            &state.payments,
+            &actor,
+            &mut conn,
+        )
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(payment))
+}
+
+pub(crate) async fn confirm_payment(
+    req: HttpRequest,
+    auth_token: AuthenticationToken,
+    path: web::Path<(String, String)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let (store_id, payment_id) = path.into_inner();
+
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+    let actor = Actor::from_request(&req).signed_in(&sessions[0].user_id, &sessions[0].id);
+
+    let payment = web::block(move || {
+        let mut conn = state.pool.get()?;
+        let payment = find_store_payment(&store_id, &payment_id, &mut conn)?;
+        if payment.user_id != sessions[0].user_id {
+            return Err(ApiError::NotFound("Payment not found"));
+        }
+        run_action(
+            &store_id,
+            &payment_id,
+            Action::Confirm,
+            &state.payments,
+            &actor,
+            &mut conn,
+        )
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(payment))
+}
+
+pub(crate) async fn get_store_payments(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<PaymentsRead>,
+    store_id: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let pool = state.pool.clone();
+    let id_str = store_id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_access(&sessions[0].user_id, &id_str, &mut conn)
+    })
+    .await??;
+
+    let payments = web::block(move || {
+        let mut conn = state.pool.get()?;
+        list_store_payments(&store_id, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(payments))
+}
+
+pub(crate) async fn get_store_payment(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<PaymentsRead>,
+    path: web::Path<(String, String)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let (store_id, payment_id) = path.into_inner();
+
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let pool = state.pool.clone();
+    let id_str = store_id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_access(&sessions[0].user_id, &id_str, &mut conn)
+    })
+    .await??;
+
+    let payment = web::block(move || {
+        let mut conn = state.pool.get()?;
+        let payment = find_store_payment(&store_id, &payment_id, &mut conn)?;
+        get_payment_detail(payment, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(payment))
+}
+
+pub(crate) async fn capture_payment(
+    req: HttpRequest,
+    auth_token: AuthenticationToken,
+    permission: RequirePermission<PaymentsWrite>,
+    path: web::Path<(String, String)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    merchant_action(req, auth_token, permission, path, Action::Capture, state).await
+}
+
+pub(crate) async fn void_payment(
+    req: HttpRequest,
+    auth_token: AuthenticationToken,
+    permission: RequirePermission<PaymentsWrite>,
+    path: web::Path<(String, String)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    merchant_action(req, auth_token, permission, path, Action::Void, state).await
+}
+
+pub(crate) async fn refund_payment(
+    req: HttpRequest,
+    auth_token: AuthenticationToken,
+    permission: RequirePermission<PaymentsWrite>,
+    path: web::Path<(String, String)>,
+    body: web::Json<RefundPayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let action = Action::Refund(body.into_inner().amount);
+    merchant_action(req, auth_token, permission, path, action, state).await
+}
+
+async fn merchant_action(
+    req: HttpRequest,
+    auth_token: AuthenticationToken,
+    _: RequirePermission<PaymentsWrite>,
+    path: web::Path<(String, String)>,
+    action: Action,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let (store_id, payment_id) = path.into_inner();
+
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+    let actor = Actor::from_request(&req).signed_in(&sessions[0].user_id, &sessions[0].id);
+
+    let pool = state.pool.clone();
+    let id_str = store_id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_role(&sessions[0].user_id, &id_str, StoreRole::Staff, &mut conn)
+    })
+    .await??;
+
+    let payment = web::block(move || {
+        let mut conn = state.pool.get()?;
+        run_action(
+            &store_id,
+            &payment_id,
+            action,
+            &state.payments,
+            &actor,
+            &mut conn,
+        )
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(payment))
+}
+
+async fn receive_webhook(
+    req: HttpRequest,
+    provider: web::Path<String>,
+    body: web::Bytes,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let provider = state
+        .payments
+        .find(&provider)
+        .ok_or(ApiError::NotFound("Unknown payment provider"))?;
+    let event = provider.verify_webhook(req.headers(), &body)?;
+
+    web::block(move || {
+        let mut conn = state.pool.get()?;
+        apply_webhook(
+            provider.name(),
+            &event.reference,
+            event.status,
+            event.message.as_deref(),
+            &mut conn,
+        )
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json("success"))
+}
+
+/// Records the attempt before the provider is called, so it is kept even if
+/// the provider never answers. An order takes a new payment only once every
+/// earlier one failed, was declined or was voided.
+fn add_payment(
+    store: &str,
+    user: &str,
+    body: &PaymentPayload,
+    providers: &PaymentProviders,
+    conn: &mut PgConnection,
+) -> Result<Payment, ApiError> {
+    use crate::schema::{orders, payments};
+
+    let amount = body.validate()?;
+    let currency = amount.currency();
+
+    conn.transaction(|conn| {
+        find_live_store(store, conn)?;
+
+        if let Some(order) = &body.order_id {
+            let order = orders::table
+                .filter(orders::id.eq(order))
+                .filter(orders::store_id.eq(store))
+                .filter(orders::user_id.eq(user))
+                .for_update()
+                .select(Order::as_select())
+                .first::<Order>(conn)
+                .optional()?
+                .ok_or(ApiError::NotFound("Order not found"))?;
+
+            if order.status.parse::<OrderStatus>()? != OrderStatus::Pending {
+                return Err(ApiError::InvalidTransition(format!(
+                    "Order is already {}",
+                    order.status
+                )));
+            }
+            if order.total != amount {
+                return Err(ApiError::Validation(
+                    "Payment amount must match the order total".to_string(),
+                ));
+            }
+
+            // The order row is locked, so two attempts cannot both get here.
+            let abandoned = [
+                PaymentStatus::Failed,
+                PaymentStatus::Voided,
+                PaymentStatus::Declined,
+            ]
+            .map(PaymentStatus::as_str);
+            let live = payments::table
+                .filter(payments::order_id.eq(&order.id))
+                .filter(payments::status.ne_all(abandoned))
+                .count()
+                .get_result::<i64>(conn)?;
+            if live > 0 {
+                return Err(ApiError::Conflict(
+                    "Order already has a payment in progress".to_string(),
+                ));
+            }
+        }
+
+        let new_payment = NewPayment {
+            id: &Uuid::new_v4().to_string(),
+            store_id: store,
+            order_id: body.order_id.as_deref(),
+            user_id: user,
+            provider: providers.default_provider().name(),
+            amount: &amount.minor(),
+            currency: currency.as_str(),
+        };
+        let payment = diesel::insert_into(payments::table)
+            .values(&new_payment)
+            .returning(Payment::as_returning())
+            .get_result(conn)?;
+
+        add_event(
+            &payment.id,
+            "create",
+            PaymentStatus::Created,
+            Some(payment.amount.minor()),
+            None,
+            conn,
+        )?;
+        Ok(payment)
+    })
+}
+
+/// How long a provider call may stay pending before another action on the
+/// payment is let through, in case the process died waiting for the answer.
+const PENDING_TIMEOUT_MINUTES: i64 = 5;
+
+/// Runs one provider call against a payment and records what came back as a
+/// payment event. The payment is only locked while it is marked pending and
+/// while the answer is applied; the provider itself is called with no lock or
+/// transaction held. Provider failures are recorded too, and only surfaced
+/// once that record is committed.
+fn run_action(
+    store: &str,
+    payment: &str,
+    action: Action,
+    providers: &PaymentProviders,
+    actor: &Actor,
+    conn: &mut PgConnection,
+) -> Result<PaymentDetail, ApiError> {
+    let (pending, provider, refund) = begin_action(store, payment, &action, providers, conn)?;
+
+    let reference = pending.provider_reference.clone().unwrap_or_default();
+    let response = match &action {
+        Action::Authorize(payment_method) => provider.authorize(&AuthorizeRequest {
+            payment_id: &pending.id,
+            amount: pending.amount.minor(),
+            currency: pending.amount.currency().as_str(),
+            payment_method,
+        }),
+        Action::Confirm => provider.confirm(&reference),
+        Action::Capture => provider.capture(&reference, pending.amount.minor()),
+        Action::Void => provider.void(&reference),
+        Action::Refund(_) => provider.refund(&reference, refund.minor()),
+    };
+
+    let (payment, failure) = finish_action(&pending, &action, refund, response, actor, conn)?;
+    if let Some(err) = failure {
+        return Err(err);
+    }
+    get_payment_detail(payment, conn)
+}
+
+/// Checks the action is allowed and marks the payment as pending on it, so no
+/// other action starts until `finish_action` has recorded the answer. Returns
+/// the amount a refund is for, or what is left to refund for other actions.
+fn begin_action(
+    store: &str,
+    payment: &str,
+    action: &Action,
+    providers: &PaymentProviders,
+    conn: &mut PgConnection,
+) -> Result<(Payment, Arc<dyn PaymentProvider>, Money), ApiError> {
+    use crate::schema::payments;
+
+    conn.transaction(|conn| {
+        let current = payments::table
+            .filter(payments::id.eq(payment))
+            .filter(payments::store_id.eq(store))
+            .for_update()
+            .select(Payment::as_select())
+            .first::<Payment>(conn)
+            .optional()?
+            .ok_or(ApiError::NotFound("Payment not found"))?;
+
+        let stale = chrono::Local::now().naive_local() - Duration::minutes(PENDING_TIMEOUT_MINUTES);
+        if let (Some(pending), Some(since)) = (&current.pending_action, current.pending_since) {
+            if since > stale {
+                return Err(ApiError::Conflict(format!(
+                    "Payment {pending} is still in progress"
+                )));
+            }
+        }
+
+        let status = current.status.parse::<PaymentStatus>()?;
+        let provider = providers.find(&current.provider).ok_or_else(|| {
+            ApiError::Internal(format!("Payment provider {} is gone", current.provider))
+        })?;
+        let remaining = current.amount.checked_sub(current.refunded_amount)?;
+        let refund = match action {
+            Action::Refund(Some(amount)) => Money::parse(amount, current.amount.currency())?,
+            _ => remaining,
+        };
+
+        match action {
+            Action::Authorize(_) => expect_status(status, &[PaymentStatus::Created])?,
+            Action::Confirm => expect_status(status, &[PaymentStatus::RequiresAction])?,
+            Action::Capture => expect_status(status, &[PaymentStatus::Authorized])?,
+            Action::Void => {
+                status.transition(PaymentStatus::Voided)?;
+            }
+            Action::Refund(_) => {
+                status.transition(PaymentStatus::Refunded)?;
+                if refund.minor() <= 0 || refund.minor() > remaining.minor() {
+                    return Err(ApiError::Validation(format!(
+                        "Refund must be greater than zero and at most {remaining}"
+                    )));
+                }
+            }
+        }
+
+        let pending = set_pending(&current.id, Some(action.name()), conn)?;
+        Ok((pending, provider, refund))
+    })
+}
+
+/// Applies the provider's answer to the call `pending` was marked for, clears
+/// the mark and audits the outcome. A failed call is recorded and handed back
+/// rather than returned as an error, so that the record is committed.
+fn finish_action(
+    pending: &Payment,
+    action: &Action,
+    refund: Money,
+    response: Result<ProviderResponse, ProviderError>,
+    actor: &Actor,
+    conn: &mut PgConnection,
+) -> Result<(Payment, Option<ApiError>), ApiError> {
+    conn.transaction(|conn| {
+        let (payment, failure) = settle_action(pending, action, refund, response, conn)?;
+        record(
+            actor,
+            Event {
+                action: &format!("payment.{}", action.name()),
+                store_id: Some(&payment.store_id),
+                target_id: Some(&payment.id),
+                before: snapshot(pending),
+                after: snapshot(&payment),
+            },
+            conn,
+        )?;
+        Ok((payment, failure))
+    })
+}
+
+fn settle_action(
+    pending: &Payment,
+    action: &Action,
+    refund: Money,
+    response: Result<ProviderResponse, ProviderError>,
+    conn: &mut PgConnection,
+) -> Result<(Payment, Option<ApiError>), ApiError> {
+    use crate::schema::payments;
+
+    let current = payments::table
+        .find(&pending.id)
+        .for_update()
+        .select(Payment::as_select())
+        .first::<Payment>(conn)?;
+    if current.pending_since != pending.pending_since {
+        return Err(ApiError::Conflict(
+            "Payment was taken over while waiting for the provider".to_string(),
+        ));
+    }
+    let current = set_pending(&current.id, None, conn)?;
+    let status = current.status.parse::<PaymentStatus>()?;
+
+    let response = match response {
+        Ok(response) => response,
+        Err(err) => {
+            let failed = match action {
+                Action::Authorize(_) | Action::Confirm
+                    if status.next().contains(&PaymentStatus::Failed) =>
+                {
+                    PaymentStatus::Failed
+                }
+                _ => status,
+            };
+            let payment = set_status(&current, failed, None, None, conn)?;
+            add_event(
+                &current.id,
+                action.name(),
+                failed,
+                None,
+                Some(&err.to_string()),
+                conn,
+            )?;
+            return Ok((payment, Some(err.into())));
+        }
+    };
+
+    let (next, refunded, amount) = match action {
+        Action::Refund(_) => {
+            let refunded = current.refunded_amount.checked_add(refund)?;
+            let next = if refunded == current.amount {
+                PaymentStatus::Refunded
+            } else {
+                PaymentStatus::PartiallyRefunded
+            };
+            (next, Some(refunded), Some(refund.minor()))
+        }
+        Action::Capture => (response.status, None, Some(current.amount.minor())),
+        _ => (response.status, None, None),
+    };
+    let next = match status.transition(next) {
+        Ok(next) => next,
+        // A webhook delivered the same outcome while the provider was
+        // being called.
+        Err(_) if next == status && refunded.is_none() => status,
+        Err(err) => {
+            add_event(
+                &current.id,
+                action.name(),
+                status,
+                amount,
+                Some(&err.to_string()),
+                conn,
+            )?;
+            return Ok((current, Some(err)));
+        }
+    };
+
+    let reference = match action {
+        Action::Authorize(_) => Some(response.reference.as_str()),
+        _ => None,
+    };
+    let payment = set_status(&current, next, reference, refunded, conn)?;
+    add_event(
+        &current.id,
+        action.name(),
+        next,
+        amount,
+        response.message.as_deref(),
+        conn,
+    )?;
+    sync_order(&payment, next, conn)?;
+
+    Ok((payment, None))
+}
+
+fn apply_webhook(
+    provider: &str,
+    reference: &str,
+    to: PaymentStatus,
+    message: Option<&str>,
+    conn: &mut PgConnection,
+) -> Result<(), ApiError> {
+    use crate::schema::payments;
+
+    conn.transaction(|conn| {
+        let current = payments::table
+            .filter(payments::provider.eq(provider))
+            .filter(payments::provider_reference.eq(reference))
+            .for_update()
+            .select(Payment::as_select())
+            .first::<Payment>(conn)
+            .optional()?
+            .ok_or(ApiError::NotFound("Payment not found"))?;
+        let status = current.status.parse::<PaymentStatus>()?;
+
+        // Providers retry deliveries, so a status we already hold is a no-op.
+        if status == to {
+            return Ok(());
+        }
+        let next = status.transition(to)?;
+
+        let refunded = (next == PaymentStatus::Refunded).then_some(current.amount);
+        let payment = set_status(&current, next, None, refunded, conn)?;
+        add_event(&current.id, "webhook", next, None, message, conn)?;
+        sync_order(&payment, next, conn)
+    })
+}
+
+fn expect_status(status: PaymentStatus, allowed: &[PaymentStatus]) -> Result<(), ApiError> {
+    if allowed.contains(&status) {
+        Ok(())
+    } else {
+        Err(ApiError::InvalidTransition(format!("Payment is {status}")))
+    }
+}
+
+fn set_status(
+    current: &Payment,
+    to: PaymentStatus,
+    reference: Option<&str>,
+    refunded: Option<Money>,
+    conn: &mut PgConnection,
+) -> Result<Payment, ApiError> {
+    use crate::schema::payments::dsl::*;
+
+    let res = diesel::update(payments.find(&current.id))
+        .set((
+            status.eq(to.as_str()),
+            provider_reference.eq(reference.or(current.provider_reference.as_deref())),
+            refunded_amount.eq(refunded.unwrap_or(current.refunded_amount).minor()),
+            updated_at.eq(chrono::Local::now().naive_local()),
+        ))
+        .returning(Payment::as_returning())
+        .get_result(conn)?;
+    Ok(res)
+}
+
+/// Marks the payment as waiting on the provider for `action`, or clears the
+/// mark when `action` is `None`.
+fn set_pending(
+    payment: &str,
+    action: Option<&str>,
+    conn: &mut PgConnection,
+) -> Result<Payment, ApiError> {
+    use crate::schema::payments::dsl::*;
+
+    let since = action.map(|_| chrono::Local::now().naive_local());
+    let res = diesel::update(payments.find(payment))
+        .set((pending_action.eq(action), pending_since.eq(since)))
+        .returning(Payment::as_returning())
+        .get_result(conn)?;
+    Ok(res)
+}
+
+fn add_event(
+    payment: &str,
+    event_action: &str,
+    to: PaymentStatus,
+    event_amount: Option<i64>,
+    event_message: Option<&str>,
+    conn: &mut PgConnection,
+) -> Result<(), ApiError> {
+    use crate::schema::payment_events::dsl::*;
+
+    let new_event = NewPaymentEvent {
+        id: &Uuid::new_v4().to_string(),
+        payment_id: payment,
+        action: event_action,
+        status: to.as_str(),
+        amount: event_amount.as_ref(),
+        message: event_message,
+    };
+
+    diesel::insert_into(payment_events)
+        .values(&new_event)
+        .execute(conn)?;
+    Ok(())
+}
+
+/// A captured payment pays for its order and a fully refunded one refunds it.
+fn sync_order(
+    payment: &Payment,
+    status: PaymentStatus,
+    conn: &mut PgConnection,
+) -> Result<(), ApiError> {
+    use crate::schema::orders;
+
+    let order = match &payment.order_id {
+        Some(order) => order,
+        None => return Ok(()),
+    };
+    let to = match status {
+        PaymentStatus::Captured => OrderStatus::Paid,
+        PaymentStatus::Refunded => OrderStatus::Refunded,
+        _ => return Ok(()),
+    };
+
+    let order = orders::table
+        .find(order)
+        .for_update()
+        .select(Order::as_select())
+        .first::<Order>(conn)?;
+    if order.status.parse::<OrderStatus>()?.next().contains(&to) {
+        move_order(&order, to, conn)?;
+    }
+    Ok(())
+}
+
+fn list_store_payments(store: &str, conn: &mut PgConnection) -> Result<Vec<Payment>, ApiError> {
+    use crate::schema::payments::dsl::*;
+
+    let res = payments
+        .filter(store_id.eq(store))
+        .order(created_at.desc())
+        .select(Payment::as_select())
+        .load::<Payment>(conn)?;
+    Ok(res)
+}
+
+fn find_store_payment(
+    store: &str,
+    payment: &str,
+    conn: &mut PgConnection,
+) -> Result<Payment, ApiError> {
+    use crate::schema::payments::dsl::*;
+
+    let res = payments
+        .filter(id.eq(payment))
+        .filter(store_id.eq(store))
+        .select(Payment::as_select())
+        .first::<Payment>(conn)
+        .optional()?
+        .ok_or(ApiError::NotFound("Payment not found"))?;
+    Ok(res)
+}
+
+fn get_payment_detail(
+    payment: Payment,
+    conn: &mut PgConnection,
+) -> Result<PaymentDetail, ApiError> {
+    use crate::schema::payment_events::dsl::*;
+
+    let events = payment_events
+        .filter(payment_id.eq(&payment.id))
+        .order(created_at.asc())
+        .select(PaymentEvent::as_select())
+        .load::<PaymentEvent>(conn)?;
+
+    Ok(PaymentDetail { payment, events })
+}
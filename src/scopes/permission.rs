@@ -0,0 +1,170 @@
+use crate::{
+    errors::ApiError,
+    extractors::require_permission::{RequirePermission, RolesRead, RolesWrite},
+    models::{NewPermission, Permission},
+    AppState,
+};
+use actix_web::{web, HttpResponse, Scope};
+use diesel::{
+    Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub fn permission_scope() -> Scope {
+    web::scope("permissions")
+        .route("", web::get().to(get_permissions))
+        .route("", web::post().to(create_permission))
+        .route("/{id}", web::get().to(get_permission))
+        .route("/{id}", web::put().to(update_permission))
+        .route("/{id}", web::delete().to(delete_permission))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct PermissionPayload {
+    name: String,
+}
+
+async fn get_permissions(
+    _: RequirePermission<RolesRead>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let permissions = web::block(move || {
+        let mut conn = state.pool.get()?;
+        list_permissions(&mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(permissions))
+}
+
+async fn create_permission(
+    _: RequirePermission<RolesWrite>,
+    body: web::Json<PermissionPayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let permission = web::block(move || {
+        let mut conn = state.pool.get()?;
+        add_permission(&body.name, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(permission))
+}
+
+async fn get_permission(
+    _: RequirePermission<RolesRead>,
+    id: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let permission = web::block(move || {
+        let mut conn = state.pool.get()?;
+        find_permission(&id, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(permission))
+}
+
+async fn update_permission(
+    _: RequirePermission<RolesWrite>,
+    id: web::Path<String>,
+    body: web::Json<PermissionPayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let permission = web::block(move || {
+        let mut conn = state.pool.get()?;
+        edit_permission(&id, &body.name, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(permission))
+}
+
+async fn delete_permission(
+    _: RequirePermission<RolesWrite>,
+    id: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    web::block(move || {
+        let mut conn = state.pool.get()?;
+        remove_permission(&id, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json("success"))
+}
+
+fn list_permissions(conn: &mut PgConnection) -> Result<Vec<Permission>, ApiError> {
+    use crate::schema::permissions::dsl::*;
+
+    let res = permissions.order(name.asc()).load::<Permission>(conn)?;
+    Ok(res)
+}
+
+fn find_permission(permission_id: &str, conn: &mut PgConnection) -> Result<Permission, ApiError> {
+    use crate::schema::permissions::dsl::*;
+
+    let res = permissions
+        .find(permission_id)
+        .first::<Permission>(conn)
+        .optional()?
+        .ok_or(ApiError::NotFound("Permission not found"))?;
+    Ok(res)
+}
+
+fn add_permission(permission_name: &str, conn: &mut PgConnection) -> Result<Permission, ApiError> {
+    use crate::schema::permissions::dsl::*;
+
+    let permission_name = permission_name.trim();
+    if permission_name.is_empty() {
+        return Err(ApiError::Validation(
+            "Permission name must not be empty".to_string(),
+        ));
+    }
+
+    let new_permission = NewPermission {
+        id: &Uuid::new_v4().to_string(),
+        name: permission_name,
+    };
+
+    let res = diesel::insert_into(permissions)
+        .values(&new_permission)
+        .get_result(conn)?;
+    Ok(res)
+}
+
+fn edit_permission(
+    permission_id: &str,
+    permission_name: &str,
+    conn: &mut PgConnection,
+) -> Result<Permission, ApiError> {
+    use crate::schema::permissions::dsl::*;
+
+    let permission_name = permission_name.trim();
+    if permission_name.is_empty() {
+        return Err(ApiError::Validation(
+            "Permission name must not be empty".to_string(),
+        ));
+    }
+
+    let res = diesel::update(permissions.find(permission_id))
+        .set(name.eq(permission_name))
+        .get_result::<Permission>(conn)?;
+    Ok(res)
+}
+
+fn remove_permission(permission_id: &str, conn: &mut PgConnection) -> Result<Permission, ApiError> {
+    use crate::schema::{permissions, role_permissions};
+
+    conn.transaction(|conn| {
+        diesel::delete(
+            role_permissions::table.filter(role_permissions::permission_id.eq(permission_id)),
+        )
+        .execute(conn)?;
+
+        let res = diesel::delete(permissions::table.find(permission_id))
+            .get_result::<Permission>(conn)?;
+        Ok(res)
+    })
+}
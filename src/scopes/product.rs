@@ -0,0 +1,412 @@
+use crate::{
+    db::unit_of_work,
+    errors::ApiError,
+    extractors::{
+        authentication_token::AuthenticationToken,
+        require_permission::{ProductsDelete, ProductsRead, ProductsWrite, RequirePermission},
+    },
+    models::{NewProduct, Product},
+    money::{Currency, Money},
+    scopes::{
+        audit::{record, snapshot, Actor, Event},
+        category::{set_product_categories, set_product_tags},
+        member::{check_store_role, StoreRole},
+        reservation::{cancel_reservation, commit_reservation, get_product_reservations},
+        stock::{
+            adjust_product_stock, get_product_movements, get_product_stock, lock_member_product,
+            put_stock, transfer_product_stock, MovementKind,
+        },
+        store::{check_store_access, get_session, store_currency},
+        variant::{
+            create_option, create_variant, delete_option, delete_variant, product_detail,
+            product_details, update_variant, ProductDetail,
+        },
+    },
+    AppState,
+};
+use actix_web::{web, HttpRequest, HttpResponse, Scope};
+use diesel::{
+    Connection, ExpressionMethods, JoinOnDsl, OptionalExtension, PgConnection, QueryDsl,
+    RunQueryDsl, SelectableHelper,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub fn product_scope() -> Scope {
+    web::scope("products")
+        .route("/{id}", web::get().to(get_product))
+        .route("/{id}", web::put().to(update_product))
+        .route("/{id}", web::delete().to(delete_product))
+        .route("/{id}/options", web::post().to(create_option))
+        .route("/{id}/options/{option_id}", web::delete().to(delete_option))
+        .route("/{id}/variants", web::post().to(create_variant))
+        .route("/{id}/variants/{variant_id}", web::put().to(update_variant))
+        .route(
+            "/{id}/variants/{variant_id}",
+            web::delete().to(delete_variant),
+        )
+        .route("/{id}/stock", web::get().to(get_product_stock))
+        .route(
+            "/{id}/stock/adjustments",
+            web::post().to(adjust_product_stock),
+        )
+        .route(
+            "/{id}/stock/transfers",
+            web::post().to(transfer_product_stock),
+        )
+        .route(
+            "/{id}/stock/movements",
+            web::get().to(get_product_movements),
+        )
+        .route("/{id}/categories", web::put().to(set_product_categories))
+        .route("/{id}/tags", web::put().to(set_product_tags))
+        .route(
+            "/{id}/reservations",
+            web::get().to(get_product_reservations),
+        )
+        .route(
+            "/{id}/reservations/{reservation_id}/commit",
+            web::post().to(commit_reservation),
+        )
+        .route(
+            "/{id}/reservations/{reservation_id}/cancel",
+            web::post().to(cancel_reservation),
+        )
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct ProductPayload {
+    title: String,
+    description: Option<String>,
+    /// Decimal string, e.g. `"19.90"`.
+    price: String,
+    currency: Option<Currency>,
+    /// Opening stock on create, received at the store's default location.
+    /// Later changes go through stock adjustments.
+    quantity: Option<i32>,
+}
+
+impl ProductPayload {
+    /// Checks the payload and returns its price, in `default_currency` unless
+    /// the payload names one.
+    fn validate(&self, default_currency: Currency) -> Result<Money, ApiError> {
+        if self.title.trim().is_empty() {
+            return Err(ApiError::Validation(
+                "Product title must not be empty".to_string(),
+            ));
+        }
+        let price = Money::parse(&self.price, self.currency.unwrap_or(default_currency))?;
+        if price.is_negative() {
+            return Err(ApiError::Validation(
+                "Product price must not be negative".to_string(),
+            ));
+        }
+        if self.quantity.unwrap_or(0) < 0 {
+            return Err(ApiError::Validation(
+                "Product quantity must not be negative".to_string(),
+            ));
+        }
+        Ok(price)
+    }
+}
+
+pub(crate) async fn get_store_products(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<ProductsRead>,
+    store_id: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let pool = state.pool.clone();
+    let id_str = store_id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_access(&sessions[0].user_id, &id_str, &mut conn)
+    })
+    .await??;
+
+    let products = web::block(move || {
+        let mut conn = state.pool.get()?;
+        list_store_products(&store_id, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(products))
+}
+
+pub(crate) async fn create_store_product(
+    req: HttpRequest,
+    auth_token: AuthenticationToken,
+    _: RequirePermission<ProductsWrite>,
+    store_id: web::Path<String>,
+    body: web::Json<ProductPayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+    let actor = Actor::from_request(&req).signed_in(&sessions[0].user_id, &sessions[0].id);
+
+    let pool = state.pool.clone();
+    let id_str = store_id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_role(&sessions[0].user_id, &id_str, StoreRole::Staff, &mut conn)
+    })
+    .await??;
+
+    let product = unit_of_work(&state.pool, move |conn| {
+        let product = add_product(&store_id, &body, conn)?;
+        record(
+            &actor,
+            Event {
+                action: "product.create",
+                store_id: Some(&store_id),
+                target_id: Some(&product.id),
+                before: None,
+                after: snapshot(&product),
+            },
+            conn,
+        )?;
+        Ok(product)
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(product))
+}
+
+async fn get_product(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<ProductsRead>,
+    id: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let product = web::block(move || {
+        let mut conn = state.pool.get()?;
+        let product = find_member_product(&sessions[0].user_id, &id, &mut conn)?;
+        product_detail(product, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(product))
+}
+
+async fn update_product(
+    req: HttpRequest,
+    auth_token: AuthenticationToken,
+    _: RequirePermission<ProductsWrite>,
+    id: web::Path<String>,
+    body: web::Json<ProductPayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+    let actor = Actor::from_request(&req).signed_in(&sessions[0].user_id, &sessions[0].id);
+
+    let product = unit_of_work(&state.pool, move |conn| {
+        let before = lock_member_product(&sessions[0].user_id, &id, conn)?;
+        let product = edit_product(&sessions[0].user_id, &id, &body, conn)?;
+        record(
+            &actor,
+            Event {
+                action: "product.update",
+                store_id: Some(&product.store_id),
+                target_id: Some(&product.id),
+                before: snapshot(&before),
+                after: snapshot(&product),
+            },
+            conn,
+        )?;
+        Ok(product)
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(product))
+}
+
+async fn delete_product(
+    req: HttpRequest,
+    auth_token: AuthenticationToken,
+    _: RequirePermission<ProductsDelete>,
+    id: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+    let actor = Actor::from_request(&req).signed_in(&sessions[0].user_id, &sessions[0].id);
+
+    unit_of_work(&state.pool, move |conn| {
+        let product = remove_product(&sessions[0].user_id, &id, conn)?;
+        record(
+            &actor,
+            Event {
+                action: "product.delete",
+                store_id: Some(&product.store_id),
+                target_id: Some(&product.id),
+                before: snapshot(&product),
+                after: None,
+            },
+            conn,
+        )
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json("success"))
+}
+
+fn list_store_products(
+    store: &str,
+    conn: &mut PgConnection,
+) -> Result<Vec<ProductDetail>, ApiError> {
+    use crate::schema::products::dsl::*;
+
+    let res = products
+        .filter(store_id.eq(store))
+        .order(title.asc())
+        .select(Product::as_select())
+        .load::<Product>(conn)?;
+    product_details(res, conn)
+}
+
+/// Looks a product up through the `user_stores` membership of its store.
+pub(crate) fn find_member_product(
+    user: &str,
+    product: &str,
+    conn: &mut PgConnection,
+) -> Result<Product, ApiError> {
+    use crate::schema::{products, user_stores};
+
+    let res = products::table
+        .inner_join(user_stores::table.on(user_stores::store_id.eq(products::store_id)))
+        .filter(user_stores::user_id.eq(user))
+        .filter(products::id.eq(product))
+        .select(Product::as_select())
+        .first::<Product>(conn)
+        .optional()?
+        .ok_or(ApiError::NotFound("Product not found"))?;
+    Ok(res)
+}
+
+/// Like `find_member_product`, but also requires the member to hold at least
+/// `required` in the product's store.
+pub(crate) fn check_product_role(
+    user: &str,
+    product: &str,
+    required: StoreRole,
+    conn: &mut PgConnection,
+) -> Result<Product, ApiError> {
+    let res = find_member_product(user, product, conn)?;
+    check_store_role(user, &res.store_id, required, conn)?;
+    Ok(res)
+}
+
+fn add_product(
+    store: &str,
+    body: &ProductPayload,
+    conn: &mut PgConnection,
+) -> Result<Product, ApiError> {
+    use crate::schema::products::dsl::*;
+
+    let product_price = body.validate(store_currency(store, conn)?)?;
+    let product_currency = product_price.currency();
+    let product_id = Uuid::new_v4().to_string();
+
+    let new_product = NewProduct {
+        id: &product_id,
+        title: body.title.trim(),
+        description: body.description.as_deref(),
+        price: &product_price.minor(),
+        currency: product_currency.as_str(),
+        quantity: &0,
+        store_id: store,
+    };
+
+    conn.transaction(|conn| {
+        diesel::insert_into(products)
+            .values(&new_product)
+            .execute(conn)?;
+
+        let opening = body.quantity.unwrap_or(0);
+        if opening > 0 {
+            put_stock(
+                store,
+                &product_id,
+                opening,
+                MovementKind::Receive,
+                None,
+                None,
+                conn,
+            )?;
+        }
+
+        let res = products
+            .find(&product_id)
+            .select(Product::as_select())
+            .first::<Product>(conn)?;
+        Ok(res)
+    })
+}
+
+fn edit_product(
+    user: &str,
+    product: &str,
+    body: &ProductPayload,
+    conn: &mut PgConnection,
+) -> Result<Product, ApiError> {
+    use crate::schema::products::dsl::*;
+
+    // A price without a currency stays in the product's current one.
+    let current = check_product_role(user, product, StoreRole::Staff, conn)?;
+    let product_price = body.validate(current.price.currency())?;
+    if body.quantity.is_some_and(|units| units != current.quantity) {
+        return Err(ApiError::Validation(
+            "Change product stock through stock adjustments".to_string(),
+        ));
+    }
+
+    let res = diesel::update(products.find(product))
+        .set((
+            title.eq(body.title.trim()),
+            description.eq(body.description.as_deref()),
+            price.eq(product_price.minor()),
+            currency.eq(product_price.currency().as_str()),
+        ))
+        .returning(Product::as_returning())
+        .get_result(conn)?;
+    Ok(res)
+}
+
+fn remove_product(user: &str, product: &str, conn: &mut PgConnection) -> Result<Product, ApiError> {
+    use crate::schema::products::dsl::*;
+
+    check_product_role(user, product, StoreRole::Staff, conn)?;
+
+    let res = diesel::delete(products.find(product))
+        .returning(Product::as_returning())
+        .get_result(conn)?;
+    Ok(res)
+}
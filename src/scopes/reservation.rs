@@ -0,0 +1,429 @@
+use crate::{
+    errors::ApiError,
+    extractors::{
+        authentication_token::AuthenticationToken,
+        require_permission::{InventoryRead, InventoryWrite, RequirePermission},
+    },
+    models::{NewStockReservation, Product, StockReservation},
+    scopes::{
+        product::find_member_product,
+        stock::{lock_member_product, lock_store_product, take_stock, MovementKind},
+        store::{find_live_store, get_session},
+    },
+    AppState,
+};
+use actix_web::{web, HttpResponse};
+use diesel::{
+    Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl,
+    SelectableHelper,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Lifecycle of a reservation. Only active reservations hold stock; the
+/// other states are final.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ReservationStatus {
+    Active,
+    Committed,
+    Cancelled,
+    Expired,
+}
+
+impl ReservationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReservationStatus::Active => "active",
+            ReservationStatus::Committed => "committed",
+            ReservationStatus::Cancelled => "cancelled",
+            ReservationStatus::Expired => "expired",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct ReservationPayload {
+    product_id: String,
+    quantity: i32,
+}
+
+pub(crate) async fn get_product_reservations(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<InventoryRead>,
+    id: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let reservations = web::block(move || {
+        let mut conn = state.pool.get()?;
+        let product = find_member_product(&sessions[0].user_id, &id, &mut conn)?;
+        list_reservations(&product.id, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(reservations))
+}
+
+pub(crate) async fn commit_reservation(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<InventoryWrite>,
+    path: web::Path<(String, String)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let (product_id, reservation_id) = path.into_inner();
+
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let reservation = web::block(move || {
+        let mut conn = state.pool.get()?;
+        settle_reservation(
+            &sessions[0].user_id,
+            &product_id,
+            &reservation_id,
+            ReservationStatus::Committed,
+            &mut conn,
+        )
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(reservation))
+}
+
+pub(crate) async fn cancel_reservation(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<InventoryWrite>,
+    path: web::Path<(String, String)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let (product_id, reservation_id) = path.into_inner();
+
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let reservation = web::block(move || {
+        let mut conn = state.pool.get()?;
+        settle_reservation(
+            &sessions[0].user_id,
+            &product_id,
+            &reservation_id,
+            ReservationStatus::Cancelled,
+            &mut conn,
+        )
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(reservation))
+}
+
+/// Holds units of a store's product for the signed-in buyer until they check
+/// out, under the same access as checkout itself.
+pub(crate) async fn create_store_reservation(
+    auth_token: AuthenticationToken,
+    store_id: web::Path<String>,
+    body: web::Json<ReservationPayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let ttl = state.reservation_ttl;
+    let max_units = state.reservation_max_units;
+    let reservation = web::block(move || {
+        let mut conn = state.pool.get()?;
+        reserve(
+            &sessions[0].user_id,
+            &store_id,
+            &body.product_id,
+            body.quantity,
+            ttl,
+            max_units,
+            &mut conn,
+        )
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(reservation))
+}
+
+pub(crate) async fn cancel_store_reservation(
+    auth_token: AuthenticationToken,
+    path: web::Path<(String, String)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let (store_id, reservation_id) = path.into_inner();
+
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let reservation = web::block(move || {
+        let mut conn = state.pool.get()?;
+        release_reservation(&sessions[0].user_id, &store_id, &reservation_id, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(reservation))
+}
+
+fn list_reservations(
+    product: &str,
+    conn: &mut PgConnection,
+) -> Result<Vec<StockReservation>, ApiError> {
+    use crate::schema::stock_reservations::dsl::*;
+
+    let res = stock_reservations
+        .filter(product_id.eq(product))
+        .order(created_at.desc())
+        .select(StockReservation::as_select())
+        .load::<StockReservation>(conn)?;
+    Ok(res)
+}
+
+/// Marks a product's lapsed reservations expired, releasing what they held.
+fn release_expired(product: &str, conn: &mut PgConnection) -> Result<(), ApiError> {
+    use crate::schema::stock_reservations::dsl::*;
+
+    let now = chrono::Local::now().naive_local();
+    diesel::update(
+        stock_reservations
+            .filter(product_id.eq(product))
+            .filter(status.eq(ReservationStatus::Active.as_str()))
+            .filter(expires_at.le(now)),
+    )
+    .set((
+        status.eq(ReservationStatus::Expired.as_str()),
+        updated_at.eq(now),
+    ))
+    .execute(conn)?;
+    Ok(())
+}
+
+/// Units of a product held by reservations that are active and unexpired.
+pub(crate) fn held_units(product: &str, conn: &mut PgConnection) -> Result<i64, ApiError> {
+    use crate::schema::stock_reservations::dsl::*;
+
+    let held = stock_reservations
+        .filter(product_id.eq(product))
+        .filter(status.eq(ReservationStatus::Active.as_str()))
+        .filter(expires_at.gt(chrono::Local::now().naive_local()))
+        .select(diesel::dsl::sum(quantity))
+        .first::<Option<i64>>(conn)?;
+    Ok(held.unwrap_or(0))
+}
+
+/// Units `user` holds of `product` through active reservations.
+fn user_held_units(product: &str, user: &str, conn: &mut PgConnection) -> Result<i64, ApiError> {
+    use crate::schema::stock_reservations::dsl::*;
+
+    let held = stock_reservations
+        .filter(product_id.eq(product))
+        .filter(user_id.eq(user))
+        .filter(status.eq(ReservationStatus::Active.as_str()))
+        .filter(expires_at.gt(chrono::Local::now().naive_local()))
+        .select(diesel::dsl::sum(quantity))
+        .first::<Option<i64>>(conn)?;
+    Ok(held.unwrap_or(0))
+}
+
+/// Commits `user`'s active reservations of a locked product into `order`, so
+/// a checkout draws on the units that were held for it.
+pub(crate) fn commit_user_reservations(
+    product: &str,
+    user: &str,
+    order: &str,
+    conn: &mut PgConnection,
+) -> Result<(), ApiError> {
+    use crate::schema::stock_reservations::dsl::*;
+
+    release_expired(product, conn)?;
+    diesel::update(
+        stock_reservations
+            .filter(product_id.eq(product))
+            .filter(user_id.eq(user))
+            .filter(status.eq(ReservationStatus::Active.as_str())),
+    )
+    .set((
+        status.eq(ReservationStatus::Committed.as_str()),
+        reference.eq(order),
+        updated_at.eq(chrono::Local::now().naive_local()),
+    ))
+    .execute(conn)?;
+    Ok(())
+}
+
+/// Holds units of a live store's product for `user` until `ttl` runs out. No
+/// buyer may hold more than `max_units` of one product at a time, so a single
+/// account cannot tie up a store's whole stock.
+fn reserve(
+    user: &str,
+    store: &str,
+    product: &str,
+    quantity: i32,
+    ttl: chrono::Duration,
+    max_units: i32,
+    conn: &mut PgConnection,
+) -> Result<StockReservation, ApiError> {
+    use crate::schema::stock_reservations;
+
+    if quantity <= 0 {
+        return Err(ApiError::Validation(
+            "Quantity must be greater than zero".to_string(),
+        ));
+    }
+
+    conn.transaction(|conn| {
+        find_live_store(store, conn)?;
+        let product = lock_store_product(store, product, conn)?;
+        release_expired(&product.id, conn)?;
+
+        let available = i64::from(product.quantity) - held_units(&product.id, conn)?;
+        if available < i64::from(quantity) {
+            return Err(ApiError::Validation(format!(
+                "Only {} of \"{}\" available",
+                available.max(0),
+                product.title
+            )));
+        }
+        let held_by_user = user_held_units(&product.id, user, conn)?;
+        if held_by_user + i64::from(quantity) > i64::from(max_units) {
+            return Err(ApiError::Validation(format!(
+                "At most {max_units} of \"{}\" can be held at once, {held_by_user} already are",
+                product.title
+            )));
+        }
+
+        let new_reservation = NewStockReservation {
+            id: &Uuid::new_v4().to_string(),
+            store_id: &product.store_id,
+            product_id: &product.id,
+            user_id: user,
+            quantity: &quantity,
+            expires_at: chrono::Local::now().naive_local() + ttl,
+        };
+
+        let res = diesel::insert_into(stock_reservations::table)
+            .values(&new_reservation)
+            .returning(StockReservation::as_returning())
+            .get_result(conn)?;
+        Ok(res)
+    })
+}
+
+/// Lets a store member move an active reservation of one of the store's
+/// products to `to`.
+fn settle_reservation(
+    user: &str,
+    product: &str,
+    reservation: &str,
+    to: ReservationStatus,
+    conn: &mut PgConnection,
+) -> Result<StockReservation, ApiError> {
+    conn.transaction(|conn| {
+        let product = lock_member_product(user, product, conn)?;
+        settle(&product, reservation, to, user, conn)
+    })
+}
+
+/// Lets a buyer cancel one of their own reservations in `store`.
+fn release_reservation(
+    user: &str,
+    store: &str,
+    reservation: &str,
+    conn: &mut PgConnection,
+) -> Result<StockReservation, ApiError> {
+    use crate::schema::stock_reservations::dsl::*;
+
+    conn.transaction(|conn| {
+        let held = stock_reservations
+            .filter(id.eq(reservation))
+            .filter(store_id.eq(store))
+            .filter(user_id.eq(user))
+            .select(product_id)
+            .first::<String>(conn)
+            .optional()?
+            .ok_or(ApiError::NotFound("Reservation not found"))?;
+
+        let product = lock_store_product(store, &held, conn)?;
+        settle(
+            &product,
+            reservation,
+            ReservationStatus::Cancelled,
+            user,
+            conn,
+        )
+    })
+}
+
+/// Moves an active reservation of a locked product to `to`. Committing takes
+/// the held units out of stock; cancelling just releases them.
+fn settle(
+    product: &Product,
+    reservation: &str,
+    to: ReservationStatus,
+    user: &str,
+    conn: &mut PgConnection,
+) -> Result<StockReservation, ApiError> {
+    use crate::schema::stock_reservations::dsl::*;
+
+    release_expired(&product.id, conn)?;
+
+    let current = stock_reservations
+        .filter(id.eq(reservation))
+        .filter(product_id.eq(&product.id))
+        .select(StockReservation::as_select())
+        .first::<StockReservation>(conn)
+        .optional()?
+        .ok_or(ApiError::NotFound("Reservation not found"))?;
+
+    if current.status != ReservationStatus::Active.as_str() {
+        return Err(ApiError::InvalidTransition(format!(
+            "Cannot move a reservation from {} to {}",
+            current.status,
+            to.as_str()
+        )));
+    }
+
+    // Settled first so the units it held count as free when taken.
+    let res = diesel::update(stock_reservations.find(&current.id))
+        .set((
+            status.eq(to.as_str()),
+            updated_at.eq(chrono::Local::now().naive_local()),
+        ))
+        .returning(StockReservation::as_returning())
+        .get_result(conn)?;
+
+    if to == ReservationStatus::Committed {
+        take_stock(
+            product,
+            current.quantity,
+            MovementKind::Sale,
+            Some(&current.id),
+            Some(user),
+            conn,
+        )?;
+    }
+    Ok(res)
+}
@@ -0,0 +1,315 @@
+use crate::{
+    errors::ApiError,
+    extractors::require_permission::{RequirePermission, RolesRead, RolesWrite},
+    models::{NewRole, NewRolePermission, Permission, Role, RolePermission, User},
+    AppState,
+};
+use actix_web::{web, HttpResponse, Scope};
+use diesel::{
+    Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub fn role_scope() -> Scope {
+    web::scope("roles")
+        .route("", web::get().to(get_roles))
+        .route("", web::post().to(create_role))
+        .route("/{id}", web::get().to(get_role))
+        .route("/{id}", web::put().to(update_role))
+        .route("/{id}", web::delete().to(delete_role))
+        .route(
+            "/{id}/permissions/{permission_id}",
+            web::put().to(grant_permission),
+        )
+        .route(
+            "/{id}/permissions/{permission_id}",
+            web::delete().to(revoke_permission),
+        )
+        .route("/{id}/users/{user_id}", web::put().to(assign_user_role))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct RolePayload {
+    name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RoleDetail {
+    id: String,
+    name: String,
+    permissions: Vec<Permission>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct UserRole {
+    id: String,
+    email: String,
+    role_id: String,
+}
+
+async fn get_roles(
+    _: RequirePermission<RolesRead>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let roles = web::block(move || {
+        let mut conn = state.pool.get()?;
+        list_roles(&mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(roles))
+}
+
+async fn create_role(
+    _: RequirePermission<RolesWrite>,
+    body: web::Json<RolePayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let role = web::block(move || {
+        let mut conn = state.pool.get()?;
+        add_role(&body.name, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(role))
+}
+
+async fn get_role(
+    _: RequirePermission<RolesRead>,
+    id: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let role = web::block(move || {
+        let mut conn = state.pool.get()?;
+        find_role_detail(&id, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(role))
+}
+
+async fn update_role(
+    _: RequirePermission<RolesWrite>,
+    id: web::Path<String>,
+    body: web::Json<RolePayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let role = web::block(move || {
+        let mut conn = state.pool.get()?;
+        edit_role(&id, &body.name, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(role))
+}
+
+async fn delete_role(
+    _: RequirePermission<RolesWrite>,
+    id: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    web::block(move || {
+        let mut conn = state.pool.get()?;
+        remove_role(&id, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json("success"))
+}
+
+async fn grant_permission(
+    _: RequirePermission<RolesWrite>,
+    path: web::Path<(String, String)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let (role, permission) = path.into_inner();
+    let role_permission = web::block(move || {
+        let mut conn = state.pool.get()?;
+        add_role_permission(&role, &permission, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(role_permission))
+}
+
+async fn revoke_permission(
+    _: RequirePermission<RolesWrite>,
+    path: web::Path<(String, String)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let (role, permission) = path.into_inner();
+    web::block(move || {
+        let mut conn = state.pool.get()?;
+        remove_role_permission(&role, &permission, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json("success"))
+}
+
+async fn assign_user_role(
+    _: RequirePermission<RolesWrite>,
+    path: web::Path<(String, String)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let (role, user) = path.into_inner();
+    let user = web::block(move || {
+        let mut conn = state.pool.get()?;
+        set_user_role(&user, &role, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(UserRole {
+        id: user.id,
+        email: user.email,
+        role_id: user.role_id,
+    }))
+}
+
+fn list_roles(conn: &mut PgConnection) -> Result<Vec<Role>, ApiError> {
+    use crate::schema::roles::dsl::*;
+
+    let res = roles.order(name.asc()).load::<Role>(conn)?;
+    Ok(res)
+}
+
+fn find_role_detail(role_id: &str, conn: &mut PgConnection) -> Result<RoleDetail, ApiError> {
+    use crate::schema::{permissions, role_permissions, roles};
+
+    let role = roles::table
+        .find(role_id)
+        .first::<Role>(conn)
+        .optional()?
+        .ok_or(ApiError::NotFound("Role not found"))?;
+
+    let permissions = role_permissions::table
+        .inner_join(permissions::table)
+        .filter(role_permissions::role_id.eq(&role.id))
+        .select((permissions::id, permissions::name))
+        .order(permissions::name.asc())
+        .load::<Permission>(conn)?;
+
+    Ok(RoleDetail {
+        id: role.id,
+        name: role.name,
+        permissions,
+    })
+}
+
+fn add_role(role_name: &str, conn: &mut PgConnection) -> Result<Role, ApiError> {
+    use crate::schema::roles::dsl::*;
+
+    let role_name = role_name.trim();
+    if role_name.is_empty() {
+        return Err(ApiError::Validation(
+            "Role name must not be empty".to_string(),
+        ));
+    }
+
+    let new_role = NewRole {
+        id: &Uuid::new_v4().to_string(),
+        name: role_name,
+    };
+
+    let res = diesel::insert_into(roles)
+        .values(&new_role)
+        .get_result(conn)?;
+    Ok(res)
+}
+
+fn edit_role(role_id: &str, role_name: &str, conn: &mut PgConnection) -> Result<Role, ApiError> {
+    use crate::schema::roles::dsl::*;
+
+    let role_name = role_name.trim();
+    if role_name.is_empty() {
+        return Err(ApiError::Validation(
+            "Role name must not be empty".to_string(),
+        ));
+    }
+
+    let res = diesel::update(roles.find(role_id))
+        .set(name.eq(role_name))
+        .get_result::<Role>(conn)?;
+    Ok(res)
+}
+
+fn remove_role(role_id: &str, conn: &mut PgConnection) -> Result<Role, ApiError> {
+    use crate::schema::{role_permissions, roles, users};
+
+    let assigned: i64 = users::table
+        .filter(users::role_id.eq(role_id))
+        .count()
+        .get_result(conn)?;
+
+    if assigned > 0 {
+        return Err(ApiError::Conflict(
+            "Role is still assigned to users".to_string(),
+        ));
+    }
+
+    conn.transaction(|conn| {
+        diesel::delete(role_permissions::table.filter(role_permissions::role_id.eq(role_id)))
+            .execute(conn)?;
+
+        let res = diesel::delete(roles::table.find(role_id)).get_result::<Role>(conn)?;
+        Ok(res)
+    })
+}
+
+fn add_role_permission(
+    role: &str,
+    permission: &str,
+    conn: &mut PgConnection,
+) -> Result<RolePermission, ApiError> {
+    use crate::schema::role_permissions::dsl::*;
+
+    let new_role_permission = NewRolePermission {
+        role_id: role,
+        permission_id: permission,
+    };
+
+    diesel::insert_into(role_permissions)
+        .values(&new_role_permission)
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+
+    Ok(RolePermission {
+        role_id: role.to_string(),
+        permission_id: permission.to_string(),
+    })
+}
+
+fn remove_role_permission(
+    role: &str,
+    permission: &str,
+    conn: &mut PgConnection,
+) -> Result<RolePermission, ApiError> {
+    use crate::schema::role_permissions::dsl::*;
+
+    let res = diesel::delete(role_permissions.find((role, permission)))
+        .get_result::<RolePermission>(conn)?;
+    Ok(res)
+}
+
+/// Sessions carry a copy of the role, so they are moved along with the user
+/// for the change to apply without signing in again.
+pub(crate) fn set_user_role(
+    user: &str,
+    role: &str,
+    conn: &mut PgConnection,
+) -> Result<User, ApiError> {
+    use crate::schema::{session, users};
+
+    conn.transaction(|conn| {
+        let res = diesel::update(users::table.find(user))
+            .set(users::role_id.eq(role))
+            .get_result::<User>(conn)?;
+
+        diesel::update(session::table.filter(session::user_id.eq(user)))
+            .set(session::role_id.eq(role))
+            .execute(conn)?;
+
+        Ok(res)
+    })
+}
@@ -0,0 +1,558 @@
+use crate::{
+    db::unit_of_work,
+    errors::ApiError,
+    extractors::{
+        authentication_token::AuthenticationToken,
+        require_permission::{RequirePermission, SalesRead, SalesWrite},
+    },
+    models::{
+        NewSale, NewSaleItem, NewSaleRefund, NewSaleRefundItem, Product, Sale, SaleItem,
+        SaleRefund, SaleRefundItem,
+    },
+    money::Money,
+    scopes::{
+        audit::{record, snapshot, Actor, Event},
+        member::{check_store_role, StoreRole},
+        stock::{put_stock, take_stock, MovementKind},
+        store::{check_store_access, get_session},
+    },
+    AppState,
+};
+use actix_web::{web, HttpRequest, HttpResponse};
+use diesel::{
+    Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl,
+    SelectableHelper,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct SaleLine {
+    product_id: String,
+    quantity: i32,
+    /// Decimal string in the product's currency overriding its catalog
+    /// price, e.g. for a discount given at the till.
+    unit_price: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct SalePayload {
+    note: Option<String>,
+    items: Vec<SaleLine>,
+}
+
+impl SalePayload {
+    /// Folds repeated products into one line, ordered by product id so
+    /// concurrent sales always lock products in the same order.
+    fn lines(&self) -> Result<BTreeMap<&str, (i32, Option<&str>)>, ApiError> {
+        if self.items.is_empty() {
+            return Err(ApiError::Validation(
+                "A sale needs at least one item".to_string(),
+            ));
+        }
+
+        let mut lines = BTreeMap::new();
+        for item in &self.items {
+            if item.quantity <= 0 {
+                return Err(ApiError::Validation(
+                    "Quantity must be greater than zero".to_string(),
+                ));
+            }
+            let unit_price = item.unit_price.as_deref();
+            let (quantity, price) = lines
+                .entry(item.product_id.as_str())
+                .or_insert((0i32, unit_price));
+            if *price != unit_price {
+                return Err(ApiError::Validation(
+                    "A product cannot be sold at two prices in one sale".to_string(),
+                ));
+            }
+            *quantity = quantity.saturating_add(item.quantity);
+        }
+        Ok(lines)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct RefundLine {
+    sale_item_id: String,
+    quantity: i32,
+    restock: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct RefundPayload {
+    reason: Option<String>,
+    /// Default for lines that do not say otherwise.
+    #[serde(default)]
+    restock: bool,
+    /// Everything not yet refunded when omitted.
+    items: Option<Vec<RefundLine>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RefundDetail {
+    #[serde(flatten)]
+    refund: SaleRefund,
+    items: Vec<SaleRefundItem>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SaleDetail {
+    #[serde(flatten)]
+    sale: Sale,
+    items: Vec<SaleItem>,
+    refunds: Vec<RefundDetail>,
+}
+
+pub(crate) async fn get_store_sales(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<SalesRead>,
+    store_id: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let pool = state.pool.clone();
+    let id_str = store_id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_access(&sessions[0].user_id, &id_str, &mut conn)
+    })
+    .await??;
+
+    let sales = web::block(move || {
+        let mut conn = state.pool.get()?;
+        list_store_sales(&store_id, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(sales))
+}
+
+pub(crate) async fn create_sale(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<SalesWrite>,
+    store_id: web::Path<String>,
+    body: web::Json<SalePayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let pool = state.pool.clone();
+    let id_str = store_id.clone();
+    let user_id = sessions[0].user_id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_role(&sessions[0].user_id, &id_str, StoreRole::Staff, &mut conn)
+    })
+    .await??;
+
+    let sale = web::block(move || {
+        let mut conn = state.pool.get()?;
+        record_sale(&store_id, &user_id, &body, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(sale))
+}
+
+pub(crate) async fn get_store_sale(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<SalesRead>,
+    path: web::Path<(String, String)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let (store_id, sale_id) = path.into_inner();
+
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let pool = state.pool.clone();
+    let id_str = store_id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_access(&sessions[0].user_id, &id_str, &mut conn)
+    })
+    .await??;
+
+    let sale = web::block(move || {
+        let mut conn = state.pool.get()?;
+        let sale = find_store_sale(&store_id, &sale_id, &mut conn)?;
+        get_sale_detail(sale, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(sale))
+}
+
+pub(crate) async fn refund_sale(
+    req: HttpRequest,
+    auth_token: AuthenticationToken,
+    _: RequirePermission<SalesWrite>,
+    path: web::Path<(String, String)>,
+    body: web::Json<RefundPayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let (store_id, sale_id) = path.into_inner();
+
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+    let actor = Actor::from_request(&req).signed_in(&sessions[0].user_id, &sessions[0].id);
+
+    let pool = state.pool.clone();
+    let id_str = store_id.clone();
+    let user_id = sessions[0].user_id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_role(&sessions[0].user_id, &id_str, StoreRole::Staff, &mut conn)
+    })
+    .await??;
+
+    let sale = unit_of_work(&state.pool, move |conn| {
+        let before = lock_store_sale(&store_id, &sale_id, conn)?;
+        let sale = add_refund(&store_id, &sale_id, &user_id, &body, conn)?;
+        record(
+            &actor,
+            Event {
+                action: "sale.refund",
+                store_id: Some(&store_id),
+                target_id: Some(&sale_id),
+                before: snapshot(&before),
+                after: snapshot(&sale.sale),
+            },
+            conn,
+        )?;
+        Ok(sale)
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(sale))
+}
+
+/// Snapshots each product's title and price into the sale and takes the sold
+/// quantity out of stock, all or nothing.
+fn record_sale(
+    store: &str,
+    user: &str,
+    body: &SalePayload,
+    conn: &mut PgConnection,
+) -> Result<SaleDetail, ApiError> {
+    use crate::schema::{products, sale_items, sales};
+
+    let lines = body.lines()?;
+
+    conn.transaction(|conn| {
+        let sale_id = Uuid::new_v4().to_string();
+        let mut sold = Vec::with_capacity(lines.len());
+        for (product_id, (quantity, unit_price)) in lines {
+            let product = products::table
+                .filter(products::id.eq(product_id))
+                .filter(products::store_id.eq(store))
+                .for_update()
+                .select(Product::as_select())
+                .first::<Product>(conn)
+                .optional()?
+                .ok_or(ApiError::NotFound("Product not found"))?;
+
+            take_stock(
+                &product,
+                quantity,
+                MovementKind::Sale,
+                Some(&sale_id),
+                Some(user),
+                conn,
+            )?;
+
+            let price = match unit_price {
+                Some(amount) => Money::parse(amount, product.price.currency())?,
+                None => product.price,
+            };
+            if price.is_negative() {
+                return Err(ApiError::Validation(
+                    "Unit price must not be negative".to_string(),
+                ));
+            }
+            sold.push((product, quantity, price));
+        }
+
+        // Lines are never empty, and every line must share the first one's
+        // currency.
+        let currency = sold[0].2.currency();
+        let total = Money::sum(
+            currency,
+            sold.iter()
+                .map(|(_, quantity, price)| price.checked_mul(*quantity))
+                .collect::<Result<Vec<Money>, _>>()?,
+        )?;
+
+        let new_sale = NewSale {
+            id: &sale_id,
+            store_id: store,
+            user_id: user,
+            note: body.note.as_deref(),
+            total: &total.minor(),
+            currency: currency.as_str(),
+        };
+        let sale = diesel::insert_into(sales::table)
+            .values(&new_sale)
+            .returning(Sale::as_returning())
+            .get_result(conn)?;
+
+        for (product, quantity, price) in &sold {
+            let new_item = NewSaleItem {
+                id: &Uuid::new_v4().to_string(),
+                sale_id: &sale.id,
+                product_id: Some(&product.id),
+                title: &product.title,
+                unit_price: &price.minor(),
+                currency: currency.as_str(),
+                quantity,
+            };
+            diesel::insert_into(sale_items::table)
+                .values(&new_item)
+                .execute(conn)?;
+        }
+
+        get_sale_detail(sale, conn)
+    })
+}
+
+fn list_store_sales(store: &str, conn: &mut PgConnection) -> Result<Vec<Sale>, ApiError> {
+    use crate::schema::sales::dsl::*;
+
+    let res = sales
+        .filter(store_id.eq(store))
+        .order(created_at.desc())
+        .select(Sale::as_select())
+        .load::<Sale>(conn)?;
+    Ok(res)
+}
+
+fn find_store_sale(store: &str, sale: &str, conn: &mut PgConnection) -> Result<Sale, ApiError> {
+    use crate::schema::sales::dsl::*;
+
+    let res = sales
+        .filter(id.eq(sale))
+        .filter(store_id.eq(store))
+        .select(Sale::as_select())
+        .first::<Sale>(conn)
+        .optional()?
+        .ok_or(ApiError::NotFound("Sale not found"))?;
+    Ok(res)
+}
+
+fn lock_store_sale(store: &str, sale: &str, conn: &mut PgConnection) -> Result<Sale, ApiError> {
+    use crate::schema::sales::dsl::*;
+
+    let res = sales
+        .filter(id.eq(sale))
+        .filter(store_id.eq(store))
+        .for_update()
+        .select(Sale::as_select())
+        .first::<Sale>(conn)
+        .optional()?
+        .ok_or(ApiError::NotFound("Sale not found"))?;
+    Ok(res)
+}
+
+fn get_sale_detail(sale: Sale, conn: &mut PgConnection) -> Result<SaleDetail, ApiError> {
+    use crate::schema::{sale_items, sale_refund_items, sale_refunds};
+
+    let items = sale_items::table
+        .filter(sale_items::sale_id.eq(&sale.id))
+        .order(sale_items::title.asc())
+        .select(SaleItem::as_select())
+        .load::<SaleItem>(conn)?;
+
+    let refunds = sale_refunds::table
+        .filter(sale_refunds::sale_id.eq(&sale.id))
+        .order(sale_refunds::created_at.asc())
+        .select(SaleRefund::as_select())
+        .load::<SaleRefund>(conn)?;
+
+    let mut refund_items = sale_refund_items::table
+        .filter(sale_refund_items::refund_id.eq_any(refunds.iter().map(|refund| &refund.id)))
+        .select(SaleRefundItem::as_select())
+        .load::<SaleRefundItem>(conn)?;
+
+    let refunds = refunds
+        .into_iter()
+        .map(|refund| {
+            let (items, rest) = refund_items
+                .drain(..)
+                .partition(|item| item.refund_id == refund.id);
+            refund_items = rest;
+            RefundDetail { refund, items }
+        })
+        .collect();
+
+    Ok(SaleDetail {
+        sale,
+        items,
+        refunds,
+    })
+}
+
+/// Records one immutable refund against a locked sale, moving each line's
+/// refunded quantity forward and optionally putting units back in stock.
+fn add_refund(
+    store: &str,
+    sale: &str,
+    user: &str,
+    body: &RefundPayload,
+    conn: &mut PgConnection,
+) -> Result<SaleDetail, ApiError> {
+    use crate::schema::{sale_items, sale_refund_items, sale_refunds, sales};
+
+    conn.transaction(|conn| {
+        let current = lock_store_sale(store, sale, conn)?;
+
+        let items = sale_items::table
+            .filter(sale_items::sale_id.eq(&current.id))
+            .order(sale_items::id.asc())
+            .select(SaleItem::as_select())
+            .load::<SaleItem>(conn)?;
+
+        let mut lines = BTreeMap::new();
+        match &body.items {
+            Some(requested) => {
+                if requested.is_empty() {
+                    return Err(ApiError::Validation(
+                        "A refund needs at least one item".to_string(),
+                    ));
+                }
+                for line in requested {
+                    if line.quantity <= 0 {
+                        return Err(ApiError::Validation(
+                            "Quantity must be greater than zero".to_string(),
+                        ));
+                    }
+                    let item = items
+                        .iter()
+                        .find(|item| item.id == line.sale_item_id)
+                        .ok_or(ApiError::NotFound("Sale item not found"))?;
+                    let restock = line.restock.unwrap_or(body.restock);
+                    if lines
+                        .insert(item.id.as_str(), (item, line.quantity, restock))
+                        .is_some()
+                    {
+                        return Err(ApiError::Validation(
+                            "Each sale item can appear only once in a refund".to_string(),
+                        ));
+                    }
+                }
+            }
+            None => {
+                for item in &items {
+                    let remaining = item.quantity - item.refunded_quantity;
+                    if remaining > 0 {
+                        lines.insert(item.id.as_str(), (item, remaining, body.restock));
+                    }
+                }
+                if lines.is_empty() {
+                    return Err(ApiError::Conflict(
+                        "Sale is already fully refunded".to_string(),
+                    ));
+                }
+            }
+        }
+
+        for (item, quantity, _) in lines.values() {
+            let remaining = item.quantity - item.refunded_quantity;
+            if *quantity > remaining {
+                return Err(ApiError::Validation(format!(
+                    "Only {} of \"{}\" left to refund",
+                    remaining, item.title
+                )));
+            }
+        }
+
+        let currency = current.total.currency();
+        let line_amounts = lines
+            .values()
+            .map(|(item, quantity, _)| item.unit_price.checked_mul(*quantity))
+            .collect::<Result<Vec<Money>, _>>()?;
+        let amount = Money::sum(currency, line_amounts.iter().copied())?;
+        let refunded_total = current.refunded_total.checked_add(amount)?;
+        if refunded_total.minor() > current.total.minor() {
+            return Err(ApiError::Validation(
+                "Refunds cannot exceed the sale total".to_string(),
+            ));
+        }
+
+        let new_refund = NewSaleRefund {
+            id: &Uuid::new_v4().to_string(),
+            sale_id: &current.id,
+            user_id: user,
+            reason: body.reason.as_deref(),
+            amount: &amount.minor(),
+            currency: currency.as_str(),
+        };
+        let refund = diesel::insert_into(sale_refunds::table)
+            .values(&new_refund)
+            .returning(SaleRefund::as_returning())
+            .get_result(conn)?;
+
+        for ((item, quantity, restock), line_amount) in lines.values().zip(&line_amounts) {
+            diesel::update(sale_items::table.find(&item.id))
+                .set(sale_items::refunded_quantity.eq(sale_items::refunded_quantity + quantity))
+                .execute(conn)?;
+
+            // A product deleted since the sale has nowhere to go back to.
+            let restocked = match (&item.product_id, restock) {
+                (Some(product), true) => {
+                    put_stock(
+                        store,
+                        product,
+                        *quantity,
+                        MovementKind::Return,
+                        Some(&current.id),
+                        Some(user),
+                        conn,
+                    )?;
+                    true
+                }
+                _ => false,
+            };
+
+            let new_item = NewSaleRefundItem {
+                refund_id: &refund.id,
+                sale_item_id: &item.id,
+                quantity,
+                amount: &line_amount.minor(),
+                currency: currency.as_str(),
+                restocked: &restocked,
+            };
+            diesel::insert_into(sale_refund_items::table)
+                .values(&new_item)
+                .execute(conn)?;
+        }
+
+        let updated = diesel::update(sales::table.find(&current.id))
+            .set(sales::refunded_total.eq(refunded_total.minor()))
+            .returning(Sale::as_returning())
+            .get_result(conn)?;
+
+        get_sale_detail(updated, conn)
+    })
+}
@@ -0,0 +1,271 @@
+use crate::{
+    errors::ApiError,
+    models::Product,
+    money::{Currency, Money},
+    schema::products,
+    scopes::{
+        category::descendants,
+        store::{find_live_store, store_currency},
+        variant::{product_details, ProductDetail},
+    },
+    AppState,
+};
+use actix_web::{web, HttpResponse};
+use diesel::{
+    dsl::{count_star, sql},
+    pg::Pg,
+    sql_types::{Bool, Float4, Text},
+    BoxableExpression, ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl, SelectableHelper,
+};
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_LIMIT: i64 = 20;
+const MAX_LIMIT: i64 = 100;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct SearchParams {
+    q: String,
+    /// Only products filed under this category or one of its descendants.
+    category_id: Option<String>,
+    /// Decimal amounts in the store currency.
+    min_price: Option<String>,
+    max_price: Option<String>,
+    in_stock: Option<bool>,
+    limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CategoryFacet {
+    id: String,
+    name: String,
+    slug: String,
+    count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PriceFacet {
+    min: Money,
+    max: Money,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StockFacet {
+    in_stock: i64,
+    out_of_stock: i64,
+}
+
+/// Counts over every product matching the query text, before the category,
+/// price and stock filters narrow it down.
+#[derive(Debug, Serialize, Deserialize)]
+struct Facets {
+    categories: Vec<CategoryFacet>,
+    /// Absent when nothing priced in the store currency matches.
+    price: Option<PriceFacet>,
+    stock: StockFacet,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SearchResults {
+    /// Products passing every filter, before `limit` applies.
+    total: i64,
+    /// Best match first.
+    items: Vec<ProductDetail>,
+    facets: Facets,
+}
+
+/// The storefront search box: open to guests and buyers like the cart, so no
+/// membership is needed.
+pub(crate) async fn search_store_products(
+    store_id: web::Path<String>,
+    params: web::Query<SearchParams>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let results = web::block(move || {
+        let mut conn = state.pool.get()?;
+        search_products(&store_id, &params, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(results))
+}
+
+/// Turns free text into a `to_tsquery` expression in which every word must
+/// match as a prefix, so results follow the user's typing. Anything but
+/// letters and digits is dropped, so the expression is always valid.
+fn prefix_query(text: &str) -> Result<String, ApiError> {
+    let terms = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(|term| format!("{}:*", term.to_lowercase()))
+        .collect::<Vec<String>>();
+    if terms.is_empty() {
+        return Err(ApiError::Validation(
+            "Search query must contain a letter or digit".to_string(),
+        ));
+    }
+    Ok(terms.join(" & "))
+}
+
+type ProductExpression<ST> = Box<dyn BoxableExpression<products::table, Pg, SqlType = ST>>;
+
+/// Whether a product matches a `prefix_query` expression.
+fn text_match(query: &str) -> ProductExpression<Bool> {
+    Box::new(
+        sql::<Bool>("products.search_vector @@ to_tsquery('english', ")
+            .bind::<Text, _>(query.to_string())
+            .sql(")"),
+    )
+}
+
+/// How well a product matches a `prefix_query` expression; titles outrank
+/// descriptions.
+fn text_rank(query: &str) -> ProductExpression<Float4> {
+    Box::new(
+        sql::<Float4>("ts_rank_cd(products.search_vector, to_tsquery('english', ")
+            .bind::<Text, _>(query.to_string())
+            .sql("))"),
+    )
+}
+
+fn search_products(
+    store: &str,
+    params: &SearchParams,
+    conn: &mut PgConnection,
+) -> Result<SearchResults, ApiError> {
+    use crate::schema::product_categories;
+
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(ApiError::Validation(format!(
+            "Limit must be between 1 and {MAX_LIMIT}"
+        )));
+    }
+
+    find_live_store(store, conn)?;
+    let currency = store_currency(store, conn)?;
+    let min_price = params
+        .min_price
+        .as_deref()
+        .map(|amount| Money::parse(amount, currency))
+        .transpose()?;
+    let max_price = params
+        .max_price
+        .as_deref()
+        .map(|amount| Money::parse(amount, currency))
+        .transpose()?;
+
+    let text = prefix_query(&params.q)?;
+    let tree = params
+        .category_id
+        .as_deref()
+        .map(|category| descendants(category, conn))
+        .transpose()?;
+
+    let filtered = || {
+        let mut query = products::table
+            .filter(products::store_id.eq(store))
+            .filter(text_match(&text))
+            .into_boxed();
+
+        if let Some(tree) = &tree {
+            query = query.filter(
+                products::id.eq_any(
+                    product_categories::table
+                        .filter(product_categories::category_id.eq_any(tree))
+                        .select(product_categories::product_id),
+                ),
+            );
+        }
+        if min_price.is_some() || max_price.is_some() {
+            query = query.filter(products::currency.eq(currency.as_str()));
+        }
+        if let Some(price) = min_price {
+            query = query.filter(products::price.ge(price.minor()));
+        }
+        if let Some(price) = max_price {
+            query = query.filter(products::price.le(price.minor()));
+        }
+        match params.in_stock {
+            Some(true) => query = query.filter(products::quantity.gt(0)),
+            Some(false) => query = query.filter(products::quantity.le(0)),
+            None => {}
+        }
+        query
+    };
+
+    let total = filtered().count().get_result::<i64>(conn)?;
+    let found = filtered()
+        .select(Product::as_select())
+        .order((text_rank(&text).desc(), products::title.asc()))
+        .limit(limit)
+        .load::<Product>(conn)?;
+
+    Ok(SearchResults {
+        total,
+        items: product_details(found, conn)?,
+        facets: facets(store, &text, currency, conn)?,
+    })
+}
+
+fn facets(
+    store: &str,
+    text: &str,
+    currency: Currency,
+    conn: &mut PgConnection,
+) -> Result<Facets, ApiError> {
+    use crate::schema::{categories, product_categories};
+
+    let matching = || {
+        products::table
+            .filter(products::store_id.eq(store))
+            .filter(text_match(text))
+    };
+
+    let categories = product_categories::table
+        .inner_join(categories::table)
+        .filter(product_categories::product_id.eq_any(matching().select(products::id).into_boxed()))
+        .group_by((categories::id, categories::name, categories::slug))
+        .order(categories::name.asc())
+        .select((
+            categories::id,
+            categories::name,
+            categories::slug,
+            count_star(),
+        ))
+        .load::<(String, String, String, i64)>(conn)?
+        .into_iter()
+        .map(|(id, name, slug, count)| CategoryFacet {
+            id,
+            name,
+            slug,
+            count,
+        })
+        .collect();
+
+    let (low, high) = matching()
+        .filter(products::currency.eq(currency.as_str()))
+        .select((
+            diesel::dsl::min(products::price),
+            diesel::dsl::max(products::price),
+        ))
+        .first::<(Option<i64>, Option<i64>)>(conn)?;
+    let price = low.zip(high).map(|(low, high)| PriceFacet {
+        min: Money::from_minor(low, currency),
+        max: Money::from_minor(high, currency),
+    });
+
+    let matches = matching().count().get_result::<i64>(conn)?;
+    let in_stock = matching()
+        .filter(products::quantity.gt(0))
+        .count()
+        .get_result::<i64>(conn)?;
+
+    Ok(Facets {
+        categories,
+        price,
+        stock: StockFacet {
+            in_stock,
+            out_of_stock: matches - in_stock,
+        },
+    })
+}
@@ -0,0 +1,673 @@
+use crate::{
+    db::unit_of_work,
+    errors::ApiError,
+    extractors::{
+        authentication_token::AuthenticationToken,
+        require_permission::{InventoryRead, InventoryWrite, RequirePermission},
+    },
+    models::{NewStockLocation, NewStockMovement, Product, StockLocation, StockMovement},
+    scopes::{
+        audit::{record, snapshot, Actor, Event},
+        member::{check_store_role, StoreRole},
+        product::{check_product_role, find_member_product},
+        reservation::held_units,
+        store::{check_store_access, get_session},
+    },
+    AppState,
+};
+use actix_web::{web, HttpRequest, HttpResponse};
+use diesel::{
+    Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl,
+    SelectableHelper,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum MovementKind {
+    Receive,
+    Sale,
+    Return,
+    Adjustment,
+    Transfer,
+}
+
+impl MovementKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MovementKind::Receive => "receive",
+            MovementKind::Sale => "sale",
+            MovementKind::Return => "return",
+            MovementKind::Adjustment => "adjustment",
+            MovementKind::Transfer => "transfer",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct LocationPayload {
+    name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct AdjustmentPayload {
+    location_id: String,
+    kind: MovementKind,
+    /// Units added, or removed when negative (adjustments only).
+    quantity: i32,
+    note: Option<String>,
+}
+
+impl AdjustmentPayload {
+    fn validate(&self) -> Result<(), ApiError> {
+        match self.kind {
+            MovementKind::Receive | MovementKind::Return if self.quantity <= 0 => {
+                Err(ApiError::Validation(format!(
+                    "A {} must add at least one unit",
+                    self.kind.as_str()
+                )))
+            }
+            MovementKind::Adjustment if self.quantity == 0 => Err(ApiError::Validation(
+                "An adjustment must change the quantity".to_string(),
+            )),
+            MovementKind::Sale | MovementKind::Transfer => Err(ApiError::Validation(format!(
+                "{} movements are recorded by sales and transfers",
+                self.kind.as_str()
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct TransferPayload {
+    from_location_id: String,
+    to_location_id: String,
+    quantity: i32,
+    note: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct MovementFilter {
+    location_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LocationStock {
+    location_id: String,
+    name: String,
+    on_hand: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ProductStock {
+    product_id: String,
+    on_hand: i64,
+    /// Held by active reservations.
+    reserved: i64,
+    available: i64,
+    locations: Vec<LocationStock>,
+}
+
+pub(crate) async fn get_store_locations(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<InventoryRead>,
+    store_id: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let pool = state.pool.clone();
+    let id_str = store_id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_access(&sessions[0].user_id, &id_str, &mut conn)
+    })
+    .await??;
+
+    let locations = web::block(move || {
+        let mut conn = state.pool.get()?;
+        list_locations(&store_id, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(locations))
+}
+
+pub(crate) async fn create_store_location(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<InventoryWrite>,
+    store_id: web::Path<String>,
+    body: web::Json<LocationPayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let pool = state.pool.clone();
+    let id_str = store_id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_role(&sessions[0].user_id, &id_str, StoreRole::Staff, &mut conn)
+    })
+    .await??;
+
+    let location = web::block(move || {
+        let mut conn = state.pool.get()?;
+        add_location(&store_id, body.name.trim(), false, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(location))
+}
+
+pub(crate) async fn get_product_stock(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<InventoryRead>,
+    id: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let stock = web::block(move || {
+        let mut conn = state.pool.get()?;
+        let product = find_member_product(&sessions[0].user_id, &id, &mut conn)?;
+        product_stock(&product, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(stock))
+}
+
+pub(crate) async fn get_product_movements(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<InventoryRead>,
+    id: web::Path<String>,
+    filter: web::Query<MovementFilter>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let movements = web::block(move || {
+        let mut conn = state.pool.get()?;
+        let product = find_member_product(&sessions[0].user_id, &id, &mut conn)?;
+        list_movements(&product.id, &filter, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(movements))
+}
+
+pub(crate) async fn adjust_product_stock(
+    req: HttpRequest,
+    auth_token: AuthenticationToken,
+    _: RequirePermission<InventoryWrite>,
+    id: web::Path<String>,
+    body: web::Json<AdjustmentPayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+    let actor = Actor::from_request(&req).signed_in(&sessions[0].user_id, &sessions[0].id);
+
+    let stock = unit_of_work(&state.pool, move |conn| {
+        let product = lock_member_product(&sessions[0].user_id, &id, conn)?;
+        let before = product_stock(&product, conn)?;
+        let stock = adjust_stock(&sessions[0].user_id, &id, &body, conn)?;
+        record(
+            &actor,
+            Event {
+                action: "stock.adjust",
+                store_id: Some(&product.store_id),
+                target_id: Some(&product.id),
+                before: snapshot(&before),
+                after: snapshot(&stock),
+            },
+            conn,
+        )?;
+        Ok(stock)
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(stock))
+}
+
+pub(crate) async fn transfer_product_stock(
+    req: HttpRequest,
+    auth_token: AuthenticationToken,
+    _: RequirePermission<InventoryWrite>,
+    id: web::Path<String>,
+    body: web::Json<TransferPayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+    let actor = Actor::from_request(&req).signed_in(&sessions[0].user_id, &sessions[0].id);
+
+    let stock = unit_of_work(&state.pool, move |conn| {
+        let product = lock_member_product(&sessions[0].user_id, &id, conn)?;
+        let before = product_stock(&product, conn)?;
+        let stock = transfer_stock(&sessions[0].user_id, &id, &body, conn)?;
+        record(
+            &actor,
+            Event {
+                action: "stock.transfer",
+                store_id: Some(&product.store_id),
+                target_id: Some(&product.id),
+                before: snapshot(&before),
+                after: snapshot(&stock),
+            },
+            conn,
+        )?;
+        Ok(stock)
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(stock))
+}
+
+fn list_locations(store: &str, conn: &mut PgConnection) -> Result<Vec<StockLocation>, ApiError> {
+    use crate::schema::stock_locations::dsl::*;
+
+    let res = stock_locations
+        .filter(store_id.eq(store))
+        .order((is_default.desc(), name.asc()))
+        .select(StockLocation::as_select())
+        .load::<StockLocation>(conn)?;
+    Ok(res)
+}
+
+pub(crate) fn add_location(
+    store: &str,
+    location_name: &str,
+    default: bool,
+    conn: &mut PgConnection,
+) -> Result<StockLocation, ApiError> {
+    use crate::schema::stock_locations::dsl::*;
+
+    if location_name.is_empty() {
+        return Err(ApiError::Validation(
+            "Location name must not be empty".to_string(),
+        ));
+    }
+
+    let new_location = NewStockLocation {
+        id: &Uuid::new_v4().to_string(),
+        store_id: store,
+        name: location_name,
+        is_default: &default,
+    };
+
+    let res = diesel::insert_into(stock_locations)
+        .values(&new_location)
+        .returning(StockLocation::as_returning())
+        .get_result(conn)?;
+    Ok(res)
+}
+
+fn find_store_location(
+    store: &str,
+    location: &str,
+    conn: &mut PgConnection,
+) -> Result<StockLocation, ApiError> {
+    use crate::schema::stock_locations::dsl::*;
+
+    let res = stock_locations
+        .filter(id.eq(location))
+        .filter(store_id.eq(store))
+        .select(StockLocation::as_select())
+        .first::<StockLocation>(conn)
+        .optional()?
+        .ok_or(ApiError::NotFound("Location not found"))?;
+    Ok(res)
+}
+
+/// On-hand units of `product` per location id, summed from the ledger.
+fn balances(product: &str, conn: &mut PgConnection) -> Result<HashMap<String, i64>, ApiError> {
+    use crate::schema::stock_movements::dsl::*;
+
+    let res = stock_movements
+        .filter(product_id.eq(product))
+        .group_by(location_id)
+        .select((location_id, diesel::dsl::sum(quantity)))
+        .load::<(String, Option<i64>)>(conn)?
+        .into_iter()
+        .map(|(location, units)| (location, units.unwrap_or(0)))
+        .collect();
+    Ok(res)
+}
+
+fn product_stock(product: &Product, conn: &mut PgConnection) -> Result<ProductStock, ApiError> {
+    let on_hand = balances(&product.id, conn)?;
+
+    let locations = list_locations(&product.store_id, conn)?
+        .into_iter()
+        .map(|location| LocationStock {
+            on_hand: on_hand.get(&location.id).copied().unwrap_or(0),
+            location_id: location.id,
+            name: location.name,
+        })
+        .collect::<Vec<LocationStock>>();
+
+    let total = locations.iter().map(|location| location.on_hand).sum();
+    let reserved = held_units(&product.id, conn)?;
+
+    Ok(ProductStock {
+        product_id: product.id.clone(),
+        on_hand: total,
+        reserved,
+        available: total - reserved,
+        locations,
+    })
+}
+
+fn list_movements(
+    product: &str,
+    filter: &MovementFilter,
+    conn: &mut PgConnection,
+) -> Result<Vec<StockMovement>, ApiError> {
+    use crate::schema::stock_movements::dsl::*;
+
+    let mut query = stock_movements
+        .filter(product_id.eq(product))
+        .select(StockMovement::as_select())
+        .into_boxed();
+
+    if let Some(location) = &filter.location_id {
+        query = query.filter(location_id.eq(location));
+    }
+
+    let res = query.order(created_at.desc()).load::<StockMovement>(conn)?;
+    Ok(res)
+}
+
+/// Appends one movement to the ledger and keeps `products.quantity`, the
+/// running total across locations, in step with it.
+fn record_movement(movement: &NewStockMovement, conn: &mut PgConnection) -> Result<(), ApiError> {
+    use crate::schema::{products, stock_movements};
+
+    diesel::insert_into(stock_movements::table)
+        .values(movement)
+        .execute(conn)?;
+
+    diesel::update(products::table.find(movement.product_id))
+        .set(products::quantity.eq(products::quantity + movement.quantity))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Takes `quantity` units of a product whose row the caller has locked,
+/// drawing on the default location first and then the others by name. Units
+/// held by active reservations are not available.
+pub(crate) fn take_stock(
+    product: &Product,
+    quantity: i32,
+    kind: MovementKind,
+    reference: Option<&str>,
+    user: Option<&str>,
+    conn: &mut PgConnection,
+) -> Result<(), ApiError> {
+    let on_hand = balances(&product.id, conn)?;
+
+    let available = on_hand.values().sum::<i64>() - held_units(&product.id, conn)?;
+    if available < i64::from(quantity) {
+        return Err(ApiError::Validation(format!(
+            "Only {} of \"{}\" available",
+            available.max(0),
+            product.title
+        )));
+    }
+
+    let mut remaining = i64::from(quantity);
+    for location in list_locations(&product.store_id, conn)? {
+        if remaining == 0 {
+            break;
+        }
+        let available = on_hand.get(&location.id).copied().unwrap_or(0);
+        let taken = available.min(remaining);
+        if taken <= 0 {
+            continue;
+        }
+        remaining -= taken;
+
+        record_movement(
+            &NewStockMovement {
+                id: &Uuid::new_v4().to_string(),
+                store_id: &product.store_id,
+                product_id: &product.id,
+                location_id: &location.id,
+                kind: kind.as_str(),
+                quantity: &-(taken as i32),
+                transfer_id: None,
+                reference,
+                note: None,
+                user_id: user,
+            },
+            conn,
+        )?;
+    }
+
+    if remaining > 0 {
+        return Err(ApiError::Validation(format!(
+            "Only {} of \"{}\" in stock",
+            i64::from(quantity) - remaining,
+            product.title
+        )));
+    }
+    Ok(())
+}
+
+/// Puts `quantity` units back at the store's default location.
+pub(crate) fn put_stock(
+    store: &str,
+    product: &str,
+    quantity: i32,
+    kind: MovementKind,
+    reference: Option<&str>,
+    user: Option<&str>,
+    conn: &mut PgConnection,
+) -> Result<(), ApiError> {
+    use crate::schema::stock_locations;
+
+    let location = stock_locations::table
+        .filter(stock_locations::store_id.eq(store))
+        .filter(stock_locations::is_default.eq(true))
+        .select(stock_locations::id)
+        .first::<String>(conn)
+        .optional()?
+        .ok_or_else(|| ApiError::Internal(format!("Store {store} has no default location")))?;
+
+    record_movement(
+        &NewStockMovement {
+            id: &Uuid::new_v4().to_string(),
+            store_id: store,
+            product_id: product,
+            location_id: &location,
+            kind: kind.as_str(),
+            quantity: &quantity,
+            transfer_id: None,
+            reference,
+            note: None,
+            user_id: user,
+        },
+        conn,
+    )
+}
+
+/// Finds a product of `store` and locks its row, for buyers who act on a
+/// store's products without being members of it.
+pub(crate) fn lock_store_product(
+    store: &str,
+    product: &str,
+    conn: &mut PgConnection,
+) -> Result<Product, ApiError> {
+    use crate::schema::products;
+
+    let res = products::table
+        .filter(products::id.eq(product))
+        .filter(products::store_id.eq(store))
+        .for_update()
+        .select(Product::as_select())
+        .first::<Product>(conn)
+        .optional()?
+        .ok_or(ApiError::NotFound("Product not found"))?;
+    Ok(res)
+}
+
+/// Finds a product the user may change stock of, as staff or above, and
+/// locks its row so concurrent movements and reservations see each other's
+/// balances.
+pub(crate) fn lock_member_product(
+    user: &str,
+    product: &str,
+    conn: &mut PgConnection,
+) -> Result<Product, ApiError> {
+    use crate::schema::products;
+
+    let product = check_product_role(user, product, StoreRole::Staff, conn)?;
+    let res = products::table
+        .find(&product.id)
+        .for_update()
+        .select(Product::as_select())
+        .first::<Product>(conn)?;
+    Ok(res)
+}
+
+fn adjust_stock(
+    user: &str,
+    product: &str,
+    body: &AdjustmentPayload,
+    conn: &mut PgConnection,
+) -> Result<ProductStock, ApiError> {
+    body.validate()?;
+
+    conn.transaction(|conn| {
+        let product = lock_member_product(user, product, conn)?;
+        let location = find_store_location(&product.store_id, &body.location_id, conn)?;
+
+        let on_hand = balances(&product.id, conn)?;
+        let available = on_hand.get(&location.id).copied().unwrap_or(0);
+        if available + i64::from(body.quantity) < 0 {
+            return Err(ApiError::Validation(format!(
+                "Only {available} of \"{}\" at {}",
+                product.title, location.name
+            )));
+        }
+        // Reserved units are spoken for and cannot be written off.
+        if body.quantity < 0 {
+            let unreserved = on_hand.values().sum::<i64>() - held_units(&product.id, conn)?;
+            if unreserved + i64::from(body.quantity) < 0 {
+                return Err(ApiError::Validation(format!(
+                    "Only {} of \"{}\" are not reserved",
+                    unreserved.max(0),
+                    product.title
+                )));
+            }
+        }
+
+        record_movement(
+            &NewStockMovement {
+                id: &Uuid::new_v4().to_string(),
+                store_id: &product.store_id,
+                product_id: &product.id,
+                location_id: &location.id,
+                kind: body.kind.as_str(),
+                quantity: &body.quantity,
+                transfer_id: None,
+                reference: None,
+                note: body.note.as_deref(),
+                user_id: Some(user),
+            },
+            conn,
+        )?;
+
+        product_stock(&product, conn)
+    })
+}
+
+/// Moves units between two locations as a pair of movements sharing a
+/// transfer id; the product's total is unchanged.
+fn transfer_stock(
+    user: &str,
+    product: &str,
+    body: &TransferPayload,
+    conn: &mut PgConnection,
+) -> Result<ProductStock, ApiError> {
+    if body.quantity <= 0 {
+        return Err(ApiError::Validation(
+            "Quantity must be greater than zero".to_string(),
+        ));
+    }
+    if body.from_location_id == body.to_location_id {
+        return Err(ApiError::Validation(
+            "Cannot transfer stock to the location it is at".to_string(),
+        ));
+    }
+
+    conn.transaction(|conn| {
+        let product = lock_member_product(user, product, conn)?;
+        let from = find_store_location(&product.store_id, &body.from_location_id, conn)?;
+        let to = find_store_location(&product.store_id, &body.to_location_id, conn)?;
+
+        let available = balances(&product.id, conn)?
+            .get(&from.id)
+            .copied()
+            .unwrap_or(0);
+        if available < i64::from(body.quantity) {
+            return Err(ApiError::Validation(format!(
+                "Only {available} of \"{}\" at {}",
+                product.title, from.name
+            )));
+        }
+
+        let transfer_id = Uuid::new_v4().to_string();
+        for (location, quantity) in [(&from, -body.quantity), (&to, body.quantity)] {
+            record_movement(
+                &NewStockMovement {
+                    id: &Uuid::new_v4().to_string(),
+                    store_id: &product.store_id,
+                    product_id: &product.id,
+                    location_id: &location.id,
+                    kind: MovementKind::Transfer.as_str(),
+                    quantity: &quantity,
+                    transfer_id: Some(&transfer_id),
+                    reference: None,
+                    note: body.note.as_deref(),
+                    user_id: Some(user),
+                },
+                conn,
+            )?;
+        }
+
+        product_stock(&product, conn)
+    })
+}
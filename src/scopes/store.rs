@@ -1,15 +1,55 @@
 use crate::{
-    extractors::authentication_token::AuthenticationToken,
-    models::{NewStore, NewUserStore, Session, Store, UserStore},
+    db::unit_of_work,
+    errors::ApiError,
+    extractors::{
+        authentication_token::AuthenticationToken,
+        require_permission::{RequirePermission, StoresDelete, StoresRead, StoresWrite},
+    },
+    models::{
+        NewStore, NewStoreSettings, NewStoreStageTransition, NewUserStore, Session, Store,
+        StoreSettings, StoreStageTransition, UserStore,
+    },
+    money::Currency,
+    order_status::OrderStatus,
+    pagination::{keyset_column, ListParams, ListQuery, ListSpec, Page, CURSOR_TIMESTAMP},
+    scopes::{
+        audit::{get_store_audit, record, snapshot, Actor, Event},
+        cart::{add_cart_item, delete_cart_item, get_cart, update_cart_item},
+        category::{
+            create_category, delete_category, get_category_products, get_store_categories,
+            update_category,
+        },
+        collection::{
+            create_collection, delete_collection, get_collection_products, get_store_collection,
+            get_store_collections, set_collection_products,
+        },
+        member::{
+            check_store_role, create_invitation, get_store_invitations, get_store_members,
+            list_members, remove_store_member, revoke_invitation, transfer_store_ownership,
+            StoreMember, StoreRole,
+        },
+        order::{checkout, get_store_order, get_store_orders, update_order_status},
+        payment::{
+            capture_payment, confirm_payment, create_payment, get_store_payment,
+            get_store_payments, refund_payment, void_payment,
+        },
+        product::{create_store_product, get_store_products},
+        reservation::{cancel_store_reservation, create_store_reservation},
+        sale::{create_sale, get_store_sale, get_store_sales, refund_sale},
+        search::search_store_products,
+        stock::{add_location, create_store_location, get_store_locations},
+    },
+    store_stage::StoreStage,
     AppState,
 };
-use actix_web::{web, Error, HttpResponse, Scope};
-use diesel::{ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl};
+use actix_web::{web, HttpRequest, HttpResponse, Scope};
+use diesel::{
+    Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl,
+    SelectableHelper,
+};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-pub type DbError = Box<dyn std::error::Error + Send + Sync>;
-
 pub fn store_scope() -> Scope {
     web::scope("stores")
         .route("", web::get().to(get_stores))
@@ -17,6 +57,115 @@ pub fn store_scope() -> Scope {
         .route("/{id}", web::get().to(get_store))
         .route("/{id}", web::put().to(update_store))
         .route("/{id}", web::delete().to(delete_store))
+        .route("/{id}/settings", web::put().to(update_store_settings))
+        .route("/{id}/publish", web::post().to(publish_store))
+        .route("/{id}/pause", web::post().to(pause_store))
+        .route("/{id}/archive", web::post().to(archive_store))
+        .route("/{id}/stage-history", web::get().to(get_stage_history))
+        .route("/{id}/audit", web::get().to(get_store_audit))
+        .route("/{id}/members", web::get().to(get_store_members))
+        .route(
+            "/{id}/members/{user_id}",
+            web::delete().to(remove_store_member),
+        )
+        .route("/{id}/transfer", web::post().to(transfer_store_ownership))
+        .route("/{id}/invitations", web::get().to(get_store_invitations))
+        .route("/{id}/invitations", web::post().to(create_invitation))
+        .route(
+            "/{id}/invitations/{invitation_id}",
+            web::delete().to(revoke_invitation),
+        )
+        .route("/{id}/products", web::get().to(get_store_products))
+        .route("/{id}/products", web::post().to(create_store_product))
+        .route(
+            "/{id}/products/search",
+            web::get().to(search_store_products),
+        )
+        .route("/{id}/cart", web::get().to(get_cart))
+        .route("/{id}/cart/items", web::post().to(add_cart_item))
+        .route(
+            "/{id}/cart/items/{product_id}",
+            web::put().to(update_cart_item),
+        )
+        .route(
+            "/{id}/cart/items/{product_id}",
+            web::delete().to(delete_cart_item),
+        )
+        .route(
+            "/{id}/reservations",
+            web::post().to(create_store_reservation),
+        )
+        .route(
+            "/{id}/reservations/{reservation_id}/cancel",
+            web::post().to(cancel_store_reservation),
+        )
+        .route("/{id}/checkout", web::post().to(checkout))
+        .route("/{id}/orders", web::get().to(get_store_orders))
+        .route("/{id}/orders/{order_id}", web::get().to(get_store_order))
+        .route(
+            "/{id}/orders/{order_id}/status",
+            web::put().to(update_order_status),
+        )
+        .route("/{id}/payments", web::get().to(get_store_payments))
+        .route("/{id}/payments", web::post().to(create_payment))
+        .route(
+            "/{id}/payments/{payment_id}",
+            web::get().to(get_store_payment),
+        )
+        .route(
+            "/{id}/payments/{payment_id}/confirm",
+            web::post().to(confirm_payment),
+        )
+        .route(
+            "/{id}/payments/{payment_id}/capture",
+            web::post().to(capture_payment),
+        )
+        .route(
+            "/{id}/payments/{payment_id}/void",
+            web::post().to(void_payment),
+        )
+        .route(
+            "/{id}/payments/{payment_id}/refund",
+            web::post().to(refund_payment),
+        )
+        .route("/{id}/sales", web::get().to(get_store_sales))
+        .route("/{id}/sales", web::post().to(create_sale))
+        .route("/{id}/sales/{sale_id}", web::get().to(get_store_sale))
+        .route("/{id}/sales/{sale_id}/refunds", web::post().to(refund_sale))
+        .route("/{id}/locations", web::get().to(get_store_locations))
+        .route("/{id}/locations", web::post().to(create_store_location))
+        .route("/{id}/categories", web::get().to(get_store_categories))
+        .route("/{id}/categories", web::post().to(create_category))
+        .route(
+            "/{id}/categories/{category_id}",
+            web::put().to(update_category),
+        )
+        .route(
+            "/{id}/categories/{category_id}",
+            web::delete().to(delete_category),
+        )
+        .route(
+            "/{id}/categories/{category_id}/products",
+            web::get().to(get_category_products),
+        )
+        .route("/{id}/collections", web::get().to(get_store_collections))
+        .route("/{id}/collections", web::post().to(create_collection))
+        .route(
+            "/{id}/collections/{collection_id}",
+            web::get().to(get_store_collection),
+        )
+        .route(
+            "/{id}/collections/{collection_id}",
+            web::delete().to(delete_collection),
+        )
+        .route(
+            "/{id}/collections/{collection_id}/products",
+            web::get().to(get_collection_products),
+        )
+        .route(
+            "/{id}/collections/{collection_id}/products",
+            web::put().to(set_collection_products),
+        )
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -25,190 +174,611 @@ struct StoreJoined {
     user_id: String,
     name: String,
     stage: String,
+    created_at: chrono::NaiveDateTime,
 }
 
+const STORE_LIST: ListSpec = ListSpec {
+    sorts: &["name", "stage", "created", "id"],
+    filters: &["stage"],
+    default_sort: "name",
+};
+
 async fn get_stores(
     auth_token: AuthenticationToken,
+    _: RequirePermission<StoresRead>,
+    query: web::Query<ListQuery>,
     state: web::Data<AppState>,
-) -> Result<HttpResponse, Error> {
+) -> Result<HttpResponse, ApiError> {
+    let params = query.parse(&STORE_LIST)?;
+
     let pool = state.pool.clone();
     let sessions = web::block(move || {
         let mut conn = pool.get()?;
         get_session(&auth_token.id.to_string(), &mut conn)
     })
-    .await?
-    .map_err(actix_web::error::ErrorInternalServerError)?;
+    .await??;
 
     let pool = state.pool.clone();
     let stores = web::block(move || {
         let mut conn = pool.get()?;
-        get_user_stores(&sessions[0].user_id, &mut conn)
+        get_user_stores(&sessions[0].user_id, &params, &mut conn)
     })
-    .await?
-    .map_err(actix_web::error::ErrorInternalServerError)?;
+    .await??;
 
     Ok(HttpResponse::Ok().json(stores))
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+struct StoreDetail {
+    id: String,
+    name: String,
+    stage: String,
+    members: Vec<StoreMember>,
+    product_count: i64,
+    settings: StoreSettings,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct StorePayload {
     name: String,
-    stage: String,
+    /// New stores default to draft. On update, a different stage goes
+    /// through the same rules as the transition endpoints.
+    stage: Option<StoreStage>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct StoreSettingsPayload {
+    contact_email: Option<String>,
+    timezone: String,
+    /// Left unchanged when omitted; existing prices keep their currency.
+    currency: Option<Currency>,
 }
 
 async fn create_store(
+    req: HttpRequest,
     auth_token: AuthenticationToken,
+    _: RequirePermission<StoresWrite>,
     state: web::Data<AppState>,
     body: web::Json<StorePayload>,
-) -> Result<HttpResponse, Error> {
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+    let actor = Actor::from_request(&req).signed_in(&sessions[0].user_id, &sessions[0].id);
+
+    unit_of_work(&state.pool, move |conn| {
+        let store = open_store(
+            &sessions[0].user_id,
+            &body.name,
+            body.stage.unwrap_or(StoreStage::Draft),
+            conn,
+        )?;
+        record(
+            &actor,
+            Event {
+                action: "store.create",
+                store_id: Some(&store.id),
+                target_id: Some(&store.id),
+                before: None,
+                after: snapshot(&store),
+            },
+            conn,
+        )
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json("success"))
+}
+
+async fn get_store(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<StoresRead>,
+    id: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
     let pool = state.pool.clone();
     let sessions = web::block(move || {
         let mut conn = pool.get()?;
         get_session(&auth_token.id.to_string(), &mut conn)
     })
-    .await?
-    .map_err(actix_web::error::ErrorInternalServerError)?;
+    .await??;
 
     let pool = state.pool.clone();
-    let body_clone = body.clone();
     let store = web::block(move || {
         let mut conn = pool.get()?;
-        add_store(&body_clone.name, &body_clone.stage, &mut conn)
+        check_store_access(&sessions[0].user_id, &id, &mut conn)
     })
-    .await?
-    .map_err(actix_web::error::ErrorInternalServerError)?;
+    .await??;
 
-    web::block(move || {
+    let detail = web::block(move || {
         let mut conn = state.pool.get()?;
-        add_user_store(&sessions[0].user_id, &store.id, &mut conn)
+        get_store_detail(store, &mut conn)
     })
-    .await?
-    .map_err(actix_web::error::ErrorInternalServerError)?;
-
-    Ok(HttpResponse::Ok().json("success"))
-}
+    .await??;
 
-async fn get_store() -> Result<HttpResponse, Error> {
-    Ok(HttpResponse::Ok().json("success"))
+    Ok(HttpResponse::Ok().json(detail))
 }
 
 async fn update_store(
+    req: HttpRequest,
+    auth_token: AuthenticationToken,
+    _: RequirePermission<StoresWrite>,
     id: web::Path<String>,
     body: web::Json<StorePayload>,
     state: web::Data<AppState>,
-) -> Result<HttpResponse, Error> {
-    let store = web::block(move || {
-        let mut conn = state.pool.get()?;
-        edit_store(&id, &body.name, &body.stage, &mut conn)
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+    let actor = Actor::from_request(&req).signed_in(&sessions[0].user_id, &sessions[0].id);
+
+    let pool = state.pool.clone();
+    let id_str = id.clone();
+    let user_id = sessions[0].user_id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_role(&user_id, &id_str, StoreRole::Manager, &mut conn)
+    })
+    .await??;
+
+    let store = unit_of_work(&state.pool, move |conn| {
+        let before = lock_store(&id, conn)?;
+        let store = edit_store(&sessions[0].user_id, &id, &body, conn)?;
+        record(
+            &actor,
+            Event {
+                action: "store.update",
+                store_id: Some(&id),
+                target_id: Some(&id),
+                before: snapshot(&before),
+                after: snapshot(&store),
+            },
+            conn,
+        )?;
+        Ok(store)
     })
-    .await?
-    .map_err(actix_web::error::ErrorInternalServerError)?;
+    .await?;
 
     Ok(HttpResponse::Ok().json(store))
 }
 
-async fn delete_store(
+async fn publish_store(
+    req: HttpRequest,
+    auth_token: AuthenticationToken,
+    permission: RequirePermission<StoresWrite>,
+    id: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    change_stage(
+        req,
+        auth_token,
+        permission,
+        id,
+        StoreStage::Live,
+        "store.publish",
+        state,
+    )
+    .await
+}
+
+async fn pause_store(
+    req: HttpRequest,
+    auth_token: AuthenticationToken,
+    permission: RequirePermission<StoresWrite>,
+    id: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    change_stage(
+        req,
+        auth_token,
+        permission,
+        id,
+        StoreStage::Paused,
+        "store.pause",
+        state,
+    )
+    .await
+}
+
+async fn archive_store(
+    req: HttpRequest,
+    auth_token: AuthenticationToken,
+    permission: RequirePermission<StoresWrite>,
+    id: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    change_stage(
+        req,
+        auth_token,
+        permission,
+        id,
+        StoreStage::Archived,
+        "store.archive",
+        state,
+    )
+    .await
+}
+
+/// Moves the store to a stage and audits it under `action`, for a manager.
+async fn change_stage(
+    req: HttpRequest,
     auth_token: AuthenticationToken,
+    _: RequirePermission<StoresWrite>,
     id: web::Path<String>,
+    to: StoreStage,
+    action: &'static str,
     state: web::Data<AppState>,
-) -> Result<HttpResponse, Error> {
+) -> Result<HttpResponse, ApiError> {
     let pool = state.pool.clone();
     let sessions = web::block(move || {
         let mut conn = pool.get()?;
         get_session(&auth_token.id.to_string(), &mut conn)
     })
-    .await?
-    .map_err(actix_web::error::ErrorInternalServerError)?;
+    .await??;
+    let actor = Actor::from_request(&req).signed_in(&sessions[0].user_id, &sessions[0].id);
 
     let pool = state.pool.clone();
     let id_str = id.clone();
+    let user_id = sessions[0].user_id.clone();
     web::block(move || {
         let mut conn = pool.get()?;
-        remove_user_store(&id_str, &sessions[0].user_id, &mut conn)
+        check_store_role(&user_id, &id_str, StoreRole::Manager, &mut conn)
+    })
+    .await??;
+
+    let store = unit_of_work(&state.pool, move |conn| {
+        let before = lock_store(&id, conn)?;
+        let store = transition_store(&sessions[0].user_id, &id, to, conn)?;
+        record(
+            &actor,
+            Event {
+                action,
+                store_id: Some(&id),
+                target_id: Some(&id),
+                before: snapshot(&before),
+                after: snapshot(&store),
+            },
+            conn,
+        )?;
+        Ok(store)
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(store))
+}
+
+async fn get_stage_history(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<StoresRead>,
+    id: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
     })
-    .await?
-    .map_err(actix_web::error::ErrorInternalServerError)?;
+    .await??;
 
+    let pool = state.pool.clone();
+    let id_str = id.clone();
     web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_access(&sessions[0].user_id, &id_str, &mut conn)
+    })
+    .await??;
+
+    let history = web::block(move || {
         let mut conn = state.pool.get()?;
-        remove_store(&id, &mut conn)
+        list_stage_transitions(&id, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(history))
+}
+
+async fn update_store_settings(
+    req: HttpRequest,
+    auth_token: AuthenticationToken,
+    _: RequirePermission<StoresWrite>,
+    id: web::Path<String>,
+    body: web::Json<StoreSettingsPayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+    let actor = Actor::from_request(&req).signed_in(&sessions[0].user_id, &sessions[0].id);
+
+    let pool = state.pool.clone();
+    let id_str = id.clone();
+    web::block(move || {
+        let mut conn = pool.get()?;
+        check_store_role(&sessions[0].user_id, &id_str, StoreRole::Manager, &mut conn)
+    })
+    .await??;
+
+    let settings = unit_of_work(&state.pool, move |conn| {
+        let before = crate::schema::store_settings::table
+            .find(&*id)
+            .first::<StoreSettings>(conn)?;
+        let settings = edit_store_settings(&id, &body, conn)?;
+        record(
+            &actor,
+            Event {
+                action: "store.settings.update",
+                store_id: Some(&id),
+                target_id: Some(&id),
+                before: snapshot(&before),
+                after: snapshot(&settings),
+            },
+            conn,
+        )?;
+        Ok(settings)
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json(settings))
+}
+
+async fn delete_store(
+    req: HttpRequest,
+    auth_token: AuthenticationToken,
+    _: RequirePermission<StoresDelete>,
+    id: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+    let actor = Actor::from_request(&req).signed_in(&sessions[0].user_id, &sessions[0].id);
+
+    unit_of_work(&state.pool, move |conn| {
+        let store = check_store_role(&sessions[0].user_id, &id, StoreRole::Owner, conn)?;
+        remove_store(&id, conn)?;
+        record(
+            &actor,
+            Event {
+                action: "store.delete",
+                store_id: Some(&id),
+                target_id: Some(&id),
+                before: snapshot(&store),
+                after: None,
+            },
+            conn,
+        )
     })
-    .await?
-    .map_err(actix_web::error::ErrorInternalServerError)?;
+    .await?;
 
     Ok(HttpResponse::Ok().json("success"))
 }
 
-fn get_session(user_session_id: &str, conn: &mut PgConnection) -> Result<Vec<Session>, DbError> {
+pub(crate) fn get_session(
+    user_session_id: &str,
+    conn: &mut PgConnection,
+) -> Result<Vec<Session>, ApiError> {
     use crate::schema::session::dsl::*;
 
     let sessions = session
         .filter(id.eq(user_session_id))
         .load::<Session>(conn)?;
 
+    if sessions.is_empty() {
+        return Err(ApiError::SessionNotFound);
+    }
+
     if sessions[0].expires_at < chrono::Local::now().naive_local() {
         diesel::delete(session.find(&sessions[0].id)).get_result::<Session>(conn)?;
-        return Err("User session expired".into());
+        return Err(ApiError::SessionExpired);
     }
 
-    if sessions.is_empty() {
-        Err("User session not found".into())
-    } else {
-        Ok(sessions)
-    }
+    Ok(sessions)
 }
 
-fn get_user_stores(user: &str, conn: &mut PgConnection) -> Result<Vec<StoreJoined>, DbError> {
-    use crate::schema::stores::dsl::*;
-    use crate::schema::user_stores::dsl::*;
+/// One page of the stores `user` belongs to, filtered, sorted and cut at the
+/// cursor in SQL.
+fn get_user_stores(
+    user: &str,
+    params: &ListParams,
+    conn: &mut PgConnection,
+) -> Result<Page<StoreJoined>, ApiError> {
+    use crate::schema::{stores, user_stores};
+
+    let mut query = stores::table
+        .filter(
+            stores::id.eq_any(
+                user_stores::table
+                    .filter(user_stores::user_id.eq(user))
+                    .select(user_stores::store_id),
+            ),
+        )
+        .into_boxed();
 
-    let results: Vec<(UserStore, Option<Store>)> = user_stores
-        .left_outer_join(stores)
-        .filter(store_id.eq(id))
-        .load::<(UserStore, Option<Store>)>(conn)?;
+    for (field, value) in &params.filters {
+        query = match *field {
+            "stage" => query.filter(stores::stage.eq(value)),
+            _ => return Err(ApiError::Internal(format!("No filter for \"{field}\""))),
+        };
+    }
+
+    let after = params.after_condition(|key, value| {
+        Ok(match key.field {
+            "name" => keyset_column(stores::name, value.to_string(), key.descending),
+            "stage" => keyset_column(stores::stage, value.to_string(), key.descending),
+            "created" => {
+                let created = chrono::NaiveDateTime::parse_from_str(value, CURSOR_TIMESTAMP)
+                    .map_err(|_| ApiError::Validation("cursor is invalid".to_string()))?;
+                keyset_column(stores::created_at, created, key.descending)
+            }
+            _ => keyset_column(stores::id, value.to_string(), key.descending),
+        })
+    })?;
+    if let Some(after) = after {
+        query = query.filter(after);
+    }
 
-    let results = results
+    for key in &params.sort {
+        query = match (key.field, key.descending) {
+            ("name", false) => query.then_order_by(stores::name.asc()),
+            ("name", true) => query.then_order_by(stores::name.desc()),
+            ("stage", false) => query.then_order_by(stores::stage.asc()),
+            ("stage", true) => query.then_order_by(stores::stage.desc()),
+            ("created", false) => query.then_order_by(stores::created_at.asc()),
+            ("created", true) => query.then_order_by(stores::created_at.desc()),
+            (_, false) => query.then_order_by(stores::id.asc()),
+            (_, true) => query.then_order_by(stores::id.desc()),
+        };
+    }
+
+    let rows = query
+        .limit(params.fetch_limit())
+        .load::<Store>(conn)?
         .into_iter()
-        .filter_map(|(r#struct, opt)| {
-            opt.map(|_store| StoreJoined {
-                id: _store.id,
-                user_id: r#struct.user_id,
-                name: _store.name,
-                stage: _store.stage,
-            })
+        .map(|store| StoreJoined {
+            id: store.id,
+            user_id: user.to_string(),
+            name: store.name,
+            stage: store.stage,
+            created_at: store.created_at,
         })
-        .filter(|_store| _store.user_id == user)
         .collect();
 
-    Ok(results)
+    Ok(params.page(rows, |store, key| match key.field {
+        "name" => store.name.clone(),
+        "stage" => store.stage.clone(),
+        "created" => store.created_at.format(CURSOR_TIMESTAMP).to_string(),
+        _ => store.id.clone(),
+    }))
+}
+
+/// Loads the store, failing with 404 when it does not exist and with 403
+/// when the user is not linked to it through `user_stores`.
+pub(crate) fn check_store_access(
+    user: &str,
+    store: &str,
+    conn: &mut PgConnection,
+) -> Result<Store, ApiError> {
+    use crate::schema::{stores, user_stores};
+
+    let res = stores::table
+        .find(store)
+        .first::<Store>(conn)
+        .optional()?
+        .ok_or(ApiError::NotFound("Store not found"))?;
+
+    let member: i64 = user_stores::table
+        .filter(user_stores::user_id.eq(user))
+        .filter(user_stores::store_id.eq(store))
+        .count()
+        .get_result(conn)?;
+
+    if member > 0 {
+        Ok(res)
+    } else {
+        Err(ApiError::Forbidden("User is not a member of this store"))
+    }
+}
+
+fn get_store_detail(store: Store, conn: &mut PgConnection) -> Result<StoreDetail, ApiError> {
+    use crate::schema::{products, store_settings};
+
+    let members = list_members(&store.id, conn)?;
+
+    let product_count: i64 = products::table
+        .filter(products::store_id.eq(&store.id))
+        .count()
+        .get_result(conn)?;
+
+    let settings = store_settings::table
+        .find(&store.id)
+        .first::<StoreSettings>(conn)?;
+
+    Ok(StoreDetail {
+        id: store.id,
+        name: store.name,
+        stage: store.stage,
+        members,
+        product_count,
+        settings,
+    })
+}
+
+/// Creates a store with its settings, default location and first stage, and
+/// makes `user` its owner. Runs inside the caller's unit of work.
+fn open_store(
+    user: &str,
+    store_name: &str,
+    store_stage: StoreStage,
+    conn: &mut PgConnection,
+) -> Result<Store, ApiError> {
+    let store = add_store(user, store_name, store_stage, conn)?;
+    add_user_store(user, &store.id, StoreRole::Owner, conn)?;
+    Ok(store)
 }
 
 fn add_store(
+    user: &str,
     store_name: &str,
-    store_stage: &str,
+    store_stage: StoreStage,
     conn: &mut PgConnection,
-) -> Result<Store, DbError> {
+) -> Result<Store, ApiError> {
     use crate::schema::stores::dsl::*;
 
+    if !matches!(store_stage, StoreStage::Draft | StoreStage::Setup) {
+        return Err(ApiError::Validation(
+            "New stores start in draft or setup; publish them once they are ready".to_string(),
+        ));
+    }
+
     let new_store = NewStore {
         id: &Uuid::new_v4().to_string(),
         name: store_name,
-        stage: store_stage,
+        stage: store_stage.as_str(),
     };
 
     let res: Store = diesel::insert_into(stores)
         .values(&new_store)
         .get_result(conn)?;
 
+    diesel::insert_into(crate::schema::store_settings::table)
+        .values(&NewStoreSettings {
+            store_id: &res.id,
+            contact_email: None,
+            timezone: "UTC",
+            currency: Currency::USD.as_str(),
+        })
+        .execute(conn)?;
+
+    add_location(&res.id, "Default", true, conn)?;
+    record_stage_transition(&res.id, None, store_stage, user, conn)?;
+
     Ok(res)
 }
 
-fn add_user_store(user: &str, store: &str, conn: &mut PgConnection) -> Result<UserStore, DbError> {
+fn add_user_store(
+    user: &str,
+    store: &str,
+    store_role: StoreRole,
+    conn: &mut PgConnection,
+) -> Result<UserStore, ApiError> {
     use crate::schema::user_stores::dsl::*;
 
     let new_user_store = NewUserStore {
         user_id: user,
         store_id: store,
+        role: store_role.as_str(),
     };
 
     let res = diesel::insert_into(user_stores)
@@ -219,35 +789,505 @@ fn add_user_store(user: &str, store: &str, conn: &mut PgConnection) -> Result<Us
 }
 
 fn edit_store(
+    user: &str,
     _id: &str,
-    _name: &str,
-    _stage: &str,
+    body: &StorePayload,
     conn: &mut PgConnection,
-) -> Result<Store, DbError> {
+) -> Result<Store, ApiError> {
+    use crate::schema::stores::dsl::*;
+
+    conn.transaction(|conn| {
+        let current = lock_store(_id, conn)?;
+        if let Some(to) = body.stage {
+            if current.stage.parse::<StoreStage>()? != to {
+                move_store(user, &current, to, conn)?;
+            }
+        }
+
+        let store = diesel::update(stores.find(_id))
+            .set(name.eq(&body.name))
+            .get_result::<Store>(conn)?;
+        Ok(store)
+    })
+}
+
+fn lock_store(store: &str, conn: &mut PgConnection) -> Result<Store, ApiError> {
     use crate::schema::stores::dsl::*;
 
-    let store = diesel::update(stores.find(_id))
-        .set((name.eq(_name), stage.eq(_stage)))
+    let res = stores
+        .find(store)
+        .for_update()
+        .first::<Store>(conn)
+        .optional()?
+        .ok_or(ApiError::NotFound("Store not found"))?;
+    Ok(res)
+}
+
+/// Loads a store buyers can shop in. The share lock keeps it from being
+/// paused or archived until the caller's transaction ends.
+pub(crate) fn find_live_store(store: &str, conn: &mut PgConnection) -> Result<Store, ApiError> {
+    use crate::schema::stores::dsl::*;
+
+    let res = stores
+        .find(store)
+        .for_share()
+        .first::<Store>(conn)
+        .optional()?
+        .ok_or(ApiError::NotFound("Store not found"))?;
+    if res.stage.parse::<StoreStage>()? != StoreStage::Live {
+        return Err(ApiError::Conflict(format!(
+            "Store is {} and not open to buyers",
+            res.stage
+        )));
+    }
+    Ok(res)
+}
+
+fn transition_store(
+    user: &str,
+    store: &str,
+    to: StoreStage,
+    conn: &mut PgConnection,
+) -> Result<Store, ApiError> {
+    conn.transaction(|conn| {
+        let current = lock_store(store, conn)?;
+        move_store(user, &current, to, conn)
+    })
+}
+
+/// Moves an already locked store to `to`, checking that the store is ready
+/// for it, and records the move in the stage history.
+fn move_store(
+    user: &str,
+    current: &Store,
+    to: StoreStage,
+    conn: &mut PgConnection,
+) -> Result<Store, ApiError> {
+    use crate::schema::{orders, products, stores};
+
+    let from = current.stage.parse::<StoreStage>()?;
+    let next = from.transition(to)?;
+
+    match next {
+        StoreStage::Live => {
+            let product_count: i64 = products::table
+                .filter(products::store_id.eq(&current.id))
+                .count()
+                .get_result(conn)?;
+            if product_count == 0 {
+                return Err(ApiError::Validation(
+                    "A store needs at least one product to go live".to_string(),
+                ));
+            }
+        }
+        StoreStage::Archived => {
+            let open = [
+                OrderStatus::Pending,
+                OrderStatus::Paid,
+                OrderStatus::Fulfilled,
+            ]
+            .map(OrderStatus::as_str);
+            let open_orders: i64 = orders::table
+                .filter(orders::store_id.eq(&current.id))
+                .filter(orders::status.eq_any(open))
+                .count()
+                .get_result(conn)?;
+            if open_orders > 0 {
+                return Err(ApiError::Validation(format!(
+                    "Store has {open_orders} open orders; complete or cancel them before archiving"
+                )));
+            }
+        }
+        StoreStage::Draft | StoreStage::Setup | StoreStage::Paused => {}
+    }
+
+    let res = diesel::update(stores::table.find(&current.id))
+        .set(stores::stage.eq(next.as_str()))
         .get_result::<Store>(conn)?;
-    Ok(store)
+    record_stage_transition(&current.id, Some(from), next, user, conn)?;
+    Ok(res)
 }
 
-fn remove_user_store(
-    _id: &str,
-    _user_id: &str,
+fn record_stage_transition(
+    store: &str,
+    from: Option<StoreStage>,
+    to: StoreStage,
+    user: &str,
     conn: &mut PgConnection,
-) -> Result<UserStore, DbError> {
-    use crate::schema::user_stores::dsl::*;
+) -> Result<(), ApiError> {
+    use crate::schema::store_stage_transitions;
 
-    let user_store =
-        diesel::delete(user_stores.find((_user_id, _id))).get_result::<UserStore>(conn)?;
-    Ok(user_store)
+    diesel::insert_into(store_stage_transitions::table)
+        .values(&NewStoreStageTransition {
+            id: &Uuid::new_v4().to_string(),
+            store_id: store,
+            from_stage: from.map(StoreStage::as_str),
+            to_stage: to.as_str(),
+            user_id: Some(user),
+        })
+        .execute(conn)?;
+    Ok(())
 }
 
-fn remove_store(_id: &str, conn: &mut PgConnection) -> Result<Store, DbError> {
-    use crate::schema::stores::dsl::*;
+fn list_stage_transitions(
+    store: &str,
+    conn: &mut PgConnection,
+) -> Result<Vec<StoreStageTransition>, ApiError> {
+    use crate::schema::store_stage_transitions::dsl::*;
+
+    let res = store_stage_transitions
+        .filter(store_id.eq(store))
+        .order(created_at.asc())
+        .select(StoreStageTransition::as_select())
+        .load::<StoreStageTransition>(conn)?;
+    Ok(res)
+}
+
+fn edit_store_settings(
+    store: &str,
+    body: &StoreSettingsPayload,
+    conn: &mut PgConnection,
+) -> Result<StoreSettings, ApiError> {
+    use crate::schema::store_settings::dsl::*;
+
+    if body.timezone.trim().is_empty() {
+        return Err(ApiError::Validation(
+            "Store timezone must not be empty".to_string(),
+        ));
+    }
+
+    let settings = diesel::update(store_settings.find(store))
+        .set((
+            contact_email.eq(body.contact_email.as_deref()),
+            timezone.eq(body.timezone.trim()),
+            body.currency.map(|code| currency.eq(code.to_string())),
+        ))
+        .get_result::<StoreSettings>(conn)?;
+    Ok(settings)
+}
+
+/// The currency new prices in `store` default to.
+pub(crate) fn store_currency(store: &str, conn: &mut PgConnection) -> Result<Currency, ApiError> {
+    use crate::schema::store_settings::dsl::*;
+
+    let code = store_settings
+        .find(store)
+        .select(currency)
+        .first::<String>(conn)
+        .optional()?
+        .ok_or(ApiError::NotFound("Store not found"))?;
+    Ok(code.parse()?)
+}
+
+/// Deletes a store with its memberships and settings. Runs inside the
+/// caller's unit of work. A store that has traded keeps its history and can
+/// only be archived.
+fn remove_store(_id: &str, conn: &mut PgConnection) -> Result<Store, ApiError> {
+    use crate::schema::{
+        orders, payments, products, sales, stock_movements, store_settings, stores, user_stores,
+    };
+
+    let history: [i64; 5] = [
+        products::table
+            .filter(products::store_id.eq(_id))
+            .count()
+            .get_result(conn)?,
+        orders::table
+            .filter(orders::store_id.eq(_id))
+            .count()
+            .get_result(conn)?,
+        sales::table
+            .filter(sales::store_id.eq(_id))
+            .count()
+            .get_result(conn)?,
+        payments::table
+            .filter(payments::store_id.eq(_id))
+            .count()
+            .get_result(conn)?,
+        stock_movements::table
+            .filter(stock_movements::store_id.eq(_id))
+            .count()
+            .get_result(conn)?,
+    ];
+    if history.iter().any(|rows| *rows > 0) {
+        return Err(ApiError::Conflict(
+            "Archive stores that have products or orders instead of deleting them".to_string(),
+        ));
+    }
+
+    diesel::delete(user_stores::table.filter(user_stores::store_id.eq(_id))).execute(conn)?;
+    diesel::delete(store_settings::table.find(_id)).execute(conn)?;
 
-    let count = diesel::delete(stores.find(_id)).get_result::<Store>(conn)?;
+    let count = diesel::delete(stores::table.find(_id)).get_result::<Store>(conn)?;
 
     Ok(count)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{find_live_store, open_store, remove_store};
+    use crate::{
+        db::{
+            test_support::{create_user, test_pool},
+            unit_of_work,
+        },
+        errors::ApiError,
+        models::NewProduct,
+        schema::{store_settings, store_stage_transitions, stores, user_stores},
+        scopes::{
+            member::{check_store_role, StoreRole},
+            product::{check_product_role, find_member_product},
+        },
+        store_stage::StoreStage,
+    };
+    use actix_web::{http::StatusCode, ResponseError};
+    use diesel::{ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl};
+
+    /// Rows a store creation writes, across every table it touches.
+    fn store_rows(conn: &mut PgConnection) -> [i64; 5] {
+        use crate::schema::stock_locations;
+
+        [
+            stores::table.count().get_result(conn).unwrap(),
+            store_settings::table.count().get_result(conn).unwrap(),
+            stock_locations::table.count().get_result(conn).unwrap(),
+            store_stage_transitions::table
+                .count()
+                .get_result(conn)
+                .unwrap(),
+            user_stores::table.count().get_result(conn).unwrap(),
+        ]
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a migrated database in DATABASE_URL"]
+    async fn creating_a_store_links_its_owner() {
+        let pool = test_pool();
+        let owner = create_user(&mut pool.get().unwrap());
+
+        let user = owner.clone();
+        let store = unit_of_work(&pool, move |conn| {
+            open_store(&user, "Corner shop", StoreStage::Draft, conn)
+        })
+        .await
+        .unwrap();
+
+        let role = user_stores::table
+            .find((&owner, &store.id))
+            .select(user_stores::role)
+            .first::<String>(&mut pool.get().unwrap())
+            .unwrap();
+        assert_eq!(role, "owner");
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a migrated database in DATABASE_URL"]
+    async fn failed_store_creation_leaves_nothing_behind() {
+        let pool = test_pool();
+        let before = store_rows(&mut pool.get().unwrap());
+
+        // The store, its settings, location and stage are written before the
+        // owner link fails on the unknown user.
+        let res = unit_of_work(&pool, |conn| {
+            open_store("no-such-user", "Orphan", StoreStage::Draft, conn)
+        })
+        .await;
+
+        assert!(res.is_err());
+        assert_eq!(store_rows(&mut pool.get().unwrap()), before);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a migrated database in DATABASE_URL"]
+    async fn deleting_a_store_removes_every_member() {
+        let pool = test_pool();
+        let mut conn = pool.get().unwrap();
+        let owner = create_user(&mut conn);
+        let member = create_user(&mut conn);
+        drop(conn);
+
+        let store = unit_of_work(&pool, move |conn| {
+            let store = open_store(&owner, "Shared", StoreStage::Draft, conn)?;
+            diesel::insert_into(user_stores::table)
+                .values((
+                    user_stores::user_id.eq(&member),
+                    user_stores::store_id.eq(&store.id),
+                    user_stores::role.eq("staff"),
+                ))
+                .execute(conn)?;
+            Ok(store)
+        })
+        .await
+        .unwrap();
+
+        let id = store.id.clone();
+        unit_of_work(&pool, move |conn| remove_store(&id, conn))
+            .await
+            .unwrap();
+
+        let mut conn = pool.get().unwrap();
+        let links: i64 = user_stores::table
+            .filter(user_stores::store_id.eq(&store.id))
+            .count()
+            .get_result(&mut conn)
+            .unwrap();
+        assert_eq!(links, 0);
+        let left: i64 = stores::table
+            .find(&store.id)
+            .count()
+            .get_result(&mut conn)
+            .unwrap();
+        assert_eq!(left, 0);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a migrated database in DATABASE_URL"]
+    async fn failed_store_deletion_keeps_members_and_settings() {
+        let pool = test_pool();
+        let owner = create_user(&mut pool.get().unwrap());
+
+        let store = unit_of_work(&pool, move |conn| {
+            open_store(&owner, "Doomed", StoreStage::Draft, conn)
+        })
+        .await
+        .unwrap();
+        let before = store_rows(&mut pool.get().unwrap());
+
+        // Whatever the handler does after the delete fails, after memberships,
+        // settings and the store row are already gone.
+        let id = store.id.clone();
+        let res = unit_of_work(&pool, move |conn| {
+            remove_store(&id, conn)?;
+            Err::<(), _>(ApiError::Internal("injected failure".to_string()))
+        })
+        .await;
+
+        assert!(res.is_err());
+        assert_eq!(store_rows(&mut pool.get().unwrap()), before);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a migrated database in DATABASE_URL"]
+    async fn stores_with_products_are_archived_not_deleted() {
+        let pool = test_pool();
+        let owner = create_user(&mut pool.get().unwrap());
+
+        let store = unit_of_work(&pool, move |conn| {
+            let store = open_store(&owner, "Stocked", StoreStage::Draft, conn)?;
+            diesel::insert_into(crate::schema::products::table)
+                .values(&NewProduct {
+                    id: &uuid::Uuid::new_v4().to_string(),
+                    title: "Mug",
+                    description: None,
+                    price: &500,
+                    currency: "USD",
+                    quantity: &0,
+                    store_id: &store.id,
+                })
+                .execute(conn)?;
+            Ok(store)
+        })
+        .await
+        .unwrap();
+        let before = store_rows(&mut pool.get().unwrap());
+
+        let id = store.id.clone();
+        let res = unit_of_work(&pool, move |conn| remove_store(&id, conn)).await;
+
+        match res {
+            Err(ApiError::Conflict(message)) => assert_eq!(
+                message,
+                "Archive stores that have products or orders instead of deleting them"
+            ),
+            other => panic!("expected a conflict, got {other:?}"),
+        }
+        assert_eq!(store_rows(&mut pool.get().unwrap()), before);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a migrated database in DATABASE_URL"]
+    async fn only_live_stores_are_open_to_buyers() {
+        let pool = test_pool();
+        let owner = create_user(&mut pool.get().unwrap());
+
+        let store = unit_of_work(&pool, move |conn| {
+            open_store(&owner, "Pop-up", StoreStage::Draft, conn)
+        })
+        .await
+        .unwrap();
+
+        let mut conn = pool.get().unwrap();
+        for (stage, open) in [
+            (StoreStage::Draft, false),
+            (StoreStage::Live, true),
+            (StoreStage::Paused, false),
+            (StoreStage::Archived, false),
+        ] {
+            diesel::update(stores::table.find(&store.id))
+                .set(stores::stage.eq(stage.as_str()))
+                .execute(&mut conn)
+                .unwrap();
+            let res = find_live_store(&store.id, &mut conn);
+            if open {
+                assert!(res.is_ok(), "{stage}");
+            } else {
+                assert!(matches!(res, Err(ApiError::Conflict(_))), "{stage}");
+            }
+        }
+        assert!(matches!(
+            find_live_store("no-such-store", &mut conn),
+            Err(ApiError::NotFound(_))
+        ));
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a migrated database in DATABASE_URL"]
+    async fn viewers_can_read_but_not_change_a_store() {
+        let pool = test_pool();
+        let mut conn = pool.get().unwrap();
+        let owner = create_user(&mut conn);
+        let viewer = create_user(&mut conn);
+        let staff = create_user(&mut conn);
+        drop(conn);
+
+        let (viewer_id, staff_id) = (viewer.clone(), staff.clone());
+        let (store, product) = unit_of_work(&pool, move |conn| {
+            let store = open_store(&owner, "Watched", StoreStage::Draft, conn)?;
+            for (user, role) in [(&viewer_id, "viewer"), (&staff_id, "staff")] {
+                diesel::insert_into(user_stores::table)
+                    .values((
+                        user_stores::user_id.eq(user),
+                        user_stores::store_id.eq(&store.id),
+                        user_stores::role.eq(role),
+                    ))
+                    .execute(conn)?;
+            }
+            let product = uuid::Uuid::new_v4().to_string();
+            diesel::insert_into(crate::schema::products::table)
+                .values(&NewProduct {
+                    id: &product,
+                    title: "Mug",
+                    description: None,
+                    price: &500,
+                    currency: "USD",
+                    quantity: &0,
+                    store_id: &store.id,
+                })
+                .execute(conn)?;
+            Ok((store.id, product))
+        })
+        .await
+        .unwrap();
+
+        let mut conn = pool.get().unwrap();
+        assert!(find_member_product(&viewer, &product, &mut conn).is_ok());
+        let res = check_product_role(&viewer, &product, StoreRole::Staff, &mut conn);
+        assert!(matches!(&res, Err(ApiError::Forbidden(_))));
+        assert_eq!(res.unwrap_err().status_code(), StatusCode::FORBIDDEN);
+        let res = check_store_role(&viewer, &store, StoreRole::Staff, &mut conn);
+        assert!(matches!(res, Err(ApiError::Forbidden(_))));
+
+        assert!(check_product_role(&staff, &product, StoreRole::Staff, &mut conn).is_ok());
+        assert!(check_store_role(&staff, &store, StoreRole::Staff, &mut conn).is_ok());
+    }
+}
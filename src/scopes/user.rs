@@ -1,25 +1,47 @@
 use crate::{
-    extractors::authentication_token::{AuthenticationToken, Claims},
-    models::{NewSession, NewUser, Role, Session, User},
+    db::unit_of_work,
+    errors::ApiError,
+    extractors::{
+        authentication_token::{AuthenticationToken, Claims},
+        cart_owner::read_cart_token,
+    },
+    models::{NewRefreshToken, NewSession, NewUser, RefreshToken, Role, Session, User},
+    password::{PasswordHasher, Verification},
+    scopes::{
+        audit::{record, Actor, Event},
+        cart::merge_guest_cart,
+        store::get_session,
+    },
+    seed::SIGN_UP_ROLE,
+    token::{hash_token, random_token},
     AppState,
 };
-use actix_web::{web, Error, HttpResponse, Scope};
+use actix_web::{web, HttpRequest, HttpResponse, Scope};
 use chrono::{Duration, Utc};
-use diesel::{ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl};
+use diesel::{
+    Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl,
+};
 use jsonwebtoken::{
-    decode, encode, errors::Error as JwtError, Algorithm, DecodingKey, EncodingKey, Header,
-    TokenData, Validation,
+    decode, encode,
+    errors::{Error as JwtError, ErrorKind},
+    Algorithm, DecodingKey, EncodingKey, Header, TokenData, Validation,
 };
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-pub type DbError = Box<dyn std::error::Error + Send + Sync>;
-
 pub fn user_scope() -> Scope {
     web::scope("/user")
         .route("/sign-up", web::post().to(sign_up))
         .route("/sign-in", web::post().to(sign_in))
+        .route("/refresh", web::post().to(refresh))
+        .route("/logout", web::post().to(logout))
+        .route("/sessions", web::get().to(get_sessions))
+        .route(
+            "/sessions/revoke-others",
+            web::post().to(revoke_other_sessions),
+        )
+        .route("/sessions/{id}", web::delete().to(revoke_session))
         .route("/decode-token", web::post().to(decode_token))
         .route("/protected", web::post().to(protected))
 }
@@ -39,82 +61,116 @@ struct EncodeBody {
 struct EncodeResponse {
     message: String,
     token: String,
+    refresh_token: String,
 }
 
-async fn sign_up(
-    body: web::Json<EncodeBody>,
-    state: web::Data<AppState>,
-) -> Result<HttpResponse, Error> {
-    let mut rng = rand::thread_rng();
-    let id: usize = rng.gen();
-    let exp: usize = (Utc::now() + Duration::hours(24)).timestamp() as usize;
-    let claims: Claims = Claims { id, exp };
-    let token: String = encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(state.secret.as_str().as_ref()),
-    )
-    .unwrap();
+#[derive(Serialize, Deserialize)]
+struct RefreshBody {
+    refresh_token: String,
+}
 
-    let pool_clone = state.pool.clone();
-    let body_clone = body.clone();
-    web::block(move || {
-        let mut conn = pool_clone.get()?;
-        check_user(body_clone.email, &mut conn, true)
-    })
-    .await?
-    .map_err(actix_web::error::ErrorInternalServerError)?;
+/// Where a sign-in came from, shown back to the user in `/user/sessions`.
+struct SessionClient {
+    user_agent: Option<String>,
+    ip: Option<String>,
+}
 
-    let pool_clone = state.pool.clone();
-    let role = web::block(move || {
-        let mut conn = pool_clone.get()?;
-        get_admin(&mut conn)
-    })
-    .await?
-    .map_err(actix_web::error::ErrorInternalServerError)?;
+impl SessionClient {
+    fn from_request(req: &HttpRequest) -> Self {
+        SessionClient {
+            user_agent: req
+                .headers()
+                .get(actix_web::http::header::USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(String::from),
+            ip: req.connection_info().realip_remote_addr().map(String::from),
+        }
+    }
+}
 
-    let mut role_id = role[0].id.clone();
+#[derive(Serialize, Deserialize)]
+struct SessionSummary {
+    id: String,
+    created_at: chrono::NaiveDateTime,
+    expires_at: chrono::NaiveDateTime,
+    user_agent: Option<String>,
+    ip: Option<String>,
+    current: bool,
+}
 
-    let pool_clone = state.pool.clone();
-    let user = web::block(move || {
-        let mut conn = pool_clone.get()?;
-        // let hashed_password: String = hash_password(&body.password)?;
+enum Rotation {
+    Rotated {
+        token: String,
+        refresh_token: String,
+    },
+    Reused,
+    Expired,
+}
 
-        add_user(&role_id, &body, &mut conn)
-    })
-    .await?
-    .map_err(actix_web::error::ErrorInternalServerError)?;
+async fn sign_up(
+    req: HttpRequest,
+    body: web::Json<EncodeBody>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let client = SessionClient::from_request(&req);
+    let actor = Actor::from_request(&req);
+    let cart_token = read_cart_token(&req);
+    let mut rng = rand::thread_rng();
+    let id: usize = rng.gen();
+    let token = encode_access_token(id, &state.secret, state.access_token_ttl)?;
 
+    // The account and its first session are created together, so a failure
+    // part way never leaves behind a user who cannot sign in.
     let token_clone = token.clone();
-    role_id = role[0].id.clone();
-    web::block(move || {
-        let mut conn = state.pool.get()?;
-
-        add_to_session(&mut conn, &id.to_string(), &user.id, &role_id, &token_clone)
+    let hasher = state.hasher.clone();
+    let session_ttl = state.session_ttl;
+    let refresh_token = unit_of_work(&state.pool, move |conn| {
+        let user = register_user(&body, &hasher, conn)?;
+        add_to_session(
+            conn,
+            &id.to_string(),
+            &user.id,
+            &user.role_id,
+            &token_clone,
+            session_ttl,
+            &client,
+        )?;
+        if let Some(cart_token) = &cart_token {
+            merge_guest_cart(cart_token, &user.id, conn)?;
+        }
+        record(
+            &actor.signed_in(&user.id, &id.to_string()),
+            Event {
+                action: "user.sign_up",
+                store_id: None,
+                target_id: Some(&user.id),
+                before: None,
+                after: Some(serde_json::json!({ "email": user.email, "role_id": user.role_id })),
+            },
+            conn,
+        )?;
+        add_refresh_token(&id.to_string(), conn)
     })
-    .await?
-    .map_err(actix_web::error::ErrorInternalServerError)?;
+    .await?;
 
     Ok(HttpResponse::Ok().json(EncodeResponse {
         message: String::from("Authorized"),
         token,
+        refresh_token,
     }))
 }
 
 async fn sign_in(
+    req: HttpRequest,
     body: web::Json<EncodeBody>,
     state: web::Data<AppState>,
-) -> Result<HttpResponse, Error> {
+) -> Result<HttpResponse, ApiError> {
+    let client = SessionClient::from_request(&req);
+    let actor = Actor::from_request(&req);
+    let cart_token = read_cart_token(&req);
     let mut rng = rand::thread_rng();
     let id: usize = rng.gen();
-    let exp: usize = (Utc::now() + Duration::hours(24)).timestamp() as usize;
-    let claims: Claims = Claims { id, exp };
-    let token: String = encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(state.secret.as_str().as_ref()),
-    )
-    .unwrap();
+    let token = encode_access_token(id, &state.secret, state.access_token_ttl)?;
 
     let pool_clone = state.pool.clone();
     let body_clone = body.clone();
@@ -122,38 +178,198 @@ async fn sign_in(
         let mut conn = pool_clone.get()?;
         check_user(body_clone.email, &mut conn, false)
     })
-    .await?
-    .map_err(actix_web::error::ErrorInternalServerError)?;
+    .await??;
 
     let pool_clone = state.pool.clone();
+    let hasher = state.hasher.clone();
     let users = web::block(move || {
         let mut conn = pool_clone.get()?;
-        validate_user(&body.email, &body.password, &mut conn)
+        validate_user(&body.email, &body.password, &hasher, &mut conn)
     })
-    .await?
-    .map_err(actix_web::error::ErrorInternalServerError)?;
+    .await??;
 
     let token_clone = token.clone();
-    web::block(move || {
-        let mut conn = state.pool.get()?;
-
+    let session_ttl = state.session_ttl;
+    let refresh_token = unit_of_work(&state.pool, move |conn| {
         add_to_session(
-            &mut conn,
+            conn,
             &id.to_string(),
             &users[0].id,
             &users[0].role_id,
             &token_clone,
-        )
+            session_ttl,
+            &client,
+        )?;
+        if let Some(cart_token) = &cart_token {
+            merge_guest_cart(cart_token, &users[0].id, conn)?;
+        }
+        record(
+            &actor.signed_in(&users[0].id, &id.to_string()),
+            Event {
+                action: "user.sign_in",
+                store_id: None,
+                target_id: Some(&users[0].id),
+                before: None,
+                after: None,
+            },
+            conn,
+        )?;
+        add_refresh_token(&id.to_string(), conn)
     })
-    .await?
-    .map_err(actix_web::error::ErrorInternalServerError)?;
+    .await?;
 
     Ok(HttpResponse::Ok().json(EncodeResponse {
         message: String::from("Authorized"),
         token,
+        refresh_token,
     }))
 }
 
+async fn refresh(
+    req: HttpRequest,
+    body: web::Json<RefreshBody>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let actor = Actor::from_request(&req);
+    let rotation = web::block(move || {
+        let mut conn = state.pool.get()?;
+        rotate_refresh_token(
+            &body.refresh_token,
+            &state.secret,
+            state.access_token_ttl,
+            state.session_ttl,
+            &actor,
+            &mut conn,
+        )
+    })
+    .await??;
+
+    match rotation {
+        Rotation::Rotated {
+            token,
+            refresh_token,
+        } => Ok(HttpResponse::Ok().json(EncodeResponse {
+            message: String::from("Authorized"),
+            token,
+            refresh_token,
+        })),
+        Rotation::Reused => Err(ApiError::RefreshTokenReused),
+        Rotation::Expired => Err(ApiError::SessionExpired),
+    }
+}
+
+async fn logout(
+    req: HttpRequest,
+    auth_token: AuthenticationToken,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+    let actor = Actor::from_request(&req).signed_in(&sessions[0].user_id, &sessions[0].id);
+
+    unit_of_work(&state.pool, move |conn| {
+        remove_session(&sessions[0].id, conn)?;
+        record(
+            &actor,
+            Event {
+                action: "user.logout",
+                store_id: None,
+                target_id: Some(&sessions[0].id),
+                before: None,
+                after: None,
+            },
+            conn,
+        )
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json("success"))
+}
+
+async fn get_sessions(
+    auth_token: AuthenticationToken,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let sessions = web::block(move || {
+        let mut conn = state.pool.get()?;
+        list_user_sessions(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(sessions))
+}
+
+async fn revoke_session(
+    req: HttpRequest,
+    auth_token: AuthenticationToken,
+    id: web::Path<String>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let actor = Actor::from_request(&req).signed_in(&sessions[0].user_id, &sessions[0].id);
+
+    unit_of_work(&state.pool, move |conn| {
+        remove_user_session(&sessions[0].user_id, &id, conn)?;
+        record(
+            &actor,
+            Event {
+                action: "session.revoke",
+                store_id: None,
+                target_id: Some(&id),
+                before: None,
+                after: None,
+            },
+            conn,
+        )
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json("success"))
+}
+
+async fn revoke_other_sessions(
+    req: HttpRequest,
+    auth_token: AuthenticationToken,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let actor = Actor::from_request(&req).signed_in(&sessions[0].user_id, &sessions[0].id);
+
+    unit_of_work(&state.pool, move |conn| {
+        let revoked = remove_other_sessions(&sessions[0].user_id, &sessions[0].id, conn)?;
+        record(
+            &actor,
+            Event {
+                action: "session.revoke_others",
+                store_id: None,
+                target_id: Some(&sessions[0].user_id),
+                before: None,
+                after: Some(serde_json::json!({ "revoked": revoked })),
+            },
+            conn,
+        )
+    })
+    .await?;
+
+    Ok(HttpResponse::Ok().json("success"))
+}
+
 #[derive(Serialize, Deserialize)]
 struct DecodeResponse {
     message: String,
@@ -165,7 +381,10 @@ struct DecodeBody {
     token: String,
 }
 
-async fn decode_token(body: web::Json<DecodeBody>, state: web::Data<AppState>) -> HttpResponse {
+async fn decode_token(
+    body: web::Json<DecodeBody>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
     let token_result: Result<TokenData<Claims>, JwtError> = decode::<Claims>(
         &body.token,
         &DecodingKey::from_secret(state.secret.as_str().as_ref()),
@@ -173,34 +392,59 @@ async fn decode_token(body: web::Json<DecodeBody>, state: web::Data<AppState>) -
     );
 
     match token_result {
-        Ok(token) => HttpResponse::Ok().json(DecodeResponse {
+        Ok(token) => Ok(HttpResponse::Ok().json(DecodeResponse {
             message: String::from("Successfully logged in."),
             id: token.claims.id,
-        }),
-        Err(e) => HttpResponse::Unauthorized().json(Response {
-            message: e.to_string(),
-        }),
+        })),
+        Err(err) => match err.kind() {
+            ErrorKind::ExpiredSignature => Err(ApiError::TokenExpired),
+            _ => Err(ApiError::Unauthorized("Invalid authentication token sent!")),
+        },
     }
 }
 
-async fn protected(auth_token: AuthenticationToken) -> Result<HttpResponse, Error> {
+async fn protected(_auth_token: AuthenticationToken) -> Result<HttpResponse, ApiError> {
     Ok(HttpResponse::Ok().json(Response {
         message: String::from("Authorized"),
     }))
 }
 
+fn encode_access_token(id: usize, secret: &str, ttl: Duration) -> Result<String, ApiError> {
+    let exp: usize = (Utc::now() + ttl).timestamp() as usize;
+    let claims: Claims = Claims { id, exp };
+    let token: String = encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(secret.as_ref()),
+    )?;
+    Ok(token)
+}
+
+/// Adds a user with the sign-up role, unless the email is already taken.
+fn register_user(
+    body: &EncodeBody,
+    hasher: &PasswordHasher,
+    conn: &mut PgConnection,
+) -> Result<User, ApiError> {
+    check_user(body.email.clone(), conn, true)?;
+    let role = get_role(SIGN_UP_ROLE, conn)?;
+    add_user(&role.id, body, hasher, conn)
+}
+
 fn add_user(
     roles_id: &str,
-    body: &web::Json<EncodeBody>,
+    body: &EncodeBody,
+    hasher: &PasswordHasher,
     conn: &mut PgConnection,
-) -> Result<User, DbError> {
+) -> Result<User, ApiError> {
     use crate::schema::users::dsl::*;
 
+    let hashed_password = hasher.hash(&body.password)?;
     let new_user = NewUser {
         id: &Uuid::new_v4().to_string(),
         role_id: roles_id,
         email: &body.email,
-        password: &body.password,
+        password: &hashed_password,
     };
 
     let res = diesel::insert_into(users)
@@ -213,7 +457,7 @@ fn check_user(
     user_email: String,
     conn: &mut PgConnection,
     sign_up: bool,
-) -> Result<Vec<User>, DbError> {
+) -> Result<Vec<User>, ApiError> {
     use crate::schema::users::dsl::*;
     let user = users.filter(email.eq(user_email)).load::<User>(conn)?;
 
@@ -221,21 +465,23 @@ fn check_user(
         if user.is_empty() {
             Ok(user)
         } else {
-            Err("Email has already registered".into())
+            Err(ApiError::EmailTaken)
         }
+    } else if user.is_empty() {
+        Err(ApiError::InvalidCredentials)
     } else {
-        if user.is_empty() {
-            Err("Email has not been registered".into())
-        } else {
-            Ok(user)
-        }
+        Ok(user)
     }
 }
 
-fn get_admin(conn: &mut PgConnection) -> Result<Vec<Role>, DbError> {
+fn get_role(role_name: &str, conn: &mut PgConnection) -> Result<Role, ApiError> {
     use crate::schema::roles::dsl::*;
-    let role = roles.filter(name.eq("admin")).load::<Role>(conn)?;
-    Ok(role)
+    let role = roles
+        .filter(name.eq(role_name))
+        .first::<Role>(conn)
+        .optional()?;
+
+    role.ok_or_else(|| ApiError::Internal(format!("Role \"{role_name}\" does not exist")))
 }
 
 fn add_to_session(
@@ -244,20 +490,23 @@ fn add_to_session(
     user: &str,
     role: &str,
     token: &str,
-) -> Result<Session, DbError> {
+    ttl: Duration,
+    client: &SessionClient,
+) -> Result<Session, ApiError> {
     use crate::schema::session::dsl::*;
 
     diesel::delete(session.filter(user_id.eq(user)))
         .filter(expires_at.lt(chrono::Local::now().naive_local()))
-        .execute(conn)
-        .expect("Error deleting old sessions");
+        .execute(conn)?;
 
     let new_session = NewSession {
         id: claim_id,
         user_id: user,
         role_id: role,
         access_token: token,
-        expires_at: chrono::Local::now().naive_local() + Duration::days(1),
+        expires_at: chrono::Local::now().naive_local() + ttl,
+        user_agent: client.user_agent.as_deref(),
+        ip: client.ip.as_deref(),
     };
 
     let res = diesel::insert_into(session)
@@ -269,15 +518,306 @@ fn add_to_session(
 fn validate_user(
     user_email: &str,
     user_password: &str,
+    hasher: &PasswordHasher,
     conn: &mut PgConnection,
-) -> Result<Vec<User>, DbError> {
+) -> Result<Vec<User>, ApiError> {
     use crate::schema::users::dsl::*;
 
-    let user = users.filter(email.eq(user_email)).load::<User>(conn)?;
+    let mut user = users.filter(email.eq(user_email)).load::<User>(conn)?;
 
-    if !user.is_empty() && user_password == user[0].password {
-        Ok(user)
-    } else {
-        Err("Invalid email or password".into())
+    if user.is_empty() {
+        return Err(ApiError::InvalidCredentials);
+    }
+
+    match hasher.verify(user_password, &user[0].password)? {
+        Verification::Valid => Ok(user),
+        Verification::ValidNeedsRehash => {
+            let hashed_password = hasher.hash(user_password)?;
+            user[0] = diesel::update(users.find(&user[0].id))
+                .set(password.eq(hashed_password))
+                .get_result::<User>(conn)?;
+            Ok(user)
+        }
+        Verification::Invalid => Err(ApiError::InvalidCredentials),
+    }
+}
+
+fn add_refresh_token(claim_id: &str, conn: &mut PgConnection) -> Result<String, ApiError> {
+    use crate::schema::refresh_tokens::dsl::*;
+
+    let token = random_token();
+
+    let new_refresh_token = NewRefreshToken {
+        id: &Uuid::new_v4().to_string(),
+        session_id: claim_id,
+        token_hash: &hash_token(&token),
+    };
+
+    diesel::insert_into(refresh_tokens)
+        .values(&new_refresh_token)
+        .execute(conn)?;
+    Ok(token)
+}
+
+/// Trades a refresh token for a new access/refresh pair on the same session.
+/// Presenting a token that was already rotated means it leaked, so the whole
+/// session and every refresh token issued for it are revoked.
+fn rotate_refresh_token(
+    token: &str,
+    secret: &str,
+    access_token_ttl: Duration,
+    session_ttl: Duration,
+    actor: &Actor,
+    conn: &mut PgConnection,
+) -> Result<Rotation, ApiError> {
+    use crate::schema::{refresh_tokens, session};
+
+    let now = chrono::Local::now().naive_local();
+
+    conn.transaction(|conn| {
+        let stored = refresh_tokens::table
+            .filter(refresh_tokens::token_hash.eq(hash_token(token)))
+            .for_update()
+            .first::<RefreshToken>(conn)
+            .optional()?
+            .ok_or(ApiError::Unauthorized("Invalid refresh token"))?;
+
+        if stored.used_at.is_some() {
+            log::warn!(
+                "Refresh token reuse detected, revoking session {}",
+                stored.session_id
+            );
+            let revoked = diesel::delete(session::table.find(&stored.session_id))
+                .returning(session::user_id)
+                .get_result::<String>(conn)
+                .optional()?;
+            if let Some(user) = revoked {
+                record(
+                    &actor.clone().signed_in(&user, &stored.session_id),
+                    Event {
+                        action: "session.revoke_reused",
+                        store_id: None,
+                        target_id: Some(&stored.session_id),
+                        before: None,
+                        after: None,
+                    },
+                    conn,
+                )?;
+            }
+            return Ok(Rotation::Reused);
+        }
+
+        let user_session = session::table
+            .find(&stored.session_id)
+            .first::<Session>(conn)?;
+
+        if user_session.expires_at < now {
+            diesel::delete(session::table.find(&user_session.id)).execute(conn)?;
+            return Ok(Rotation::Expired);
+        }
+
+        diesel::update(refresh_tokens::table.find(&stored.id))
+            .set(refresh_tokens::used_at.eq(now))
+            .execute(conn)?;
+
+        let claim_id: usize = user_session
+            .id
+            .parse()
+            .map_err(|_| ApiError::Internal(format!("Invalid session id {}", user_session.id)))?;
+        let access_token = encode_access_token(claim_id, secret, access_token_ttl)?;
+
+        diesel::update(session::table.find(&user_session.id))
+            .set((
+                session::access_token.eq(&access_token),
+                session::expires_at.eq(now + session_ttl),
+            ))
+            .execute(conn)?;
+
+        record(
+            &actor
+                .clone()
+                .signed_in(&user_session.user_id, &user_session.id),
+            Event {
+                action: "session.refresh",
+                store_id: None,
+                target_id: Some(&user_session.id),
+                before: None,
+                after: None,
+            },
+            conn,
+        )?;
+
+        Ok(Rotation::Rotated {
+            token: access_token,
+            refresh_token: add_refresh_token(&user_session.id, conn)?,
+        })
+    })
+}
+
+/// Deleting the session cascades to its refresh tokens, ending the chain.
+fn remove_session(claim_id: &str, conn: &mut PgConnection) -> Result<(), ApiError> {
+    use crate::schema::session::dsl::*;
+
+    diesel::delete(session.find(claim_id)).execute(conn)?;
+    Ok(())
+}
+
+fn list_user_sessions(
+    claim_id: &str,
+    conn: &mut PgConnection,
+) -> Result<Vec<SessionSummary>, ApiError> {
+    use crate::schema::session::dsl::*;
+
+    let current = get_session(claim_id, conn)?;
+
+    let res = session
+        .filter(user_id.eq(&current[0].user_id))
+        .filter(expires_at.gt(chrono::Local::now().naive_local()))
+        .order(created_at.desc())
+        .load::<Session>(conn)?
+        .into_iter()
+        .map(|user_session| SessionSummary {
+            current: user_session.id == current[0].id,
+            id: user_session.id,
+            created_at: user_session.created_at,
+            expires_at: user_session.expires_at,
+            user_agent: user_session.user_agent,
+            ip: user_session.ip,
+        })
+        .collect();
+    Ok(res)
+}
+
+fn remove_user_session(
+    user: &str,
+    claim_id: &str,
+    conn: &mut PgConnection,
+) -> Result<(), ApiError> {
+    use crate::schema::session::dsl::*;
+
+    let deleted =
+        diesel::delete(session.filter(id.eq(claim_id)).filter(user_id.eq(user))).execute(conn)?;
+
+    if deleted == 0 {
+        return Err(ApiError::NotFound("Session not found"));
+    }
+    Ok(())
+}
+
+/// Signs `user` out everywhere but `claim_id`, returning how many sessions
+/// were ended.
+fn remove_other_sessions(
+    user: &str,
+    claim_id: &str,
+    conn: &mut PgConnection,
+) -> Result<usize, ApiError> {
+    use crate::schema::session::dsl::*;
+
+    let res =
+        diesel::delete(session.filter(user_id.eq(user)).filter(id.ne(claim_id))).execute(conn)?;
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add_to_session, register_user, EncodeBody, SessionClient};
+    use crate::{
+        db::{
+            test_support::{create_user, test_pool},
+            unit_of_work,
+        },
+        errors::ApiError,
+        models::NewSession,
+        password::PasswordHasher,
+        schema::{session, users},
+    };
+    use chrono::Duration;
+    use diesel::{ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl};
+
+    fn sign_up_body() -> EncodeBody {
+        EncodeBody {
+            email: format!("{}@example.com", uuid::Uuid::new_v4()),
+            password: "correct horse battery staple".to_string(),
+        }
+    }
+
+    fn hasher() -> PasswordHasher {
+        PasswordHasher::new(1024, 1, 1).unwrap()
+    }
+
+    fn users_with(email: &str, conn: &mut PgConnection) -> i64 {
+        users::table
+            .filter(users::email.eq(email))
+            .count()
+            .get_result(conn)
+            .unwrap()
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a migrated database in DATABASE_URL"]
+    async fn failed_sign_up_leaves_no_user_behind() {
+        let pool = test_pool();
+
+        // A session already holding the claim id makes the session insert
+        // fail after the user row is written.
+        let mut conn = pool.get().unwrap();
+        let other = create_user(&mut conn);
+        let role_id = users::table
+            .find(&other)
+            .select(users::role_id)
+            .first::<String>(&mut conn)
+            .unwrap();
+        diesel::insert_into(session::table)
+            .values(&NewSession {
+                id: "taken-claim",
+                user_id: &other,
+                role_id: &role_id,
+                access_token: "token",
+                expires_at: chrono::Local::now().naive_local() + Duration::hours(1),
+                user_agent: None,
+                ip: None,
+            })
+            .execute(&mut conn)
+            .unwrap();
+        drop(conn);
+
+        let body = sign_up_body();
+        let email = body.email.clone();
+        let res = unit_of_work(&pool, move |conn| {
+            let user = register_user(&body, &hasher(), conn)?;
+            add_to_session(
+                conn,
+                "taken-claim",
+                &user.id,
+                &user.role_id,
+                "token",
+                Duration::hours(1),
+                &SessionClient {
+                    user_agent: None,
+                    ip: None,
+                },
+            )
+        })
+        .await;
+
+        assert!(matches!(res, Err(ApiError::Conflict(_))));
+        assert_eq!(users_with(&email, &mut pool.get().unwrap()), 0);
+    }
+
+    #[actix_web::test]
+    #[ignore = "needs a migrated database in DATABASE_URL"]
+    async fn signing_up_twice_with_one_email_is_rejected() {
+        let pool = test_pool();
+        let body = sign_up_body();
+        let email = body.email.clone();
+
+        let first = body.clone();
+        unit_of_work(&pool, move |conn| register_user(&first, &hasher(), conn))
+            .await
+            .unwrap();
+        let res = unit_of_work(&pool, move |conn| register_user(&body, &hasher(), conn)).await;
+
+        assert!(matches!(res, Err(ApiError::EmailTaken)));
+        assert_eq!(users_with(&email, &mut pool.get().unwrap()), 1);
     }
 }
@@ -0,0 +1,673 @@
+use crate::{
+    errors::ApiError,
+    extractors::{
+        authentication_token::AuthenticationToken,
+        require_permission::{ProductsWrite, RequirePermission},
+    },
+    models::{
+        NewProductOption, NewProductOptionValue, NewProductVariant, NewVariantOptionValue, Product,
+        ProductOption, ProductOptionValue, ProductVariant,
+    },
+    money::Money,
+    scopes::{member::StoreRole, product::check_product_role, store::get_session},
+    AppState,
+};
+use actix_web::{web, HttpResponse};
+use diesel::{
+    Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl,
+    SelectableHelper,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct OptionPayload {
+    name: String,
+    values: Vec<String>,
+}
+
+impl OptionPayload {
+    fn validate(&self) -> Result<(), ApiError> {
+        if self.name.trim().is_empty() {
+            return Err(ApiError::Validation(
+                "Option name must not be empty".to_string(),
+            ));
+        }
+        if self.values.is_empty() {
+            return Err(ApiError::Validation(
+                "An option needs at least one value".to_string(),
+            ));
+        }
+        let mut seen = HashSet::new();
+        for value in &self.values {
+            let value = value.trim();
+            if value.is_empty() {
+                return Err(ApiError::Validation(
+                    "Option values must not be empty".to_string(),
+                ));
+            }
+            if !seen.insert(value) {
+                return Err(ApiError::Validation(format!(
+                    "Option value \"{value}\" is listed twice"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Variants have no stock of their own; units are counted per product in the
+/// stock ledger.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub(crate) struct VariantPayload {
+    sku: String,
+    /// Decimal string in the product's currency; the variant sells at the
+    /// product price when omitted.
+    price: Option<String>,
+    barcode: Option<String>,
+    /// Option name to value, one entry for every option of the product.
+    #[serde(default)]
+    options: BTreeMap<String, String>,
+}
+
+impl VariantPayload {
+    /// Checks the payload against `product` and returns the price override
+    /// in minor units.
+    fn validate(&self, product: &Product) -> Result<Option<i64>, ApiError> {
+        if self.sku.trim().is_empty() {
+            return Err(ApiError::Validation("SKU must not be empty".to_string()));
+        }
+        match &self.price {
+            Some(amount) => {
+                let price = Money::parse(amount, product.price.currency())?;
+                if price.is_negative() {
+                    return Err(ApiError::Validation(
+                        "Variant price must not be negative".to_string(),
+                    ));
+                }
+                Ok(Some(price.minor()))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OptionValueView {
+    id: String,
+    value: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OptionView {
+    id: String,
+    name: String,
+    values: Vec<OptionValueView>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct VariantView {
+    id: String,
+    sku: String,
+    /// The override when one is set, otherwise the product price.
+    price: Money,
+    barcode: Option<String>,
+    options: BTreeMap<String, String>,
+}
+
+/// A product with its option matrix, the variants picked from it and how it
+/// is classified.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ProductDetail {
+    #[serde(flatten)]
+    product: Product,
+    options: Vec<OptionView>,
+    variants: Vec<VariantView>,
+    category_ids: Vec<String>,
+    tags: Vec<String>,
+}
+
+pub(crate) async fn create_option(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<ProductsWrite>,
+    id: web::Path<String>,
+    body: web::Json<OptionPayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let product = web::block(move || {
+        let mut conn = state.pool.get()?;
+        add_option(&sessions[0].user_id, &id, &body, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(product))
+}
+
+pub(crate) async fn delete_option(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<ProductsWrite>,
+    path: web::Path<(String, String)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let (id, option_id) = path.into_inner();
+
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let product = web::block(move || {
+        let mut conn = state.pool.get()?;
+        remove_option(&sessions[0].user_id, &id, &option_id, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(product))
+}
+
+pub(crate) async fn create_variant(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<ProductsWrite>,
+    id: web::Path<String>,
+    body: web::Json<VariantPayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let product = web::block(move || {
+        let mut conn = state.pool.get()?;
+        save_variant(&sessions[0].user_id, &id, None, &body, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(product))
+}
+
+pub(crate) async fn update_variant(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<ProductsWrite>,
+    path: web::Path<(String, String)>,
+    body: web::Json<VariantPayload>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let (id, variant_id) = path.into_inner();
+
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let product = web::block(move || {
+        let mut conn = state.pool.get()?;
+        save_variant(
+            &sessions[0].user_id,
+            &id,
+            Some(&variant_id),
+            &body,
+            &mut conn,
+        )
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(product))
+}
+
+pub(crate) async fn delete_variant(
+    auth_token: AuthenticationToken,
+    _: RequirePermission<ProductsWrite>,
+    path: web::Path<(String, String)>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let (id, variant_id) = path.into_inner();
+
+    let pool = state.pool.clone();
+    let sessions = web::block(move || {
+        let mut conn = pool.get()?;
+        get_session(&auth_token.id.to_string(), &mut conn)
+    })
+    .await??;
+
+    let product = web::block(move || {
+        let mut conn = state.pool.get()?;
+        remove_variant(&sessions[0].user_id, &id, &variant_id, &mut conn)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(product))
+}
+
+/// Loads the options and variants of every product in one query per table.
+pub(crate) fn product_details(
+    products: Vec<Product>,
+    conn: &mut PgConnection,
+) -> Result<Vec<ProductDetail>, ApiError> {
+    use crate::schema::{
+        product_categories, product_option_values, product_options, product_tags, product_variants,
+        variant_option_values,
+    };
+
+    let product_ids = products
+        .iter()
+        .map(|product| product.id.as_str())
+        .collect::<Vec<&str>>();
+
+    let options = product_options::table
+        .filter(product_options::product_id.eq_any(&product_ids))
+        .order((product_options::position.asc(), product_options::name.asc()))
+        .select(ProductOption::as_select())
+        .load::<ProductOption>(conn)?;
+
+    let values = product_option_values::table
+        .filter(product_option_values::option_id.eq_any(options.iter().map(|option| &option.id)))
+        .order((
+            product_option_values::position.asc(),
+            product_option_values::value.asc(),
+        ))
+        .select(ProductOptionValue::as_select())
+        .load::<ProductOptionValue>(conn)?;
+
+    let variants = product_variants::table
+        .filter(product_variants::product_id.eq_any(&product_ids))
+        .order((
+            product_variants::created_at.asc(),
+            product_variants::sku.asc(),
+        ))
+        .select(ProductVariant::as_select())
+        .load::<ProductVariant>(conn)?;
+
+    let picks = variant_option_values::table
+        .inner_join(product_option_values::table.inner_join(product_options::table))
+        .filter(
+            variant_option_values::variant_id.eq_any(variants.iter().map(|variant| &variant.id)),
+        )
+        .select((
+            variant_option_values::variant_id,
+            product_options::name,
+            product_option_values::value,
+        ))
+        .load::<(String, String, String)>(conn)?;
+
+    let mut variant_options = BTreeMap::<String, BTreeMap<String, String>>::new();
+    for (variant, name, value) in picks {
+        variant_options
+            .entry(variant)
+            .or_default()
+            .insert(name, value);
+    }
+
+    let mut categories = BTreeMap::<String, Vec<String>>::new();
+    for (product, category) in product_categories::table
+        .filter(product_categories::product_id.eq_any(&product_ids))
+        .order(product_categories::category_id.asc())
+        .load::<(String, String)>(conn)?
+    {
+        categories.entry(product).or_default().push(category);
+    }
+
+    let mut tags = BTreeMap::<String, Vec<String>>::new();
+    for (product, tag) in product_tags::table
+        .filter(product_tags::product_id.eq_any(&product_ids))
+        .order(product_tags::tag.asc())
+        .load::<(String, String)>(conn)?
+    {
+        tags.entry(product).or_default().push(tag);
+    }
+
+    let res = products
+        .into_iter()
+        .map(|product| {
+            let options = options
+                .iter()
+                .filter(|option| option.product_id == product.id)
+                .map(|option| OptionView {
+                    id: option.id.clone(),
+                    name: option.name.clone(),
+                    values: values
+                        .iter()
+                        .filter(|value| value.option_id == option.id)
+                        .map(|value| OptionValueView {
+                            id: value.id.clone(),
+                            value: value.value.clone(),
+                        })
+                        .collect(),
+                })
+                .collect();
+
+            let variants = variants
+                .iter()
+                .filter(|variant| variant.product_id == product.id)
+                .map(|variant| VariantView {
+                    id: variant.id.clone(),
+                    sku: variant.sku.clone(),
+                    price: variant.price.map_or(product.price, |price| {
+                        Money::from_minor(price, product.price.currency())
+                    }),
+                    barcode: variant.barcode.clone(),
+                    options: variant_options.remove(&variant.id).unwrap_or_default(),
+                })
+                .collect();
+
+            ProductDetail {
+                category_ids: categories.remove(&product.id).unwrap_or_default(),
+                tags: tags.remove(&product.id).unwrap_or_default(),
+                product,
+                options,
+                variants,
+            }
+        })
+        .collect();
+    Ok(res)
+}
+
+pub(crate) fn product_detail(
+    product: Product,
+    conn: &mut PgConnection,
+) -> Result<ProductDetail, ApiError> {
+    product_details(vec![product], conn)?
+        .pop()
+        .ok_or_else(|| ApiError::Internal("Product detail went missing".to_string()))
+}
+
+/// Options can only change while a product has no variants; otherwise the
+/// existing variants would be left without a value for them.
+fn check_no_variants(product: &str, conn: &mut PgConnection) -> Result<(), ApiError> {
+    use crate::schema::product_variants::dsl::*;
+
+    let count = product_variants
+        .filter(product_id.eq(product))
+        .count()
+        .get_result::<i64>(conn)?;
+    if count > 0 {
+        return Err(ApiError::Conflict(
+            "Remove the product's variants before changing its options".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn add_option(
+    user: &str,
+    product: &str,
+    body: &OptionPayload,
+    conn: &mut PgConnection,
+) -> Result<ProductDetail, ApiError> {
+    use crate::schema::{product_option_values, product_options};
+
+    body.validate()?;
+
+    conn.transaction(|conn| {
+        let product = check_product_role(user, product, StoreRole::Staff, conn)?;
+        check_no_variants(&product.id, conn)?;
+
+        let name = body.name.trim();
+        let taken = product_options::table
+            .filter(product_options::product_id.eq(&product.id))
+            .filter(product_options::name.eq(name))
+            .select(product_options::id)
+            .first::<String>(conn)
+            .optional()?;
+        if taken.is_some() {
+            return Err(ApiError::Conflict(format!(
+                "Product already has a \"{name}\" option"
+            )));
+        }
+
+        let position = product_options::table
+            .filter(product_options::product_id.eq(&product.id))
+            .count()
+            .get_result::<i64>(conn)? as i32;
+
+        let option_id = Uuid::new_v4().to_string();
+        diesel::insert_into(product_options::table)
+            .values(&NewProductOption {
+                id: &option_id,
+                product_id: &product.id,
+                name,
+                position: &position,
+            })
+            .execute(conn)?;
+
+        for (position, value) in body.values.iter().enumerate() {
+            diesel::insert_into(product_option_values::table)
+                .values(&NewProductOptionValue {
+                    id: &Uuid::new_v4().to_string(),
+                    option_id: &option_id,
+                    value: value.trim(),
+                    position: &(position as i32),
+                })
+                .execute(conn)?;
+        }
+
+        product_detail(product, conn)
+    })
+}
+
+fn remove_option(
+    user: &str,
+    product: &str,
+    option: &str,
+    conn: &mut PgConnection,
+) -> Result<ProductDetail, ApiError> {
+    use crate::schema::product_options;
+
+    conn.transaction(|conn| {
+        let product = check_product_role(user, product, StoreRole::Staff, conn)?;
+        check_no_variants(&product.id, conn)?;
+
+        let deleted = diesel::delete(
+            product_options::table
+                .filter(product_options::id.eq(option))
+                .filter(product_options::product_id.eq(&product.id)),
+        )
+        .execute(conn)?;
+        if deleted == 0 {
+            return Err(ApiError::NotFound("Option not found"));
+        }
+
+        product_detail(product, conn)
+    })
+}
+
+/// Resolves the payload's option names and values to option value ids,
+/// requiring exactly one value for every option of the product.
+fn resolve_options(
+    product: &str,
+    picked: &BTreeMap<String, String>,
+    conn: &mut PgConnection,
+) -> Result<BTreeSet<String>, ApiError> {
+    use crate::schema::{product_option_values, product_options};
+
+    let choices = product_options::table
+        .inner_join(product_option_values::table)
+        .filter(product_options::product_id.eq(product))
+        .select((
+            product_options::name,
+            product_option_values::value,
+            product_option_values::id,
+        ))
+        .load::<(String, String, String)>(conn)?;
+
+    let names = choices
+        .iter()
+        .map(|(name, _, _)| name.as_str())
+        .collect::<BTreeSet<&str>>();
+    for name in &names {
+        if !picked.contains_key(*name) {
+            return Err(ApiError::Validation(format!(
+                "Variant needs a value for \"{name}\""
+            )));
+        }
+    }
+
+    picked
+        .iter()
+        .map(|(name, value)| {
+            if !names.contains(name.as_str()) {
+                return Err(ApiError::Validation(format!(
+                    "Product has no \"{name}\" option"
+                )));
+            }
+            choices
+                .iter()
+                .find(|(option, choice, _)| option == name && choice == value.trim())
+                .map(|(_, _, id)| id.clone())
+                .ok_or_else(|| {
+                    ApiError::Validation(format!("\"{value}\" is not a value of \"{name}\""))
+                })
+        })
+        .collect()
+}
+
+/// Creates a variant, or replaces `variant` when given. No two variants of a
+/// product may pick the same combination of option values.
+fn save_variant(
+    user: &str,
+    product: &str,
+    variant: Option<&str>,
+    body: &VariantPayload,
+    conn: &mut PgConnection,
+) -> Result<ProductDetail, ApiError> {
+    use crate::schema::{product_variants, variant_option_values};
+
+    conn.transaction(|conn| {
+        let product = check_product_role(user, product, StoreRole::Staff, conn)?;
+        let price = body.validate(&product)?;
+        let sku = body.sku.trim();
+
+        if let Some(variant) = variant {
+            product_variants::table
+                .filter(product_variants::id.eq(variant))
+                .filter(product_variants::product_id.eq(&product.id))
+                .select(product_variants::id)
+                .first::<String>(conn)
+                .optional()?
+                .ok_or(ApiError::NotFound("Variant not found"))?;
+        }
+
+        let sku_owner = product_variants::table
+            .filter(product_variants::store_id.eq(&product.store_id))
+            .filter(product_variants::sku.eq(sku))
+            .select(product_variants::id)
+            .first::<String>(conn)
+            .optional()?;
+        if sku_owner.is_some() && sku_owner.as_deref() != variant {
+            return Err(ApiError::Conflict(format!(
+                "SKU \"{sku}\" is already used in this store"
+            )));
+        }
+
+        let picked = resolve_options(&product.id, &body.options, conn)?;
+
+        let siblings = variant_option_values::table
+            .inner_join(product_variants::table)
+            .filter(product_variants::product_id.eq(&product.id))
+            .select((
+                variant_option_values::variant_id,
+                variant_option_values::option_value_id,
+            ))
+            .load::<(String, String)>(conn)?;
+        let mut combinations = BTreeMap::<String, BTreeSet<String>>::new();
+        for (sibling, value) in siblings {
+            combinations.entry(sibling).or_default().insert(value);
+        }
+        // Variants of a product without options have no picks at all.
+        let others = product_variants::table
+            .filter(product_variants::product_id.eq(&product.id))
+            .select(product_variants::id)
+            .load::<String>(conn)?;
+        let duplicate = others
+            .iter()
+            .filter(|other| Some(other.as_str()) != variant)
+            .any(|other| combinations.get(other).cloned().unwrap_or_default() == picked);
+        if duplicate {
+            return Err(ApiError::Conflict(
+                "Another variant already has these options".to_string(),
+            ));
+        }
+
+        let variant_id = match variant {
+            Some(variant) => {
+                diesel::update(product_variants::table.find(variant))
+                    .set((
+                        product_variants::sku.eq(sku),
+                        product_variants::price.eq(price),
+                        product_variants::barcode.eq(body.barcode.as_deref()),
+                    ))
+                    .execute(conn)?;
+                diesel::delete(
+                    variant_option_values::table
+                        .filter(variant_option_values::variant_id.eq(variant)),
+                )
+                .execute(conn)?;
+                variant.to_string()
+            }
+            None => {
+                let variant_id = Uuid::new_v4().to_string();
+                diesel::insert_into(product_variants::table)
+                    .values(&NewProductVariant {
+                        id: &variant_id,
+                        product_id: &product.id,
+                        store_id: &product.store_id,
+                        sku,
+                        price: price.as_ref(),
+                        barcode: body.barcode.as_deref(),
+                    })
+                    .execute(conn)?;
+                variant_id
+            }
+        };
+
+        for option_value in &picked {
+            diesel::insert_into(variant_option_values::table)
+                .values(&NewVariantOptionValue {
+                    variant_id: &variant_id,
+                    option_value_id: option_value,
+                })
+                .execute(conn)?;
+        }
+
+        product_detail(product, conn)
+    })
+}
+
+fn remove_variant(
+    user: &str,
+    product: &str,
+    variant: &str,
+    conn: &mut PgConnection,
+) -> Result<ProductDetail, ApiError> {
+    use crate::schema::product_variants;
+
+    conn.transaction(|conn| {
+        let product = check_product_role(user, product, StoreRole::Staff, conn)?;
+
+        let deleted = diesel::delete(
+            product_variants::table
+                .filter(product_variants::id.eq(variant))
+                .filter(product_variants::product_id.eq(&product.id)),
+        )
+        .execute(conn)?;
+        if deleted == 0 {
+            return Err(ApiError::NotFound("Variant not found"));
+        }
+
+        product_detail(product, conn)
+    })
+}
@@ -0,0 +1,193 @@
+use crate::{
+    errors::ApiError,
+    extractors::require_permission::ALL_PERMISSIONS,
+    models::{NewPermission, NewRole, NewRolePermission, Permission, Role, User},
+    scopes::role::set_user_role,
+};
+use diesel::{
+    Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl,
+};
+use uuid::Uuid;
+
+pub const ADMIN_ROLE: &str = "admin";
+pub const SIGN_UP_ROLE: &str = "merchant";
+
+/// Roles created on a fresh database along with the permissions they start
+/// out with. Once a role exists its grants are left to `/roles`, only the
+/// admin role is topped up with every known permission on each start.
+const DEFAULT_ROLES: &[(&str, &[&str])] = &[
+    (
+        SIGN_UP_ROLE,
+        &[
+            "stores.read",
+            "stores.write",
+            "stores.delete",
+            "products.read",
+            "products.write",
+            "products.delete",
+            "orders.read",
+            "orders.write",
+            "payments.read",
+            "payments.write",
+            "sales.read",
+            "sales.write",
+            "inventory.read",
+            "inventory.write",
+        ],
+    ),
+    (
+        "staff",
+        &[
+            "stores.read",
+            "stores.write",
+            "products.read",
+            "products.write",
+            "orders.read",
+            "orders.write",
+            "payments.read",
+            "sales.read",
+            "sales.write",
+            "inventory.read",
+            "inventory.write",
+        ],
+    ),
+];
+
+/// Creates the default permissions and roles, and gives the account behind
+/// `admin_email`, when there is one, the admin role. That is how a fresh
+/// database gets the first admin who can reach `/roles` and `/permissions`.
+pub fn seed_defaults(admin_email: Option<&str>, conn: &mut PgConnection) -> Result<(), ApiError> {
+    conn.transaction(|conn| {
+        for permission in ALL_PERMISSIONS {
+            insert_permission(permission, conn)?;
+        }
+
+        let (admin, _) = insert_role(ADMIN_ROLE, conn)?;
+        grant(&admin.id, ALL_PERMISSIONS, conn)?;
+
+        for (role, permissions) in DEFAULT_ROLES {
+            let (role, created) = insert_role(role, conn)?;
+            if created {
+                grant(&role.id, permissions, conn)?;
+            }
+        }
+
+        if let Some(email) = admin_email {
+            promote_admin(email, &admin, conn)?;
+        }
+
+        Ok(())
+    })
+}
+
+fn promote_admin(email: &str, admin: &Role, conn: &mut PgConnection) -> Result<(), ApiError> {
+    use crate::schema::users;
+
+    let user = users::table
+        .filter(users::email.eq(email))
+        .first::<User>(conn)
+        .optional()?;
+    match user {
+        Some(user) if user.role_id == admin.id => {}
+        Some(user) => {
+            set_user_role(&user.id, &admin.id, conn)?;
+            log::info!("Gave {email} the {ADMIN_ROLE} role");
+        }
+        None => {
+            log::warn!("ADMIN_EMAIL {email} has no account yet; sign up and restart to promote it")
+        }
+    }
+    Ok(())
+}
+
+fn insert_permission(permission: &str, conn: &mut PgConnection) -> Result<(), ApiError> {
+    use crate::schema::permissions::dsl::*;
+
+    diesel::insert_into(permissions)
+        .values(NewPermission {
+            id: &Uuid::new_v4().to_string(),
+            name: permission,
+        })
+        .on_conflict(name)
+        .do_nothing()
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Returns the role together with whether it was created by this call.
+fn insert_role(role: &str, conn: &mut PgConnection) -> Result<(Role, bool), ApiError> {
+    use crate::schema::roles::dsl::*;
+
+    let created = diesel::insert_into(roles)
+        .values(NewRole {
+            id: &Uuid::new_v4().to_string(),
+            name: role,
+        })
+        .on_conflict(name)
+        .do_nothing()
+        .execute(conn)?;
+
+    let res = roles.filter(name.eq(role)).first::<Role>(conn)?;
+    Ok((res, created > 0))
+}
+
+fn grant(role: &str, names: &[&str], conn: &mut PgConnection) -> Result<(), ApiError> {
+    use crate::schema::{permissions, role_permissions};
+
+    let granted = permissions::table
+        .filter(permissions::name.eq_any(names))
+        .load::<Permission>(conn)?;
+
+    let new_role_permissions: Vec<NewRolePermission> = granted
+        .iter()
+        .map(|permission| NewRolePermission {
+            role_id: role,
+            permission_id: &permission.id,
+        })
+        .collect();
+
+    diesel::insert_into(role_permissions::table)
+        .values(&new_role_permissions)
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{seed_defaults, ADMIN_ROLE};
+    use crate::{
+        db::test_support::{create_user, test_pool},
+        schema::{roles, users},
+    };
+    use diesel::{ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl};
+
+    fn role_of(user: &str, conn: &mut PgConnection) -> String {
+        users::table
+            .inner_join(roles::table)
+            .filter(users::id.eq(user))
+            .select(roles::name)
+            .first(conn)
+            .unwrap()
+    }
+
+    #[test]
+    #[ignore = "needs a migrated database in DATABASE_URL"]
+    fn admin_email_is_given_the_admin_role() {
+        let pool = test_pool();
+        let mut conn = pool.get().unwrap();
+        let user = create_user(&mut conn);
+        let bystander = create_user(&mut conn);
+
+        seed_defaults(Some(&format!("{user}@example.com")), &mut conn).unwrap();
+        assert_eq!(role_of(&user, &mut conn), ADMIN_ROLE);
+        assert_ne!(role_of(&bystander, &mut conn), ADMIN_ROLE);
+
+        // Later starts leave the admin as is and tolerate unknown emails.
+        seed_defaults(Some(&format!("{user}@example.com")), &mut conn).unwrap();
+        seed_defaults(Some("nobody@example.com"), &mut conn).unwrap();
+        assert_eq!(role_of(&user, &mut conn), ADMIN_ROLE);
+    }
+}
@@ -0,0 +1,129 @@
+use crate::errors::ApiError;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// Lifecycle of a store. Only the moves listed in `StoreStage::next` are
+/// allowed; archived stores are final.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StoreStage {
+    Draft,
+    Setup,
+    Live,
+    Paused,
+    Archived,
+}
+
+impl StoreStage {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StoreStage::Draft => "draft",
+            StoreStage::Setup => "setup",
+            StoreStage::Live => "live",
+            StoreStage::Paused => "paused",
+            StoreStage::Archived => "archived",
+        }
+    }
+
+    pub fn next(self) -> &'static [StoreStage] {
+        match self {
+            StoreStage::Draft => &[StoreStage::Setup, StoreStage::Live, StoreStage::Archived],
+            StoreStage::Setup => &[StoreStage::Draft, StoreStage::Live, StoreStage::Archived],
+            StoreStage::Live => &[StoreStage::Paused, StoreStage::Archived],
+            StoreStage::Paused => &[StoreStage::Live, StoreStage::Archived],
+            StoreStage::Archived => &[],
+        }
+    }
+
+    pub fn transition(self, to: StoreStage) -> Result<StoreStage, ApiError> {
+        if self.next().contains(&to) {
+            Ok(to)
+        } else {
+            Err(ApiError::InvalidTransition(format!(
+                "Store cannot move from {self} to {to}"
+            )))
+        }
+    }
+}
+
+impl fmt::Display for StoreStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for StoreStage {
+    type Err = ApiError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "draft" => Ok(StoreStage::Draft),
+            "setup" => Ok(StoreStage::Setup),
+            "live" => Ok(StoreStage::Live),
+            "paused" => Ok(StoreStage::Paused),
+            "archived" => Ok(StoreStage::Archived),
+            _ => Err(ApiError::Internal(format!(
+                "Unknown store stage \"{value}\""
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use StoreStage::*;
+
+    const ALL: [StoreStage; 5] = [Draft, Setup, Live, Paused, Archived];
+
+    const ALLOWED: &[(StoreStage, StoreStage)] = &[
+        (Draft, Setup),
+        (Draft, Live),
+        (Draft, Archived),
+        (Setup, Draft),
+        (Setup, Live),
+        (Setup, Archived),
+        (Live, Paused),
+        (Live, Archived),
+        (Paused, Live),
+        (Paused, Archived),
+    ];
+
+    #[test]
+    fn only_listed_moves_are_allowed() {
+        for from in ALL {
+            for to in ALL {
+                let result = from.transition(to);
+                if ALLOWED.contains(&(from, to)) {
+                    assert_eq!(result.ok(), Some(to), "{from} -> {to}");
+                } else {
+                    assert_eq!(
+                        result.unwrap_err().to_string(),
+                        format!("Store cannot move from {from} to {to}")
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn no_stage_moves_to_itself_and_archived_is_final() {
+        for stage in ALL {
+            assert!(stage.transition(stage).is_err(), "{stage}");
+        }
+        assert!(Archived.next().is_empty());
+    }
+
+    #[test]
+    fn stages_round_trip_through_their_names() {
+        for stage in ALL {
+            assert_eq!(stage.as_str().parse::<StoreStage>().ok(), Some(stage));
+            assert_eq!(
+                serde_json::to_string(&stage).unwrap(),
+                format!("\"{stage}\"")
+            );
+        }
+        assert!("Live".parse::<StoreStage>().is_err());
+        assert!("closed".parse::<StoreStage>().is_err());
+    }
+}
@@ -0,0 +1,17 @@
+use rand::{rngs::OsRng, Rng};
+use sha2::{Digest, Sha256};
+
+/// 256 random bits, hex encoded, for opaque bearer tokens handed to clients.
+pub fn random_token() -> String {
+    to_hex(&OsRng.gen::<[u8; 32]>())
+}
+
+/// Opaque tokens are only ever stored as their SHA-256, so a leaked table
+/// cannot be replayed against the API.
+pub fn hash_token(token: &str) -> String {
+    to_hex(&Sha256::digest(token.as_bytes()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}